@@ -1,18 +1,50 @@
 // ast.rs - Árbol de Sintaxis Abstracta (AST) para V-Code
 // Define todas las estructuras de datos que representan el código V-Code
 
+use crate::unidades::Dimension;
 use std::fmt;
 
-/// Posición en el código fuente para mensajes de error claros
+/// Identifica el archivo fuente de una posición, para herramientas que
+/// analizan varios archivos a la vez. El programa principal es el 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArchivoId(pub u32);
+
+/// Tramo del código fuente que ocupa un token o un nodo, para mensajes de
+/// error claros. Líneas y columnas (en caracteres) empiezan en 1, los bytes
+/// son desplazamientos en el texto original y el final es exclusivo.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Posicion {
-    pub linea: usize,
-    pub columna: usize,
+    pub archivo: ArchivoId,
+    pub linea: u32,
+    pub columna: u32,
+    pub byte: u32,
+    pub linea_fin: u32,
+    pub columna_fin: u32,
+    pub byte_fin: u32,
 }
 
 impl Posicion {
-    pub fn new(linea: usize, columna: usize) -> Self {
-        Posicion { linea, columna }
+    /// Tramo vacío en un punto del código
+    pub fn punto(archivo: ArchivoId, linea: u32, columna: u32, byte: u32) -> Self {
+        Posicion {
+            archivo,
+            linea,
+            columna,
+            byte,
+            linea_fin: linea,
+            columna_fin: columna,
+            byte_fin: byte,
+        }
+    }
+    
+    /// Tramo que va desde el inicio de este hasta el final de `fin`
+    pub fn hasta(&self, fin: &Posicion) -> Posicion {
+        Posicion {
+            linea_fin: fin.linea_fin,
+            columna_fin: fin.columna_fin,
+            byte_fin: fin.byte_fin,
+            ..self.clone()
+        }
     }
 }
 
@@ -26,6 +58,7 @@ impl fmt::Display for Posicion {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Programa {
     pub declaraciones: Vec<Declaracion>,
+    pub pos: Posicion,
 }
 
 /// Declaraciones de nivel superior
@@ -33,13 +66,47 @@ pub struct Programa {
 pub enum Declaracion {
     Escena(Escena),
     Funcion(Funcion),
+    Estructura(Estructura),
+    Enumeracion(Enumeracion),
 }
 
 /// Escena VR - equivalente a una clase o módulo principal
 #[derive(Debug, Clone, PartialEq)]
 pub struct Escena {
     pub nombre: String,
+    pub inicial: bool,              // Marcada con @inicial
     pub cuerpo: Vec<Sentencia>,
+    pub al_iniciar: Vec<Sentencia>, // Hook tras ejecutar el cuerpo
+    pub al_salir: Vec<Sentencia>,   // Hook al abandonar la escena
+    pub maquinas: Vec<Maquina>,
+    pub doc: Option<String>, // Comentarios `///` que la preceden
+    pub pos: Posicion,
+}
+
+/// Máquina de estados declarada en una escena; empieza en su primer estado
+#[derive(Debug, Clone, PartialEq)]
+pub struct Maquina {
+    pub nombre: String,
+    pub estados: Vec<Estado>,
+    pub pos: Posicion,
+}
+
+/// Estado de una máquina
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estado {
+    pub nombre: String,
+    pub al_entrar: Vec<Sentencia>,
+    pub cada_frame: Vec<Sentencia>,
+    pub al_salir: Vec<Sentencia>,
+    pub transiciones: Vec<Transicion>, // Se evalúan en orden de declaración
+    pub pos: Posicion,
+}
+
+/// `transicion a Destino cuando condición`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transicion {
+    pub destino: String,
+    pub condicion: Expresion,
     pub pos: Posicion,
 }
 
@@ -50,6 +117,8 @@ pub struct Funcion {
     pub parametros: Vec<Parametro>,
     pub tipo_retorno: Option<Tipo>,
     pub cuerpo: Vec<Sentencia>,
+    pub es_corrutina: bool, // Declarada con `corrutina`, puede usar `esperar`
+    pub doc: Option<String>,
     pub pos: Posicion,
 }
 
@@ -58,6 +127,42 @@ pub struct Funcion {
 pub struct Parametro {
     pub nombre: String,
     pub tipo_dato: Tipo,
+    pub pos: Posicion,
+}
+
+/// Tipo de registro declarado con `estructura`: agrupa valores con nombre
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estructura {
+    pub nombre: String,
+    pub campos: Vec<Campo>, // En orden de declaración
+    pub doc: Option<String>,
+    pub pos: Posicion,
+}
+
+/// Campo de una estructura
+#[derive(Debug, Clone, PartialEq)]
+pub struct Campo {
+    pub nombre: String,
+    pub tipo_dato: Tipo,
+    pub pos: Posicion,
+}
+
+/// Tipo declarado con `enumeracion`: un valor es una de sus variantes,
+/// que pueden llevar valores
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enumeracion {
+    pub nombre: String,
+    pub variantes: Vec<Variante>,
+    pub doc: Option<String>,
+    pub pos: Posicion,
+}
+
+/// Variante de una enumeración con los tipos de sus valores: `Circulo(decimal)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variante {
+    pub nombre: String,
+    pub campos: Vec<Tipo>,
+    pub pos: Posicion,
 }
 
 /// Tipos de datos en V-Code
@@ -71,6 +176,42 @@ pub enum Tipo {
     Pose,        // Posición + rotación
     Mano,        // Estado de mano VR
     Controlador, // Input de controlador
+    Lista(Box<Tipo>),            // lista<numero>
+    Mapa(Box<Tipo>, Box<Tipo>), // mapa<texto, numero>: claves y valores
+    Nombrado(String),            // Declarado con `estructura` o `enumeracion`
+    Funcion(Vec<Tipo>, Option<Box<Tipo>>), // funcion(numero, numero): numero
+}
+
+impl fmt::Display for Tipo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Tipo::Numero => write!(f, "numero"),
+            Tipo::Decimal => write!(f, "decimal"),
+            Tipo::Texto => write!(f, "texto"),
+            Tipo::Booleano => write!(f, "booleano"),
+            Tipo::Vector3 => write!(f, "vector3"),
+            Tipo::Pose => write!(f, "pose"),
+            Tipo::Mano => write!(f, "mano"),
+            Tipo::Controlador => write!(f, "controlador"),
+            Tipo::Lista(elemento) => write!(f, "lista<{}>", elemento),
+            Tipo::Mapa(clave, valor) => write!(f, "mapa<{}, {}>", clave, valor),
+            Tipo::Nombrado(nombre) => write!(f, "{}", nombre),
+            Tipo::Funcion(parametros, retorno) => {
+                write!(f, "funcion(")?;
+                for (i, parametro) in parametros.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", parametro)?;
+                }
+                write!(f, ")")?;
+                match retorno {
+                    Some(retorno) => write!(f, ": {}", retorno),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
 }
 
 /// Sentencias - instrucciones ejecutables
@@ -78,12 +219,82 @@ pub enum Tipo {
 pub enum Sentencia {
     Mostrar(Expresion, Posicion),
     Var(String, Expresion, Posicion),
+    Constante(String, Expresion, Option<String>, Posicion), // Con su documentación
     Asignacion(String, Expresion, Posicion),
-    Si(Expresion, Vec<Sentencia>, Option<Vec<Sentencia>>, Posicion),
-    Mientras(Expresion, Vec<Sentencia>, Posicion),
-    Para(String, Expresion, Expresion, Vec<Sentencia>, Posicion),
+    AsignacionIndice(Expresion, Expresion, Expresion, Posicion), // lista[indice] = valor, mapa[clave] = valor
+    AsignacionCampo(Expresion, String, Expresion, Posicion),      // punto.x = valor
+    Si(Vec<Rama>, Option<Vec<Sentencia>>, Posicion), // `si`, cada `sino si` y el `sino` final
+    Mientras(Expresion, Vec<Sentencia>, Option<String>, Posicion), // Con su etiqueta, si tiene
+    Para(String, Iteracion, Vec<Sentencia>, Option<String>, Posicion),
     Retornar(Option<Expresion>, Posicion),
-    Expresion(Expresion),
+    Romper(Option<String>, Posicion),    // Con la etiqueta del bucle del que sale
+    Continuar(Option<String>, Posicion),
+    Esperar(Espera, Posicion),
+    Emitir(String, Vec<Expresion>, Posicion),
+    Escuchar(String, Vec<String>, Vec<Sentencia>, Posicion),
+    EscucharFuncion(String, Expresion, Posicion), // escuchar "choque" al_chocar
+    Segun(Expresion, Vec<Caso<Vec<Sentencia>>>, Posicion),
+    // El bloque de `intentar` con su `capturar` y su `finalmente`; al menos
+    // tiene uno de los dos
+    Intentar(Vec<Sentencia>, Option<Captura>, Option<Vec<Sentencia>>, Posicion),
+    Lanzar(Expresion, Posicion),
+    Expresion(Expresion, Posicion),
+}
+
+/// `capturar e { ... }` de un `intentar`; el nombre del error es opcional
+#[derive(Debug, Clone, PartialEq)]
+pub struct Captura {
+    pub variable: Option<String>,
+    pub cuerpo: Vec<Sentencia>,
+    pub pos: Posicion,
+}
+
+/// Rama de un `si` o de un `sino si`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rama {
+    pub condicion: Expresion,
+    pub cuerpo: Vec<Sentencia>,
+    pub pos: Posicion, // Desde `si` hasta el final del bloque
+}
+
+/// Caso de un `segun`: su patrón y lo que produce, un bloque de
+/// sentencias o el valor de una expresión
+#[derive(Debug, Clone, PartialEq)]
+pub struct Caso<T> {
+    pub patron: Patron,
+    pub cuerpo: T,
+    pub pos: Posicion, // Desde `caso` hasta el final del cuerpo
+}
+
+/// Patrón de un caso de `segun`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Patron {
+    // `caso Circulo(radio)` da nombre a los valores de la variante (`_`
+    // ignora uno); `caso Circulo` la acepta sin mirarlos
+    Variante(String, Option<Vec<String>>, Posicion),
+    Literal(Expresion), // caso 1, caso "salir"
+    Otro(Posicion),     // otro: cualquier valor
+}
+
+/// Lo que recorre un bucle `para`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Iteracion {
+    // `para i = 0, 10` sin incluir el fin, `para i = 1 hasta 10` incluyéndolo;
+    // el paso por defecto es 1
+    Rango {
+        inicio: Expresion,
+        fin: Expresion,
+        inclusivo: bool,
+        paso: Option<Expresion>,
+    },
+    Coleccion(Expresion), // para letra en "hola"
+}
+
+/// Condición de reanudación de `esperar` dentro de una corrutina
+#[derive(Debug, Clone, PartialEq)]
+pub enum Espera {
+    Segundos(Expresion), // esperar 3
+    Hasta(Expresion),    // esperar hasta agarrado
 }
 
 /// Expresiones - producen valores
@@ -91,12 +302,26 @@ pub enum Sentencia {
 pub enum Expresion {
     Numero(i64, Posicion),
     Decimal(f64, Posicion),
+    Medida(f64, Dimension, Posicion), // Valor en la unidad base: 20cm es 0.2 metros
     Texto(String, Posicion),
     Booleano(bool, Posicion),
     Variable(String, Posicion),
     Binaria(Box<Expresion>, OperadorBinario, Box<Expresion>, Posicion),
     Unaria(OperadorUnario, Box<Expresion>, Posicion),
     Llamada(String, Vec<Expresion>, Posicion),
+    LlamadaValor(Box<Expresion>, Vec<Expresion>, Posicion), // acciones[0](), crear_contador()()
+    // funcion(x: numero): numero { ... }; `=> expr` es un cuerpo que la retorna
+    FuncionAnonima(Vec<Parametro>, Option<Box<Tipo>>, Vec<Sentencia>, Posicion),
+    Temporizador(Programacion, Vec<Sentencia>, Posicion),
+    Interpolacion(Vec<Fragmento>, Posicion), // "Puntos: {puntos}"
+    Lista(Vec<Expresion>, Posicion),         // [1, 2, 3]
+    Mapa(Vec<(Expresion, Expresion)>, Posicion), // { "oro": 10, "nivel": 2 }
+    Indice(Box<Expresion>, Box<Expresion>, Posicion),
+    // lista[desde:hasta], con cualquiera de los extremos opcional
+    Rebanada(Box<Expresion>, Option<Box<Expresion>>, Option<Box<Expresion>>, Posicion),
+    Construccion(String, Vec<(String, Expresion)>, Posicion), // Punto(x: 1, y: 2)
+    Campo(Box<Expresion>, String, Posicion),                  // punto.x
+    Segun(Box<Expresion>, Vec<Caso<Expresion>>, Posicion),
 }
 
 impl Expresion {
@@ -104,16 +329,43 @@ impl Expresion {
         match self {
             Expresion::Numero(_, pos) => pos,
             Expresion::Decimal(_, pos) => pos,
+            Expresion::Medida(_, _, pos) => pos,
             Expresion::Texto(_, pos) => pos,
             Expresion::Booleano(_, pos) => pos,
             Expresion::Variable(_, pos) => pos,
             Expresion::Binaria(_, _, _, pos) => pos,
             Expresion::Unaria(_, _, pos) => pos,
             Expresion::Llamada(_, _, pos) => pos,
+            Expresion::LlamadaValor(_, _, pos) => pos,
+            Expresion::FuncionAnonima(_, _, _, pos) => pos,
+            Expresion::Temporizador(_, _, pos) => pos,
+            Expresion::Interpolacion(_, pos) => pos,
+            Expresion::Lista(_, pos) => pos,
+            Expresion::Mapa(_, pos) => pos,
+            Expresion::Indice(_, _, pos) => pos,
+            Expresion::Rebanada(_, _, _, pos) => pos,
+            Expresion::Construccion(_, _, pos) => pos,
+            Expresion::Campo(_, _, pos) => pos,
+            Expresion::Segun(_, _, pos) => pos,
         }
     }
 }
 
+/// Trozo de un texto con interpolación
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fragmento {
+    Texto(String),
+    Expresion(Expresion), // Se muestra con el formato de `mostrar`
+}
+
+/// Cuándo se ejecuta un bloque programado en el reloj de frames
+#[derive(Debug, Clone, PartialEq)]
+pub enum Programacion {
+    Despues(Box<Expresion>), // Una vez, tras N segundos
+    Cada(Box<Expresion>),    // Periódicamente, cada N segundos
+    CadaFrame,               // Una vez por frame
+}
+
 /// Operadores binarios
 #[derive(Debug, Clone, PartialEq)]
 pub enum OperadorBinario {
@@ -0,0 +1,84 @@
+// colecciones.rs - Funciones de orden superior sobre listas
+// `mapear`, `filtrar`, `reducir`... son nativas: reciben listas y una
+// función que el ejecutor llama con cada elemento. Aquí están sus firmas,
+// que el análisis semántico comprueba antes de ejecutar, y la ordenación.
+
+/// Lo que espera una función de colecciones
+#[derive(Debug)]
+pub struct Firma {
+    pub nombre: &'static str,
+    pub minimo: usize,  // Argumentos obligatorios
+    pub maximo: usize,
+    pub listas: usize,  // Los primeros argumentos son listas
+    // Posición del argumento que es una función y cuántos parámetros
+    // recibe al llamarla
+    pub funcion: Option<(usize, usize)>,
+    pub uso: &'static str, // Para los mensajes de error: "'mapear' espera ..."
+}
+
+const CADA_ELEMENTO: &str = "una lista y una función que reciba cada elemento";
+
+const FIRMAS: &[Firma] = &[
+    Firma { nombre: "mapear", minimo: 2, maximo: 2, listas: 1, funcion: Some((1, 1)), uso: CADA_ELEMENTO },
+    Firma { nombre: "filtrar", minimo: 2, maximo: 2, listas: 1, funcion: Some((1, 1)), uso: CADA_ELEMENTO },
+    Firma {
+        nombre: "reducir",
+        minimo: 3,
+        maximo: 3,
+        listas: 1,
+        funcion: Some((2, 2)),
+        uso: "una lista, un valor inicial y una función que reciba el acumulado y cada elemento",
+    },
+    Firma {
+        nombre: "ordenar",
+        minimo: 1,
+        maximo: 2,
+        listas: 1,
+        funcion: Some((1, 2)),
+        uso: "una lista y, si no se ordena de menor a mayor, una función que reciba dos elementos y diga si el primero va antes",
+    },
+    Firma { nombre: "buscar", minimo: 2, maximo: 2, listas: 1, funcion: Some((1, 1)), uso: CADA_ELEMENTO },
+    Firma { nombre: "cualquiera", minimo: 2, maximo: 2, listas: 1, funcion: Some((1, 1)), uso: CADA_ELEMENTO },
+    Firma { nombre: "todos", minimo: 2, maximo: 2, listas: 1, funcion: Some((1, 1)), uso: CADA_ELEMENTO },
+    Firma { nombre: "enumerar", minimo: 1, maximo: 1, listas: 1, funcion: None, uso: "una lista" },
+    Firma { nombre: "zip", minimo: 2, maximo: 2, listas: 2, funcion: None, uso: "dos listas" },
+];
+
+/// Busca la firma de una función de colecciones por su nombre
+pub fn firma(nombre: &str) -> Option<&'static Firma> {
+    FIRMAS.iter().find(|firma| firma.nombre == nombre)
+}
+
+/// Ordena `elementos` de forma estable con `antes`, que dice si el primero
+/// va antes que el segundo. Es una ordenación por mezcla propia porque
+/// `antes` puede fallar y no tiene por qué ser un orden coherente.
+pub fn ordenar<T: Clone, E>(
+    elementos: Vec<T>,
+    antes: &mut impl FnMut(&T, &T) -> Result<bool, E>,
+) -> Result<Vec<T>, E> {
+    if elementos.len() <= 1 {
+        return Ok(elementos);
+    }
+    
+    let mut izquierda = elementos;
+    let derecha = izquierda.split_off(izquierda.len() / 2);
+    let izquierda = ordenar(izquierda, antes)?;
+    let derecha = ordenar(derecha, antes)?;
+    
+    // Ante la duda se toma el de la izquierda, que mantiene el orden de
+    // los elementos que se consideran iguales
+    let mut mezcla = Vec::with_capacity(izquierda.len() + derecha.len());
+    let (mut i, mut j) = (0, 0);
+    while i < izquierda.len() && j < derecha.len() {
+        if antes(&derecha[j], &izquierda[i])? {
+            mezcla.push(derecha[j].clone());
+            j += 1;
+        } else {
+            mezcla.push(izquierda[i].clone());
+            i += 1;
+        }
+    }
+    mezcla.extend_from_slice(&izquierda[i..]);
+    mezcla.extend_from_slice(&derecha[j..]);
+    Ok(mezcla)
+}
@@ -0,0 +1,758 @@
+// diagnostico.rs - Diagnósticos de V-Code
+// Todas las fases informan errores con un `Diagnostico`: un código estable
+// (E0102), la posición principal, etiquetas secundarias, notas y
+// sugerencias. `renderizar` muestra la línea de código con el error
+// subrayado y `vcode explicar <código>` amplía cada código.
+
+use crate::ast::Posicion;
+use std::fmt;
+
+/// Fase del compilador que produce el diagnóstico
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fase {
+    Lexica,
+    Sintactica,
+    Semantica,
+    Ejecucion,
+}
+
+impl fmt::Display for Fase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Fase::Lexica => "léxico",
+            Fase::Sintactica => "sintáctico",
+            Fase::Semantica => "semántico",
+            Fase::Ejecucion => "de ejecución",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Códigos de error. El número es estable: E00xx léxicos, E01xx
+/// sintácticos, E02xx semánticos y E03xx de ejecución.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Codigo {
+    CaracterInesperado,
+    TextoSinTerminar,
+    NumeroInvalido,
+    IdiomaDesconocido,
+    NombreReservado,
+    ComentarioSinTerminar,
+    InterpolacionInvalida,
+    EscapeInvalido,
+    UnidadDesconocida,
+    TokenInesperado,
+    NombreEsperado,
+    DeclaracionInvalida,
+    Duplicado,
+    FueraDeContexto,
+    ReferenciaDesconocida,
+    AsignacionAConstante,
+    UnidadesIncompatibles,
+    ClaveInvalida,
+    TipoDesconocido,
+    CamposIncorrectos,
+    CasosIncorrectos,
+    LlamadaIncorrecta,
+    FuncionIncompatible,
+    VariableNoDefinida,
+    FuncionNoDefinida,
+    TiposIncompatibles,
+    DivisionPorCero,
+    ArgumentosIncorrectos,
+    EscenaNoDefinida,
+    IntervaloInvalido,
+    BucleDeEventos,
+    MaquinaNoActiva,
+    IndiceFueraDeRango,
+    ClaveNoEncontrada,
+    CampoDesconocido,
+    SinCaso,
+    ErrorNoCapturado,
+    ColeccionCircular,
+    Desbordamiento,
+    BucleDeEscenas,
+}
+
+impl Codigo {
+    pub const TODOS: [Codigo; 40] = [
+        Codigo::CaracterInesperado,
+        Codigo::TextoSinTerminar,
+        Codigo::NumeroInvalido,
+        Codigo::IdiomaDesconocido,
+        Codigo::NombreReservado,
+        Codigo::ComentarioSinTerminar,
+        Codigo::InterpolacionInvalida,
+        Codigo::EscapeInvalido,
+        Codigo::UnidadDesconocida,
+        Codigo::TokenInesperado,
+        Codigo::NombreEsperado,
+        Codigo::DeclaracionInvalida,
+        Codigo::Duplicado,
+        Codigo::FueraDeContexto,
+        Codigo::ReferenciaDesconocida,
+        Codigo::AsignacionAConstante,
+        Codigo::UnidadesIncompatibles,
+        Codigo::ClaveInvalida,
+        Codigo::TipoDesconocido,
+        Codigo::CamposIncorrectos,
+        Codigo::CasosIncorrectos,
+        Codigo::LlamadaIncorrecta,
+        Codigo::FuncionIncompatible,
+        Codigo::VariableNoDefinida,
+        Codigo::FuncionNoDefinida,
+        Codigo::TiposIncompatibles,
+        Codigo::DivisionPorCero,
+        Codigo::ArgumentosIncorrectos,
+        Codigo::EscenaNoDefinida,
+        Codigo::IntervaloInvalido,
+        Codigo::BucleDeEventos,
+        Codigo::MaquinaNoActiva,
+        Codigo::IndiceFueraDeRango,
+        Codigo::ClaveNoEncontrada,
+        Codigo::CampoDesconocido,
+        Codigo::SinCaso,
+        Codigo::ErrorNoCapturado,
+        Codigo::ColeccionCircular,
+        Codigo::Desbordamiento,
+        Codigo::BucleDeEscenas,
+    ];
+    
+    /// Código en texto, como aparece en los mensajes
+    pub fn texto(&self) -> &'static str {
+        match self {
+            Codigo::CaracterInesperado => "E0001",
+            Codigo::TextoSinTerminar => "E0002",
+            Codigo::NumeroInvalido => "E0003",
+            Codigo::IdiomaDesconocido => "E0004",
+            Codigo::NombreReservado => "E0005",
+            Codigo::ComentarioSinTerminar => "E0006",
+            Codigo::InterpolacionInvalida => "E0007",
+            Codigo::EscapeInvalido => "E0008",
+            Codigo::UnidadDesconocida => "E0009",
+            Codigo::TokenInesperado => "E0101",
+            Codigo::NombreEsperado => "E0102",
+            Codigo::DeclaracionInvalida => "E0103",
+            Codigo::Duplicado => "E0104",
+            Codigo::FueraDeContexto => "E0105",
+            Codigo::ReferenciaDesconocida => "E0106",
+            Codigo::AsignacionAConstante => "E0107",
+            Codigo::UnidadesIncompatibles => "E0201",
+            Codigo::ClaveInvalida => "E0202",
+            Codigo::TipoDesconocido => "E0203",
+            Codigo::CamposIncorrectos => "E0204",
+            Codigo::CasosIncorrectos => "E0205",
+            Codigo::LlamadaIncorrecta => "E0206",
+            Codigo::FuncionIncompatible => "E0207",
+            Codigo::VariableNoDefinida => "E0301",
+            Codigo::FuncionNoDefinida => "E0302",
+            Codigo::TiposIncompatibles => "E0303",
+            Codigo::DivisionPorCero => "E0304",
+            Codigo::ArgumentosIncorrectos => "E0305",
+            Codigo::EscenaNoDefinida => "E0306",
+            Codigo::IntervaloInvalido => "E0307",
+            Codigo::BucleDeEventos => "E0308",
+            Codigo::MaquinaNoActiva => "E0309",
+            Codigo::IndiceFueraDeRango => "E0310",
+            Codigo::ClaveNoEncontrada => "E0311",
+            Codigo::CampoDesconocido => "E0312",
+            Codigo::SinCaso => "E0313",
+            Codigo::ErrorNoCapturado => "E0314",
+            Codigo::ColeccionCircular => "E0315",
+            Codigo::Desbordamiento => "E0316",
+            Codigo::BucleDeEscenas => "E0317",
+        }
+    }
+    
+    /// Descripción de una línea, para la lista de `vcode explicar`
+    pub fn titulo(&self) -> &'static str {
+        match self {
+            Codigo::CaracterInesperado => "carácter inesperado",
+            Codigo::TextoSinTerminar => "texto sin terminar",
+            Codigo::NumeroInvalido => "número inválido",
+            Codigo::IdiomaDesconocido => "idioma desconocido",
+            Codigo::NombreReservado => "nombre reservado en el idioma de destino",
+            Codigo::ComentarioSinTerminar => "comentario sin terminar",
+            Codigo::InterpolacionInvalida => "interpolación inválida en un texto",
+            Codigo::EscapeInvalido => "secuencia de escape inválida",
+            Codigo::UnidadDesconocida => "unidad de medida desconocida",
+            Codigo::TokenInesperado => "símbolo inesperado",
+            Codigo::NombreEsperado => "se esperaba un nombre",
+            Codigo::DeclaracionInvalida => "declaración inválida en el nivel superior",
+            Codigo::Duplicado => "declaración duplicada",
+            Codigo::FueraDeContexto => "construcción fuera de su contexto",
+            Codigo::ReferenciaDesconocida => "referencia desconocida",
+            Codigo::AsignacionAConstante => "asignación a una constante",
+            Codigo::UnidadesIncompatibles => "unidades incompatibles",
+            Codigo::ClaveInvalida => "clave de mapa inválida",
+            Codigo::TipoDesconocido => "tipo desconocido",
+            Codigo::CamposIncorrectos => "campos incorrectos al construir una estructura o una variante",
+            Codigo::CasosIncorrectos => "casos de 'segun' incompletos o repetidos",
+            Codigo::LlamadaIncorrecta => "argumentos que no encajan con la función",
+            Codigo::FuncionIncompatible => "función que no encaja con su tipo",
+            Codigo::VariableNoDefinida => "variable no definida",
+            Codigo::FuncionNoDefinida => "función no definida",
+            Codigo::TiposIncompatibles => "tipos incompatibles",
+            Codigo::DivisionPorCero => "división por cero",
+            Codigo::ArgumentosIncorrectos => "argumentos incorrectos",
+            Codigo::EscenaNoDefinida => "escena no definida o ambigua",
+            Codigo::IntervaloInvalido => "intervalo inválido",
+            Codigo::BucleDeEventos => "bucle de eventos",
+            Codigo::MaquinaNoActiva => "máquina no activa",
+            Codigo::IndiceFueraDeRango => "índice fuera de rango",
+            Codigo::ClaveNoEncontrada => "clave no encontrada",
+            Codigo::CampoDesconocido => "campo desconocido",
+            Codigo::SinCaso => "ningún caso de 'segun' coincide",
+            Codigo::ErrorNoCapturado => "error lanzado y no capturado",
+            Codigo::ColeccionCircular => "colección dentro de sí misma",
+            Codigo::Desbordamiento => "entero fuera de rango",
+            Codigo::BucleDeEscenas => "bucle de escenas",
+        }
+    }
+    
+    /// Busca un código por su texto (`E0102`, sin distinguir mayúsculas)
+    pub fn buscar(texto: &str) -> Option<Codigo> {
+        Codigo::TODOS
+            .iter()
+            .copied()
+            .find(|codigo| codigo.texto().eq_ignore_ascii_case(texto))
+    }
+    
+    pub fn fase(&self) -> Fase {
+        match self.texto().as_bytes()[2] {
+            b'0' => Fase::Lexica,
+            b'1' => Fase::Sintactica,
+            b'2' => Fase::Semantica,
+            _ => Fase::Ejecucion,
+        }
+    }
+    
+    /// Explicación larga para `vcode explicar`
+    pub fn explicacion(&self) -> &'static str {
+        match self {
+            Codigo::CaracterInesperado => "\
+El código contiene un carácter que no forma parte de V-Code.
+
+    var precio = 10 $
+
+Revisa que no haya símbolos sueltos. Para comparar distinto usa `!=`;
+la negación lógica se escribe `no`.",
+            Codigo::TextoSinTerminar => "\
+Un texto empieza con comillas pero no se cierran antes del final del
+archivo.
+
+    mostrar \"Hola
+
+Cierra el texto con otra comilla doble: \"Hola\".",
+            Codigo::NumeroInvalido => "\
+Un literal numérico no puede representarse, normalmente porque excede el
+rango de los enteros de 64 bits.
+
+    var grande = 99999999999999999999
+
+Los enteros admiten hexadecimal (`0xFF`), binario (`0b1010`) y
+separadores (`1_000_000`); el separador `_` solo puede ir entre dígitos.
+Usa un decimal, como `1.0e20`, si necesitas valores más grandes.",
+            Codigo::IdiomaDesconocido => "\
+El pragma de idioma al principio del archivo, o la opción `--idioma`,
+nombra un conjunto de palabras reservadas que no existe.
+
+    @idioma fr
+
+Los idiomas disponibles son `es` (español, por defecto) y `en` (inglés).",
+            Codigo::NombreReservado => "\
+Al traducir un archivo a otro idioma, un nombre del programa coincide
+con una palabra reservada del idioma de destino y dejaría de ser un
+nombre.
+
+    var show = 3    // `show` es `mostrar` en inglés
+
+Renombra la variable, función o escena antes de traducir.",
+            Codigo::ComentarioSinTerminar => "\
+Un comentario `/*` no se cierra antes del final del archivo. Los
+comentarios de bloque se anidan, así que cada `/*` interior necesita
+también su `*/`.
+
+    /* desactivado:
+       /* versión antigua */
+       mostrar x
+
+Cierra el comentario exterior con otro `*/`.",
+            Codigo::InterpolacionInvalida => "\
+Dentro de un texto, `{...}` inserta el valor de una expresión. La
+expresión no puede estar vacía y debe cerrarse con `}` antes de la
+comilla final.
+
+    mostrar \"Puntos: {puntos\"
+    mostrar \"Llaves: {}\"
+
+Para mostrar llaves literales escríbelas con barra: `\\{` y `\\}`.",
+            Codigo::EscapeInvalido => "\
+El escape `\\u{...}` de un texto inserta un carácter por su código
+Unicode en hexadecimal, de 1 a 6 dígitos entre llaves.
+
+    mostrar \"Ma\\u{F1}ana\"    // Mañana
+    mostrar \"\\u{110000}\"      // Error: fuera de rango
+
+Los códigos válidos van de 0 a 10FFFF, sin los sustitutos D800 a DFFF.",
+            Codigo::UnidadDesconocida => "\
+Un número lleva pegada una palabra que no es una unidad de medida.
+
+    var masa = 5kg
+
+Las unidades disponibles son m, cm, mm y km (longitud), s, ms, min y h
+(tiempo), y rad y grados (ángulo). Si la palabra es un nombre, sepárala
+del número con un espacio.",
+            Codigo::TokenInesperado => "\
+El parser esperaba un símbolo concreto (una llave, un paréntesis, una
+palabra reservada...) y encontró otro.
+
+    si x > 3
+        mostrar x
+    }
+
+El mensaje indica qué se esperaba; aquí falta la `{` del bloque.",
+            Codigo::NombreEsperado => "\
+En esta posición hace falta un nombre (de variable, función, escena,
+parámetro, estado...) y se encontró otra cosa.
+
+    funcion (x) {
+        retornar x
+    }
+
+Los nombres empiezan por una letra o `_`. Las palabras reservadas como
+`escena` o `funcion` no pueden usarse como nombre.",
+            Codigo::DeclaracionInvalida => "\
+En el nivel superior de un archivo solo pueden aparecer escenas y
+funciones.
+
+    var x = 1
+    escena Principal { }
+
+Mueve las sentencias dentro de una escena o de una función.",
+            Codigo::Duplicado => "\
+Algo que debe ser único se declaró dos veces: un bloque `al_iniciar` o
+`al_salir`, un estado de una máquina, una máquina o una escena.
+
+    maquina Puerta {
+        estado Cerrada { }
+        estado Cerrada { }
+    }
+
+Elimina o renombra una de las declaraciones.",
+            Codigo::FueraDeContexto => "\
+Una construcción se usó donde no está permitida; por ejemplo `esperar`
+fuera de una corrutina o un tipo de retorno en una corrutina.
+
+    funcion abrir() {
+        esperar 1
+    }
+
+Declara la función con `corrutina` para poder suspenderla.
+
+Tampoco se puede `esperar` dentro de `intentar`, `capturar` o `finalmente`,
+ni siquiera en una corrutina: un `intentar` se ejecuta entero en el mismo
+frame. Espera antes o después y deja dentro solo lo que puede fallar:
+
+    corrutina cargar() {
+        esperar 1
+        intentar {
+            abrir_puerta()
+        } capturar e {
+            mostrar e
+        }
+    }",
+            Codigo::ReferenciaDesconocida => "\
+Se hace referencia a algo que no está declarado en su contexto, como una
+transición a un estado que la máquina no tiene o un atributo desconocido.
+
+    estado Quieto {
+        transicion a Huir cuando peligro
+    }
+
+Declara el estado `Huir` o corrige el nombre.",
+            Codigo::AsignacionAConstante => "\
+Se asigna un valor nuevo a un nombre declarado con `constante`, que no
+puede cambiar.
+
+    constante GRAVEDAD = 9.8
+    GRAVEDAD = 1.6
+
+Declara el nombre con `var` si su valor debe cambiar.",
+            Codigo::UnidadesIncompatibles => "\
+Se combinan medidas de dimensiones distintas, o una medida con un número
+sin unidad, en una suma, resta, módulo, comparación o asignación.
+
+    var distancia = 2m
+    var total = distancia + 500ms     // metros más segundos
+    si distancia > 1 { }              // ¿1 metro? ¿1 centímetro?
+
+Las unidades de una misma dimensión se convierten solas (`1m + 20cm`).
+Escribe la unidad de cada número, y divide entre una unidad para obtener
+un número sin ella: `distancia / 1cm` vale 200. Los intervalos de
+`esperar`, `despues` y `cada` deben ser tiempos si llevan unidad.",
+            Codigo::ClaveInvalida => "\
+Las claves de un mapa solo pueden ser textos, números enteros o
+booleanos, en el tipo `mapa<K, V>` y en los literales de mapa.
+
+    funcion f(posiciones: mapa<decimal, texto>) { }
+    var por_lista = { [1, 2]: \"a\" }
+
+Usa como clave un texto o un número que identifique cada entrada.",
+            Codigo::TipoDesconocido => "\
+Un tipo con nombre no es un tipo básico ni una estructura declarada en el
+programa.
+
+    funcion mover(p: Puntto) { }
+
+Revisa el nombre o declara la estructura: estructura Punto { x: decimal }.",
+            Codigo::CamposIncorrectos => "\
+Al construir una estructura falta alguno de sus campos, se nombra uno
+que no tiene o se repite alguno.
+
+    estructura Punto { x: decimal, y: decimal }
+    var p = Punto(x: 1)
+
+Da valor a cada campo de la estructura una sola vez: Punto(x: 1, y: 0).
+Una variante de una enumeración recibe tantos valores como tipos declara,
+también al nombrarlos en un caso de `segun`:
+
+    enumeracion Forma { Circulo(decimal), Vacia }
+    var f = Circulo()",
+            Codigo::CasosIncorrectos => "\
+Un `segun` no cubre todos los valores posibles, repite un caso, tiene
+casos después de `otro` o mezcla variantes de enumeraciones distintas.
+
+    enumeracion Luz { Roja, Ambar, Verde }
+    segun luz {
+        caso Roja => mostrar \"para\"
+        caso Verde => mostrar \"sigue\"
+    }
+
+Añade los casos que faltan (`caso Ambar => ...`) o un caso `otro => ...`
+para los demás. Un `segun` sobre números o textos necesita siempre `otro`.",
+            Codigo::LlamadaIncorrecta => "\
+Se llama a una función de colecciones (`mapear`, `filtrar`, `reducir`...)
+con un número de argumentos distinto del que espera, o se le pasa una
+función que no recibe los parámetros con que se la va a llamar.
+
+    mapear(puntos, funcion(a: numero, b: numero) => a + b)
+
+`mapear` llama a su función con cada elemento, así que debe recibir uno:
+mapear(puntos, funcion(p: numero) => p * 2).",
+            Codigo::FuncionIncompatible => "\
+Se pasa, se retorna o se guarda en un campo una función cuyos parámetros o
+retorno no son los del tipo `funcion(P, ...): R` declarado.
+
+    funcion aplicar(f: funcion(numero): numero, x: numero): numero {
+        retornar f(x)
+    }
+    aplicar(funcion(a: numero, b: numero) => a + b, 5)
+
+La función debe recibir los mismos parámetros, en el mismo orden, y
+retornar lo declarado: aplicar(funcion(a: numero) => a * 2, 5).",
+            Codigo::VariableNoDefinida => "\
+Se usa o se asigna una variable que no se declaró con `var` en un ámbito
+visible.
+
+    puntos = 10
+
+Declárala primero: var puntos = 10.",
+            Codigo::FuncionNoDefinida => "\
+Se llama a una función que no está definida en el programa ni es una
+función nativa del runtime.
+
+Revisa el nombre o define la función con `funcion`.",
+            Codigo::TiposIncompatibles => "\
+Una operación recibió valores de tipos que no admite, por ejemplo restar
+un texto a un número.
+
+    var total = \"5\" - 2
+
+Convierte los valores al tipo adecuado antes de operar.",
+            Codigo::DivisionPorCero => "\
+Se dividió (o se calculó el módulo) por cero.
+
+    var media = total / cantidad
+
+Comprueba que el divisor no sea cero antes de dividir.",
+            Codigo::ArgumentosIncorrectos => "\
+Una función, función nativa u oyente de eventos recibió un número o tipo
+de argumentos distinto del que espera.
+
+    funcion sumar(a: numero, b: numero): numero { retornar a + b }
+    sumar(1)
+
+Pasa exactamente los argumentos declarados.",
+            Codigo::EscenaNoDefinida => "\
+Se pidió una escena que no existe, con `cambiar_escena` o con `--escena`,
+o la escena de entrada es ambigua o está repetida.
+
+Revisa el nombre de la escena; distingue mayúsculas y minúsculas.",
+            Codigo::IntervaloInvalido => "\
+El intervalo de `despues`, `cada` o `esperar` no es un número válido:
+es negativo, cero en `cada`, o no es finito.
+
+    cada 0 { }
+
+Usa un intervalo positivo o `cada frame`.",
+            Codigo::BucleDeEventos => "\
+Se entregaron demasiados eventos en un mismo frame. Suele indicar un
+oyente que reemite su propio evento sin condición.
+
+    escuchar \"ping\" { emitir \"ping\" }
+
+Añade una condición de parada o difiere la emisión con `despues`.",
+            Codigo::MaquinaNoActiva => "\
+`estado_de` recibió el nombre de una máquina que no está activa en la
+escena en curso.
+
+Revisa el nombre de la máquina y que esté declarada en esta escena.",
+            Codigo::IndiceFueraDeRango => "\
+Se accedió a un elemento que no existe: los índices de una lista o un
+texto van de 0 a su longitud menos uno.
+
+    var colores = [\"rojo\", \"verde\"]
+    mostrar colores[2]
+
+Comprueba el índice con `longitud(colores)` antes de usarlo.",
+            Codigo::ClaveNoEncontrada => "\
+Se leyó o se quitó de un mapa una clave que no tiene.
+
+    var inventario = { \"oro\": 10 }
+    mostrar inventario[\"plata\"]
+
+Comprueba la clave con `tiene(inventario, \"plata\")` o asígnale un
+valor antes: `inventario[\"plata\"] = 0`.",
+            Codigo::CampoDesconocido => "\
+Se leyó o se cambió un campo que la estructura no tiene.
+
+    estructura Punto { x: decimal, y: decimal }
+    var p = Punto(x: 1, y: 2)
+    mostrar p.z
+
+Revisa el nombre del campo en la declaración de la estructura.",
+            Codigo::SinCaso => "\
+El valor de un `segun` no coincide con ninguno de sus casos porque no es
+una variante de la enumeración de los casos.
+
+    enumeracion Luz { Roja, Verde }
+    funcion describir(luz: Luz) {
+        segun luz {
+            caso Roja => mostrar \"para\"
+            caso Verde => mostrar \"sigue\"
+        }
+    }
+    describir(3)
+
+Comprueba el valor que llega al `segun` o añade un caso `otro => ...`.",
+            Codigo::ErrorNoCapturado => "\
+Un `lanzar` lanzó un valor y ningún `intentar` que lo rodee lo capturó.
+
+    funcion disparar(balas: numero) {
+        si balas == 0 {
+            lanzar \"sin munición\"
+        }
+    }
+    disparar(0)
+
+Rodea la llamada con `intentar { ... } capturar e { ... }` para tratar el
+error, o comprueba antes la condición que lo lanza.",
+            Codigo::ColeccionCircular => "\
+Se intentó meter una lista o un mapa dentro de sí mismo, directamente o
+dentro de otra colección o estructura que ya guarda. Una colección así no
+se podría mostrar ni comparar.
+
+    var l = [1]
+    var otra = [l]
+    agregar(l, otra)
+
+Guarda una lista nueva con los mismos elementos, por ejemplo
+`l[0:]`, o reorganiza los datos para que ninguna colección se contenga.",
+            Codigo::Desbordamiento => "\
+Una operación entre números enteros dio un resultado fuera de su rango, que
+va de -9223372036854775808 a 9223372036854775807.
+
+    var grande = 9223372036854775807
+    mostrar grande + 1
+
+Si necesitas valores tan grandes, opera con decimales: `grande * 1.0 + 1`
+pierde precisión pero no se desborda.",
+            Codigo::BucleDeEscenas => "\
+Las escenas cambiaron de una a otra demasiadas veces seguidas sin que
+ninguna llegara a avanzar un frame. Suele indicar dos escenas que se
+cambian entre sí sin condición.
+
+    escena Menu { cambiar_escena(\"Nivel\") }
+    escena Nivel { cambiar_escena(\"Menu\") }
+
+Cambia de escena solo cuando se cumpla una condición, o difiere el cambio
+con `despues`.",
+        }
+    }
+}
+
+/// Etiqueta secundaria: otra posición relevante con su explicación
+#[derive(Debug, Clone, PartialEq)]
+pub struct Etiqueta {
+    pub pos: Posicion,
+    pub mensaje: String,
+}
+
+/// Texto que acompaña al mensaje principal, al final del diagnóstico
+#[derive(Debug, Clone, PartialEq)]
+pub enum Anotacion {
+    Nota(String),       // Contexto adicional
+    Sugerencia(String), // Cómo corregirlo
+}
+
+/// Error informado por cualquiera de las fases
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostico {
+    pub codigo: Codigo,
+    pub mensaje: String,
+    pub pos: Option<Posicion>,
+    pub etiquetas: Vec<Etiqueta>,
+    pub anotaciones: Vec<Anotacion>,
+}
+
+impl Diagnostico {
+    pub fn new(codigo: Codigo, mensaje: impl Into<String>, pos: &Posicion) -> Self {
+        Diagnostico {
+            pos: Some(pos.clone()),
+            ..Diagnostico::sin_posicion(codigo, mensaje)
+        }
+    }
+    
+    /// Diagnóstico que no apunta a ninguna línea (p. ej. opciones de la CLI)
+    pub fn sin_posicion(codigo: Codigo, mensaje: impl Into<String>) -> Self {
+        Diagnostico {
+            codigo,
+            mensaje: mensaje.into(),
+            pos: None,
+            etiquetas: Vec::new(),
+            anotaciones: Vec::new(),
+        }
+    }
+    
+    pub fn con_etiqueta(mut self, pos: &Posicion, mensaje: impl Into<String>) -> Self {
+        self.etiquetas.push(Etiqueta {
+            pos: pos.clone(),
+            mensaje: mensaje.into(),
+        });
+        self
+    }
+    
+    pub fn con_nota(mut self, nota: impl Into<String>) -> Self {
+        self.anotaciones.push(Anotacion::Nota(nota.into()));
+        self
+    }
+    
+    pub fn con_sugerencia(mut self, sugerencia: impl Into<String>) -> Self {
+        self.anotaciones.push(Anotacion::Sugerencia(sugerencia.into()));
+        self
+    }
+    
+    pub fn fase(&self) -> Fase {
+        self.codigo.fase()
+    }
+    
+    /// Muestra el diagnóstico con las líneas de `fuente` implicadas y el
+    /// tramo del error subrayado
+    pub fn renderizar(&self, fuente: &str, archivo: &str) -> String {
+        let mut salida = format!(
+            "error {} [{}]: {}\n",
+            self.fase(),
+            self.codigo.texto(),
+            self.mensaje
+        );
+        
+        let lineas: Vec<&str> = fuente.lines().collect();
+        
+        // Posición principal y etiquetas, en orden de línea
+        let mut marcas: Vec<(&Posicion, char, &str)> = Vec::new();
+        if let Some(pos) = &self.pos {
+            salida.push_str(&format!("  --> {}:{}:{}\n", archivo, pos.linea, pos.columna));
+            marcas.push((pos, '^', ""));
+        }
+        for etiqueta in &self.etiquetas {
+            marcas.push((&etiqueta.pos, '-', &etiqueta.mensaje));
+        }
+        marcas.sort_by_key(|(pos, _, _)| (pos.linea, pos.columna));
+        
+        let ancho = marcas
+            .iter()
+            .map(|(pos, _, _)| pos.linea.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let margen = " ".repeat(ancho);
+        
+        if !marcas.is_empty() {
+            salida.push_str(&format!("{} |\n", margen));
+        }
+        let mut linea_anterior = None;
+        for (pos, simbolo, mensaje) in marcas {
+            let Some(linea) = lineas.get((pos.linea as usize).wrapping_sub(1)) else {
+                continue;
+            };
+            let subrayado = simbolo.to_string().repeat(ancho_subrayado(linea, pos));
+            let sangria = " ".repeat((pos.columna as usize).saturating_sub(1));
+            // Varias marcas en la misma línea la muestran una sola vez
+            if linea_anterior != Some(pos.linea) {
+                salida.push_str(&format!("{:>ancho$} | {}\n", pos.linea, linea, ancho = ancho));
+                linea_anterior = Some(pos.linea);
+            }
+            salida.push_str(format!("{} | {}{} {}", margen, sangria, subrayado, mensaje).trim_end());
+            salida.push('\n');
+        }
+        
+        for anotacion in &self.anotaciones {
+            let (tipo, texto) = match anotacion {
+                Anotacion::Nota(texto) => ("nota", texto),
+                Anotacion::Sugerencia(texto) => ("sugerencia", texto),
+            };
+            salida.push_str(&format!("{} = {}: {}\n", margen, tipo, texto));
+        }
+        salida.push_str(&format!(
+            "{} = ayuda: `vcode explicar {}` para más detalles\n",
+            margen,
+            self.codigo.texto()
+        ));
+        
+        salida
+    }
+}
+
+/// Ancho a subrayar en `linea` para el tramo `pos`: hasta su final, o hasta
+/// el final de la línea si el tramo sigue en las siguientes. Un tramo vacío
+/// subraya la palabra que empieza ahí, o un único carácter.
+fn ancho_subrayado(linea: &str, pos: &Posicion) -> usize {
+    let columna = pos.columna as usize;
+    let ancho = if pos.linea_fin > pos.linea {
+        (linea.trim_end().chars().count() + 1).saturating_sub(columna)
+    } else if pos.columna_fin > pos.columna {
+        (pos.columna_fin - pos.columna) as usize
+    } else {
+        linea
+            .chars()
+            .skip(columna.saturating_sub(1))
+            .take_while(|c| unicode_ident::is_xid_continue(*c))
+            .count()
+    };
+    ancho.max(1)
+}
+
+impl fmt::Display for Diagnostico {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}", self.codigo.texto(), self.mensaje)?;
+        if let Some(pos) = &self.pos {
+            write!(f, " en {}", pos)?;
+        }
+        for anotacion in &self.anotaciones {
+            if let Anotacion::Nota(nota) = anotacion {
+                write!(f, " ({})", nota)?;
+            }
+        }
+        Ok(())
+    }
+}
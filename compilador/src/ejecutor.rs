@@ -2,16 +2,38 @@
 // Ejecuta el AST
 
 use crate::ast::*;
+use crate::colecciones::{self, Firma};
+use crate::diagnostico::{Codigo, Diagnostico};
+use crate::eventos::{BusEventos, Evento, Manejador, Oyente, MAX_EVENTOS_POR_FRAME};
+use crate::maquinas::{InstanciaMaquina, RegistroTransicion};
+use crate::unidades::Dimension;
+use crate::planificador::{
+    vencido, Corrutina, Despertar, Marco, Periodo, Planificador, Reloj, FRAMES_MAXIMOS,
+};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt;
+use std::rc::Rc;
+
+/// Cambios de escena seguidos, sin que ninguna avance un frame, antes de
+/// dar por hecho que las escenas se cambian entre sí en bucle
+const MAX_CAMBIOS_SIN_FRAME: usize = 1_000;
 
 /// Valor en tiempo de ejecución
 #[derive(Debug, Clone, PartialEq)]
 pub enum Valor {
     Numero(i64),
     Decimal(f64),
+    Medida(f64, Dimension), // En la unidad base de su dimensión
     Texto(String),
     Booleano(bool),
+    Lista(Rc<RefCell<Vec<Valor>>>), // Compartida: quien la recibe modifica la misma
+    Mapa(Rc<RefCell<Mapa>>),        // Compartido como las listas
+    Estructura(String, Vec<(String, Valor)>), // Se copia al asignarla; campos en orden de declaración
+    Variante(String, String, Vec<Valor>),     // Enumeración, variante y sus valores; se copia como las estructuras
+    Funcion(Rc<FuncionDefinida>),             // Declarada o anónima; dos son iguales si son la misma
+    Temporizador(u64),
+    Corrutina(u64),
     Nulo,
 }
 
@@ -20,8 +42,63 @@ impl fmt::Display for Valor {
         match self {
             Valor::Numero(n) => write!(f, "{}", n),
             Valor::Decimal(d) => write!(f, "{}", d),
+            Valor::Medida(valor, dimension) => write!(f, "{}{}", valor, dimension),
             Valor::Texto(s) => write!(f, "{}", s),
             Valor::Booleano(b) => write!(f, "{}", if *b { "verdadero" } else { "falso" }),
+            Valor::Lista(lista) => {
+                write!(f, "[")?;
+                for (i, elemento) in lista.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    elemento.escribir_citado(f)?;
+                }
+                write!(f, "]")
+            }
+            Valor::Mapa(mapa) => {
+                write!(f, "{{")?;
+                for (i, (clave, valor)) in mapa.borrow().entradas.iter().enumerate() {
+                    write!(f, "{}", if i > 0 { ", " } else { " " })?;
+                    clave.escribir_citado(f)?;
+                    write!(f, ": ")?;
+                    valor.escribir_citado(f)?;
+                }
+                if !mapa.borrow().entradas.is_empty() {
+                    write!(f, " ")?;
+                }
+                write!(f, "}}")
+            }
+            Valor::Estructura(nombre, campos) => {
+                write!(f, "{} {{", nombre)?;
+                for (i, (campo, valor)) in campos.iter().enumerate() {
+                    write!(f, "{}{}: ", if i > 0 { ", " } else { " " }, campo)?;
+                    valor.escribir_citado(f)?;
+                }
+                if !campos.is_empty() {
+                    write!(f, " ")?;
+                }
+                write!(f, "}}")
+            }
+            Valor::Variante(_, variante, valores) => {
+                write!(f, "{}", variante)?;
+                if !valores.is_empty() {
+                    write!(f, "(")?;
+                    for (i, valor) in valores.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        valor.escribir_citado(f)?;
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Valor::Funcion(funcion) => match &funcion.nombre {
+                Some(nombre) => write!(f, "función {}", nombre),
+                None => write!(f, "función anónima"),
+            },
+            Valor::Temporizador(id) => write!(f, "temporizador #{}", id),
+            Valor::Corrutina(id) => write!(f, "corrutina #{}", id),
             Valor::Nulo => write!(f, "nulo"),
         }
     }
@@ -35,16 +112,15 @@ impl Valor {
             Valor::Nulo => false,
             Valor::Numero(n) => *n != 0,
             Valor::Decimal(d) => *d != 0.0,
+            Valor::Medida(valor, _) => *valor != 0.0,
             Valor::Texto(s) => !s.is_empty(),
-        }
-    }
-    
-    /// Convierte a número si es posible
-    pub fn a_numero(&self) -> Result<i64, String> {
-        match self {
-            Valor::Numero(n) => Ok(*n),
-            Valor::Decimal(d) => Ok(*d as i64),
-            _ => Err(format!("No se puede convertir {} a número", self)),
+            Valor::Lista(lista) => !lista.borrow().is_empty(),
+            Valor::Mapa(mapa) => !mapa.borrow().entradas.is_empty(),
+            Valor::Estructura(..)
+            | Valor::Variante(..)
+            | Valor::Funcion(_)
+            | Valor::Temporizador(_)
+            | Valor::Corrutina(_) => true,
         }
     }
     
@@ -56,67 +132,301 @@ impl Valor {
             _ => Err(format!("No se puede convertir {} a decimal", self)),
         }
     }
+    
+    /// Lista nueva con los elementos `elementos`
+    pub fn lista(elementos: Vec<Valor>) -> Valor {
+        Valor::Lista(Rc::new(RefCell::new(elementos)))
+    }
+    
+    /// Si el valor es la lista o el mapa `coleccion` o lo lleva dentro, en
+    /// otra colección, una estructura o una variante
+    fn contiene_coleccion(&self, coleccion: &Valor) -> bool {
+        let misma = match (self, coleccion) {
+            (Valor::Lista(a), Valor::Lista(b)) => Rc::ptr_eq(a, b),
+            (Valor::Mapa(a), Valor::Mapa(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        };
+        misma
+            || match self {
+                Valor::Lista(lista) => lista.borrow().iter().any(|v| v.contiene_coleccion(coleccion)),
+                Valor::Mapa(mapa) => mapa.borrow().entradas.iter().any(|(_, v)| v.contiene_coleccion(coleccion)),
+                Valor::Estructura(_, campos) => campos.iter().any(|(_, v)| v.contiene_coleccion(coleccion)),
+                Valor::Variante(_, _, valores) => valores.iter().any(|v| v.contiene_coleccion(coleccion)),
+                _ => false,
+            }
+    }
+    
+    /// Escribe el valor como elemento de una lista o un mapa, con los
+    /// textos entre comillas
+    fn escribir_citado(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Valor::Texto(texto) => write!(f, "\"{}\"", texto),
+            otro => write!(f, "{}", otro),
+        }
+    }
+    
+    /// Una medida, o un número visto como medida sin dimensión
+    fn como_medida(&self) -> Option<(f64, Dimension)> {
+        match self {
+            Valor::Medida(valor, dimension) => Some((*valor, *dimension)),
+            Valor::Numero(n) => Some((*n as f64, Dimension::default())),
+            Valor::Decimal(d) => Some((*d, Dimension::default())),
+            _ => None,
+        }
+    }
+    
+    /// Medida de dimensión `dimension`; sin dimensión es un decimal
+    fn medida(valor: f64, dimension: Dimension) -> Valor {
+        if dimension.es_adimensional() {
+            Valor::Decimal(valor)
+        } else {
+            Valor::Medida(valor, dimension)
+        }
+    }
+}
+
+/// Cómo termina una sentencia: se sigue con la siguiente o se sale del
+/// bloque hacia la función o el bucle que la rodea
+#[derive(Debug, Clone, PartialEq)]
+pub enum Flujo {
+    Normal,
+    Retornar(Valor),
+    Romper(Option<String>),    // Con la etiqueta del bucle, si se indicó
+    Continuar(Option<String>),
+}
+
+impl Flujo {
+    /// Si es un `romper` o `continuar` dirigido al bucle con `etiqueta`:
+    /// sin etiqueta se dirige al bucle más interior
+    fn es_para(salto: &Option<String>, etiqueta: &Option<String>) -> bool {
+        salto.is_none() || salto == etiqueta
+    }
+}
+
+/// Entradas de un mapa en orden de inserción. Las claves son textos,
+/// números enteros o booleanos y se buscan recorriendo las entradas, que en
+/// los datos de una escena son pocas.
+#[derive(Debug, Clone, Default)]
+pub struct Mapa {
+    entradas: Vec<(Valor, Valor)>,
 }
 
-/// Entorno de ejecución (scope)
+impl Mapa {
+    pub fn obtener(&self, clave: &Valor) -> Option<&Valor> {
+        self.entradas.iter().find(|(otra, _)| otra == clave).map(|(_, valor)| valor)
+    }
+    
+    /// Cambia el valor de la clave, o la añade al final si no la tiene
+    pub fn insertar(&mut self, clave: Valor, valor: Valor) {
+        match self.entradas.iter_mut().find(|(otra, _)| *otra == clave) {
+            Some((_, anterior)) => *anterior = valor,
+            None => self.entradas.push((clave, valor)),
+        }
+    }
+    
+    pub fn quitar(&mut self, clave: &Valor) -> Option<Valor> {
+        let i = self.entradas.iter().position(|(otra, _)| otra == clave)?;
+        Some(self.entradas.remove(i).1)
+    }
+    
+    pub fn claves(&self) -> Vec<Valor> {
+        self.entradas.iter().map(|(clave, _)| clave.clone()).collect()
+    }
+    
+    pub fn valores(&self) -> Vec<Valor> {
+        self.entradas.iter().map(|(_, valor)| valor.clone()).collect()
+    }
+}
+
+/// Dos mapas son iguales si tienen las mismas entradas, en cualquier orden
+impl PartialEq for Mapa {
+    fn eq(&self, otro: &Mapa) -> bool {
+        self.entradas.len() == otro.entradas.len()
+            && self.entradas.iter().all(|(clave, valor)| otro.obtener(clave) == Some(valor))
+    }
+}
+
+/// Valores que recorre un bucle `para`, generados uno a uno
 #[derive(Debug, Clone)]
+pub enum Recorrido {
+    Enteros {
+        siguiente: Option<i64>, // `None` si el paso se sale del rango de i64
+        fin: i64,
+        paso: i64,
+        inclusivo: bool,
+    },
+    // Cada valor es `inicio + vuelta * paso`, sin acumular errores de redondeo
+    Decimales {
+        inicio: f64,
+        vuelta: u32,
+        fin: f64,
+        paso: f64,
+        inclusivo: bool,
+        dimension: Dimension,
+    },
+    Elementos(std::vec::IntoIter<Valor>), // Letras de un texto, o copia de una lista o de las claves de un mapa
+}
+
+impl Iterator for Recorrido {
+    type Item = Valor;
+    
+    fn next(&mut self) -> Option<Valor> {
+        match self {
+            Recorrido::Enteros { siguiente, fin, paso, inclusivo } => {
+                let valor = (*siguiente)?;
+                let dentro = match (*paso > 0, *inclusivo) {
+                    (true, true) => valor <= *fin,
+                    (true, false) => valor < *fin,
+                    (false, true) => valor >= *fin,
+                    (false, false) => valor > *fin,
+                };
+                if !dentro {
+                    return None;
+                }
+                *siguiente = valor.checked_add(*paso);
+                Some(Valor::Numero(valor))
+            }
+            Recorrido::Decimales { inicio, vuelta, fin, paso, inclusivo, dimension } => {
+                let valor = *inicio + *vuelta as f64 * *paso;
+                // Tolerancia para que `0 hasta 1 paso 0.1` llegue a 1
+                let margen = paso.abs() * 1e-9;
+                let dentro = match (*paso > 0.0, *inclusivo) {
+                    (true, true) => valor <= *fin + margen,
+                    (true, false) => valor < *fin - margen,
+                    (false, true) => valor >= *fin - margen,
+                    (false, false) => valor > *fin + margen,
+                };
+                if !dentro {
+                    return None;
+                }
+                *vuelta += 1;
+                Some(Valor::medida(valor, *dimension))
+            }
+            Recorrido::Elementos(elementos) => elementos.next(),
+        }
+    }
+}
+
+/// Variables de un ámbito. Se comparte entre el entorno que lo abrió y
+/// las funciones anónimas creadas dentro, que ven y hacen los mismos cambios.
+type Ambito = Rc<RefCell<HashMap<String, Valor>>>;
+
+/// Entorno de ejecución (scope): los ámbitos abiertos, del exterior al
+/// actual. Clonarlo comparte los ámbitos, no los copia.
+#[derive(Clone)]
 pub struct Entorno {
-    variables: HashMap<String, Valor>,
-    padre: Option<Box<Entorno>>,
+    ambitos: Vec<Ambito>,
 }
 
 impl Entorno {
     pub fn new() -> Self {
         Entorno {
-            variables: HashMap::new(),
-            padre: None,
+            ambitos: vec![Ambito::default()],
         }
     }
     
-    pub fn con_padre(padre: Entorno) -> Self {
-        Entorno {
-            variables: HashMap::new(),
-            padre: Some(Box::new(padre)),
-        }
+    fn actual(&self) -> &Ambito {
+        self.ambitos.last().expect("el entorno siempre tiene un ámbito")
     }
     
     pub fn definir(&mut self, nombre: String, valor: Valor) {
-        self.variables.insert(nombre, valor);
+        self.actual().borrow_mut().insert(nombre, valor);
+    }
+    
+    /// Quita una variable del ámbito actual y devuelve su valor
+    pub fn quitar(&mut self, nombre: &str) -> Option<Valor> {
+        self.actual().borrow_mut().remove(nombre)
     }
     
     pub fn obtener(&self, nombre: &str) -> Option<Valor> {
-        if let Some(valor) = self.variables.get(nombre) {
-            Some(valor.clone())
-        } else if let Some(padre) = &self.padre {
-            padre.obtener(nombre)
-        } else {
-            None
+        self.ambitos
+            .iter()
+            .rev()
+            .find_map(|ambito| ambito.borrow().get(nombre).cloned())
+    }
+    
+    /// Abre un ámbito hijo con las variables dadas
+    pub fn abrir_ambito(&mut self, variables: HashMap<String, Valor>) {
+        self.ambitos.push(Rc::new(RefCell::new(variables)));
+    }
+    
+    /// Cierra el ámbito actual y devuelve sus variables. Los cambios hechos
+    /// a variables de ámbitos exteriores se conservan.
+    pub fn cerrar_ambito(&mut self) -> HashMap<String, Valor> {
+        if self.ambitos.len() == 1 {
+            return std::mem::take(&mut *self.actual().borrow_mut());
         }
+        let ambito = self.ambitos.pop().expect("hay más de un ámbito");
+        // Si una función anónima se quedó con el ámbito, sigue siendo suyo
+        Rc::try_unwrap(ambito)
+            .map(RefCell::into_inner)
+            .unwrap_or_else(|ambito| ambito.borrow().clone())
     }
     
     pub fn asignar(&mut self, nombre: &str, valor: Valor) -> Result<(), String> {
-        if self.variables.contains_key(nombre) {
-            self.variables.insert(nombre.to_string(), valor);
-            Ok(())
-        } else if let Some(padre) = &mut self.padre {
-            padre.asignar(nombre, valor)
-        } else {
-            Err(format!("Variable '{}' no definida", nombre))
+        match self.ambitos.iter().rev().find(|ambito| ambito.borrow().contains_key(nombre)) {
+            Some(ambito) => {
+                ambito.borrow_mut().insert(nombre.to_string(), valor);
+                Ok(())
+            }
+            None => Err(format!("Variable '{}' no definida", nombre)),
         }
     }
 }
 
+/// Solo la profundidad: los ámbitos pueden contener funciones que a su vez
+/// capturan esos mismos ámbitos
+impl fmt::Debug for Entorno {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Entorno").field("ambitos", &self.ambitos.len()).finish()
+    }
+}
+
 /// Definición de función en tiempo de ejecución
 #[derive(Debug, Clone)]
 pub struct FuncionDefinida {
+    pub nombre: Option<String>, // Una anónima toma el de la variable en que se declara
     pub parametros: Vec<Parametro>,
     pub cuerpo: Vec<Sentencia>,
+    pub es_corrutina: bool,
+    pub captura: Option<Entorno>, // Ámbitos en que se creó una función anónima
+}
+
+impl FuncionDefinida {
+    /// Nombre para los mensajes de error: `'sumar'` o `anónima`
+    fn describir(&self) -> String {
+        match &self.nombre {
+            Some(nombre) => format!("'{}'", nombre),
+            None => "anónima".to_string(),
+        }
+    }
+}
+
+/// Una función solo es igual a sí misma
+impl PartialEq for FuncionDefinida {
+    fn eq(&self, otra: &Self) -> bool {
+        std::ptr::eq(self, otra)
+    }
 }
 
 /// Ejecutor
 pub struct Ejecutor {
     entorno: Entorno,
-    funciones: HashMap<String, FuncionDefinida>,
+    funciones: HashMap<String, Rc<FuncionDefinida>>,
+    escenas: HashMap<String, Escena>,
+    estructuras: HashMap<String, Estructura>,
+    enumeraciones: HashMap<String, Enumeracion>,
+    variantes: HashMap<String, String>, // Enumeración de cada variante
+    escena_inicial: Option<String>,
+    escena_siguiente: Option<String>,
+    reloj: Reloj,
+    planificador: Planificador,
+    eventos: BusEventos,
+    maquinas: Vec<InstanciaMaquina>,
+    transiciones: Vec<RegistroTransicion>,
+    traza_maquinas: bool,
+    frames_maximos: u64,
+    lanzado: Option<Valor>, // Valor del `lanzar` cuyo error se está propagando
     salida: Vec<String>,
 }
 
@@ -125,159 +435,1105 @@ impl Ejecutor {
         Ejecutor {
             entorno: Entorno::new(),
             funciones: HashMap::new(),
+            escenas: HashMap::new(),
+            estructuras: HashMap::new(),
+            enumeraciones: HashMap::new(),
+            variantes: HashMap::new(),
+            escena_inicial: None,
+            escena_siguiente: None,
+            reloj: Reloj::new(),
+            planificador: Planificador::new(),
+            eventos: BusEventos::new(),
+            maquinas: Vec::new(),
+            transiciones: Vec::new(),
+            traza_maquinas: false,
+            frames_maximos: FRAMES_MAXIMOS,
+            lanzado: None,
             salida: Vec::new(),
         }
     }
     
+    /// Fija la escena de entrada, con prioridad sobre `@inicial`
+    pub fn fijar_escena_inicial(&mut self, nombre: &str) {
+        self.escena_inicial = Some(nombre.to_string());
+    }
+    
+    /// Limita cuántos frames se simulan sin visor
+    pub fn fijar_frames_maximos(&mut self, frames: u64) {
+        self.frames_maximos = frames;
+    }
+    
+    /// Muestra cada transición de las máquinas de estados al aplicarse
+    pub fn fijar_traza_maquinas(&mut self, traza: bool) {
+        self.traza_maquinas = traza;
+    }
+    
     /// Ejecuta un programa
-    pub fn ejecutar(&mut self, programa: &Programa) -> Result<(), String> {
-        // Primera pasada: registrar funciones
+    pub fn ejecutar(&mut self, programa: &Programa) -> Result<(), Diagnostico> {
+        // Primera pasada: registrar funciones, escenas, estructuras y
+        // enumeraciones
         for declaracion in &programa.declaraciones {
-            if let Declaracion::Funcion(funcion) = declaracion {
-                self.funciones.insert(
-                    funcion.nombre.clone(),
-                    FuncionDefinida {
-                        parametros: funcion.parametros.clone(),
-                        cuerpo: funcion.cuerpo.clone(),
-                    },
-                );
+            match declaracion {
+                Declaracion::Funcion(funcion) => {
+                    self.funciones.insert(
+                        funcion.nombre.clone(),
+                        Rc::new(FuncionDefinida {
+                            nombre: Some(funcion.nombre.clone()),
+                            parametros: funcion.parametros.clone(),
+                            cuerpo: funcion.cuerpo.clone(),
+                            es_corrutina: funcion.es_corrutina,
+                            captura: None,
+                        }),
+                    );
+                }
+                Declaracion::Escena(escena) => {
+                    if self.escenas.contains_key(&escena.nombre) {
+                        return Err(Diagnostico::new(
+                            Codigo::Duplicado,
+                            format!("Escena '{}' definida más de una vez", escena.nombre),
+                            &escena.pos,
+                        )
+                        .con_etiqueta(&self.escenas[&escena.nombre].pos, "definida antes aquí"));
+                    }
+                    self.escenas.insert(escena.nombre.clone(), escena.clone());
+                }
+                Declaracion::Estructura(estructura) => {
+                    if let Some(anterior) = self.estructuras.get(&estructura.nombre) {
+                        return Err(Diagnostico::new(
+                            Codigo::Duplicado,
+                            format!("Estructura '{}' definida más de una vez", estructura.nombre),
+                            &estructura.pos,
+                        )
+                        .con_etiqueta(&anterior.pos, "definida antes aquí"));
+                    }
+                    self.estructuras.insert(estructura.nombre.clone(), estructura.clone());
+                }
+                Declaracion::Enumeracion(enumeracion) => {
+                    if let Some(anterior) = self.enumeraciones.get(&enumeracion.nombre) {
+                        return Err(Diagnostico::new(
+                            Codigo::Duplicado,
+                            format!("Enumeración '{}' definida más de una vez", enumeracion.nombre),
+                            &enumeracion.pos,
+                        )
+                        .con_etiqueta(&anterior.pos, "definida antes aquí"));
+                    }
+                    for variante in &enumeracion.variantes {
+                        self.variantes.insert(variante.nombre.clone(), enumeracion.nombre.clone());
+                    }
+                    self.enumeraciones.insert(enumeracion.nombre.clone(), enumeracion.clone());
+                }
             }
         }
         
-        // Segunda pasada: ejecutar escenas
-        for declaracion in &programa.declaraciones {
-            if let Declaracion::Escena(escena) = declaracion {
-                self.ejecutar_escena(escena)?;
+        // Segunda pasada: ejecutar desde la escena de entrada, siguiendo
+        // las transiciones pedidas con `cambiar_escena`
+        let mut siguiente = self.escena_de_entrada(programa)?;
+        let mut sin_frames = 0;
+        while let Some(nombre) = siguiente {
+            let escena = self.escenas[&nombre].clone();
+            self.ejecutar_escena(&escena)?;
+            siguiente = self.escena_siguiente.take();
+            
+            sin_frames = if self.reloj.frame == 0 { sin_frames + 1 } else { 0 };
+            if let Some(destino) = siguiente.as_ref().filter(|_| sin_frames > MAX_CAMBIOS_SIN_FRAME) {
+                return Err(Diagnostico::new(
+                    Codigo::BucleDeEscenas,
+                    format!(
+                        "Más de {} cambios de escena sin avanzar ningún frame; '{}' y '{}' parecen cambiarse en bucle",
+                        MAX_CAMBIOS_SIN_FRAME, escena.nombre, destino
+                    ),
+                    &escena.pos,
+                )
+                .con_etiqueta(&self.escenas[destino].pos, "escena a la que cambia"));
             }
         }
         
         Ok(())
     }
     
-    /// Ejecuta una escena
-    fn ejecutar_escena(&mut self, escena: &Escena) -> Result<(), String> {
-        for sentencia in &escena.cuerpo {
+    /// Determina la escena de entrada: la fijada desde la CLI, la marcada
+    /// con `@inicial` o, en su defecto, la primera del archivo
+    fn escena_de_entrada(&self, programa: &Programa) -> Result<Option<String>, Diagnostico> {
+        if let Some(nombre) = &self.escena_inicial {
+            if !self.escenas.contains_key(nombre) {
+                return Err(Diagnostico::sin_posicion(
+                    Codigo::EscenaNoDefinida,
+                    format!("La escena inicial '{}' no existe", nombre),
+                ));
+            }
+            return Ok(Some(nombre.clone()));
+        }
+        
+        let escenas: Vec<&Escena> = programa
+            .declaraciones
+            .iter()
+            .filter_map(|d| match d {
+                Declaracion::Escena(escena) => Some(escena),
+                _ => None,
+            })
+            .collect();
+        
+        let marcadas: Vec<&&Escena> = escenas.iter().filter(|e| e.inicial).collect();
+        if marcadas.len() > 1 {
+            return Err(Diagnostico::new(
+                Codigo::EscenaNoDefinida,
+                format!(
+                    "Solo una escena puede ser '@inicial': '{}' y '{}'",
+                    marcadas[0].nombre, marcadas[1].nombre
+                ),
+                &marcadas[1].pos,
+            )
+            .con_etiqueta(&marcadas[0].pos, "primera escena '@inicial'"));
+        }
+        
+        Ok(marcadas
+            .first()
+            .map(|e| e.nombre.clone())
+            .or_else(|| escenas.first().map(|e| e.nombre.clone())))
+    }
+    
+    /// Ejecuta una escena: cuerpo, `al_iniciar`, arranque de sus máquinas,
+    /// el bucle de frames mientras haya trabajo programado y finalmente
+    /// `al_salir`. Cada escena tiene su propio entorno, reloj,
+    /// temporizadores, oyentes y máquinas, que se descartan al salir. Tras
+    /// un `cambiar_escena` solo se ejecutan `al_iniciar` y `al_salir`.
+    fn ejecutar_escena(&mut self, escena: &Escena) -> Result<(), Diagnostico> {
+        self.entorno = Entorno::new();
+        self.reloj = Reloj::new();
+        
+        self.ejecutar_hasta_cambio(&escena.cuerpo)?;
+        
+        // Se ejecuta siempre, para que `al_salir` no limpie algo que no se
+        // llegó a preparar
+        self.aunque_cambie_escena(|ejecutor| ejecutor.ejecutar_hasta_cambio(&escena.al_iniciar))?;
+        
+        for maquina in &escena.maquinas {
+            if self.cambiando_escena() {
+                break;
+            }
+            let instancia = InstanciaMaquina::new(maquina.clone());
+            let al_entrar = instancia.estado().al_entrar.clone();
+            self.maquinas.push(instancia);
+            self.ejecutar_bloque_aislado(&al_entrar)?;
+        }
+        self.entregar_eventos()?;
+        
+        while self.escena_siguiente.is_none()
+            && (self.planificador.hay_pendientes() || self.maquinas.iter().any(|m| m.activa()))
+            && self.reloj.frame < self.frames_maximos
+        {
+            self.reloj.frame += 1;
+            self.ejecutar_frame()?;
+        }
+        
+        self.aunque_cambie_escena(|ejecutor| {
+            // Las máquinas abandonan su estado antes de que salga la escena
+            for indice in 0..ejecutor.maquinas.len() {
+                let al_salir = ejecutor.maquinas[indice].estado().al_salir.clone();
+                ejecutor.ejecutar_bloque_aislado(&al_salir)?;
+            }
+            ejecutor.ejecutar_hasta_cambio(&escena.al_salir)
+        })?;
+        
+        self.planificador.limpiar();
+        self.eventos.limpiar();
+        self.maquinas.clear();
+        self.entorno = Entorno::new();
+        Ok(())
+    }
+    
+    /// Ejecuta sentencias de la escena hasta que una pide `cambiar_escena`
+    fn ejecutar_hasta_cambio(&mut self, sentencias: &[Sentencia]) -> Result<(), Diagnostico> {
+        for sentencia in sentencias {
+            if self.cambiando_escena() {
+                break;
+            }
             self.ejecutar_sentencia(sentencia)?;
         }
         Ok(())
     }
     
+    /// Ejecuta `hook` entero aunque haya un `cambiar_escena` pendiente; si
+    /// el hook pide otro cambio, vale el primero
+    fn aunque_cambie_escena(
+        &mut self,
+        hook: impl FnOnce(&mut Self) -> Result<(), Diagnostico>,
+    ) -> Result<(), Diagnostico> {
+        let siguiente = self.escena_siguiente.take();
+        let resultado = hook(self);
+        self.escena_siguiente = siguiente.or(self.escena_siguiente.take());
+        resultado
+    }
+    
+    /// Ejecuta un frame: primero los temporizadores vencidos en orden de
+    /// vencimiento, después las corrutinas en orden de creación, luego las
+    /// máquinas de estados en orden de declaración y al final los eventos
+    /// emitidos. Lo programado durante el frame espera al siguiente.
+    fn ejecutar_frame(&mut self) -> Result<(), Diagnostico> {
+        for id in self.planificador.vencidos(self.reloj.tiempo()) {
+            if self.cambiando_escena() {
+                return Ok(());
+            }
+            // Un temporizador anterior del mismo frame pudo cancelarlo
+            if let Some(cuerpo) = self.planificador.disparar(id, &self.reloj) {
+                self.ejecutar_bloque_aislado(&cuerpo)?;
+            }
+        }
+        
+        for id in self.planificador.corrutinas() {
+            if self.cambiando_escena() {
+                return Ok(());
+            }
+            if let Some(corrutina) = self.planificador.tomar(id) {
+                self.continuar_corrutina(corrutina)?;
+            }
+        }
+        
+        for indice in 0..self.maquinas.len() {
+            if self.cambiando_escena() {
+                return Ok(());
+            }
+            self.actualizar_maquina(indice)?;
+        }
+        
+        self.entregar_eventos()
+    }
+    
+    /// Aplica la primera transición del estado actual cuya condición se
+    /// cumple y ejecuta el `cada frame` del estado resultante
+    fn actualizar_maquina(&mut self, indice: usize) -> Result<(), Diagnostico> {
+        let estado = self.maquinas[indice].estado().clone();
+        
+        for transicion in &estado.transiciones {
+            if !self.evaluar_expresion(&transicion.condicion)?.es_verdadero() {
+                continue;
+            }
+            
+            self.ejecutar_bloque_aislado(&estado.al_salir)?;
+            
+            let maquina = &mut self.maquinas[indice];
+            maquina.actual = maquina.indice_de(&transicion.destino).ok_or_else(|| {
+                Diagnostico::new(
+                    Codigo::ReferenciaDesconocida,
+                    format!("Estado '{}' no definido", transicion.destino),
+                    &transicion.pos,
+                )
+            })?;
+            let registro = RegistroTransicion {
+                maquina: maquina.definicion.nombre.clone(),
+                desde: estado.nombre.clone(),
+                hacia: transicion.destino.clone(),
+                frame: self.reloj.frame,
+                tiempo: self.reloj.tiempo(),
+            };
+            if self.traza_maquinas {
+                println!("🔁 {}", registro);
+            }
+            self.transiciones.push(registro);
+            
+            let al_entrar = self.maquinas[indice].estado().al_entrar.clone();
+            self.ejecutar_bloque_aislado(&al_entrar)?;
+            break;
+        }
+        
+        let cada_frame = self.maquinas[indice].estado().cada_frame.clone();
+        self.ejecutar_bloque_aislado(&cada_frame)?;
+        Ok(())
+    }
+    
+    /// Entrega los eventos pendientes a sus oyentes (ver `eventos.rs`)
+    fn entregar_eventos(&mut self) -> Result<(), Diagnostico> {
+        let mut entregados = 0;
+        while let Some(evento) = self.eventos.siguiente() {
+            if self.cambiando_escena() {
+                break;
+            }
+            entregados += 1;
+            if entregados > MAX_EVENTOS_POR_FRAME {
+                return Err(Diagnostico::new(
+                    Codigo::BucleDeEventos,
+                    format!(
+                        "Más de {} eventos en un frame; '{}' parece reemitirse en bucle",
+                        MAX_EVENTOS_POR_FRAME, evento.nombre
+                    ),
+                    &evento.pos,
+                ));
+            }
+            
+            for oyente in self.eventos.oyentes_de(&evento.nombre) {
+                if self.cambiando_escena() {
+                    break;
+                }
+                if oyente.aridad() != evento.datos.len() {
+                    return Err(Diagnostico::new(
+                        Codigo::ArgumentosIncorrectos,
+                        format!(
+                            "El evento '{}' lleva {} datos, pero el oyente en {} espera {}",
+                            evento.nombre,
+                            evento.datos.len(),
+                            oyente.pos,
+                            oyente.aridad()
+                        ),
+                        &evento.pos,
+                    )
+                    .con_etiqueta(&oyente.pos, "oyente registrado aquí"));
+                }
+                
+                match oyente.manejador {
+                    Manejador::Bloque(parametros, cuerpo) => {
+                        let parametros = parametros.into_iter().zip(evento.datos.iter().cloned());
+                        self.entorno.abrir_ambito(parametros.collect());
+                        // Un `retornar` termina el manejador
+                        let resultado = self.ejecutar_bloque(&cuerpo);
+                        self.entorno.cerrar_ambito();
+                        resultado?;
+                    }
+                    Manejador::Funcion(funcion) => {
+                        self.invocar(&funcion, evento.datos.clone(), &evento.pos)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    /// Ejecuta un bloque en un ámbito hijo del entorno actual; los cambios a
+    /// variables exteriores se conservan al cerrarlo
+    fn ejecutar_bloque_aislado(&mut self, cuerpo: &[Sentencia]) -> Result<Flujo, Diagnostico> {
+        self.entorno.abrir_ambito(HashMap::new());
+        let resultado = self.ejecutar_bloque(cuerpo);
+        self.entorno.cerrar_ambito();
+        resultado
+    }
+    
+    /// Inicia una corrutina: se ejecuta de inmediato hasta su primer
+    /// `esperar` y el resto continúa en frames posteriores
+    fn iniciar_corrutina(
+        &mut self,
+        nombre: &str,
+        locales: HashMap<String, Valor>,
+        cuerpo: Vec<Sentencia>,
+    ) -> Result<Valor, Diagnostico> {
+        let mut corrutina = self.planificador.nueva_corrutina(nombre, locales, cuerpo);
+        let id = corrutina.id;
+        self.planificador.marcar_en_curso(id);
+        
+        // Si falla se descarta: solo se conserva si quedó suspendida
+        let resultado = self.reanudar(&mut corrutina);
+        self.planificador.devolver(corrutina, !matches!(resultado, Ok(false)));
+        resultado?;
+        
+        Ok(Valor::Corrutina(id))
+    }
+    
+    /// Reanuda una corrutina suspendida si ya se cumple su espera. Una
+    /// corrutina nunca se reanuda en el mismo frame en que se suspendió.
+    fn continuar_corrutina(&mut self, mut corrutina: Corrutina) -> Result<(), Diagnostico> {
+        let despierta = if self.reloj.frame <= corrutina.frame_suspension {
+            Ok(false)
+        } else {
+            match corrutina.despertar.clone() {
+                None => Ok(true),
+                Some(Despertar::Tiempo(instante)) => Ok(vencido(instante, self.reloj.tiempo())),
+                Some(Despertar::Condicion(condicion)) => {
+                    self.entorno.abrir_ambito(std::mem::take(&mut corrutina.locales));
+                    let valor = self.evaluar_expresion(&condicion);
+                    corrutina.locales = self.entorno.cerrar_ambito();
+                    valor.map(|v| v.es_verdadero())
+                }
+            }
+        };
+        
+        let resultado = match despierta {
+            Ok(true) => self.reanudar(&mut corrutina),
+            Ok(false) => Ok(false),
+            Err(e) => Err(e),
+        };
+        
+        self.planificador.devolver(corrutina, !matches!(resultado, Ok(false)));
+        resultado.map(|_| ())
+    }
+    
+    /// Ejecuta una corrutina en su propio ámbito, hijo del entorno actual,
+    /// hasta el siguiente `esperar`. Devuelve si terminó.
+    fn reanudar(&mut self, corrutina: &mut Corrutina) -> Result<bool, Diagnostico> {
+        corrutina.despertar = None;
+        self.entorno.abrir_ambito(std::mem::take(&mut corrutina.locales));
+        let resultado = self.avanzar_corrutina(corrutina);
+        corrutina.locales = self.entorno.cerrar_ambito();
+        resultado.map_err(|e| e.con_nota(format!("en corrutina '{}'", corrutina.nombre)))
+    }
+    
+    /// Recorre la pila de marcos de la corrutina. Las sentencias de control
+    /// se descomponen en marcos para poder suspender dentro de ellas; el
+    /// resto se ejecuta de forma normal.
+    fn avanzar_corrutina(&mut self, corrutina: &mut Corrutina) -> Result<bool, Diagnostico> {
+        while let Some(marco) = corrutina.pila.last_mut() {
+            // Se queda suspendida; la escena la descarta al salir
+            if self.cambiando_escena() {
+                return Ok(false);
+            }
+            let sentencia = match marco {
+                Marco::Bloque(sentencias, indice) => {
+                    let sentencia = sentencias.get(*indice).cloned();
+                    *indice += 1;
+                    match sentencia {
+                        Some(sentencia) => sentencia,
+                        None => {
+                            corrutina.pila.pop();
+                            continue;
+                        }
+                    }
+                }
+                Marco::Mientras(condicion, cuerpo, _) => {
+                    let cuerpo = cuerpo.clone();
+                    let condicion = condicion.clone();
+                    if self.evaluar_expresion(&condicion)?.es_verdadero() {
+                        corrutina.pila.push(Marco::Bloque(cuerpo, 0));
+                    } else {
+                        corrutina.pila.pop();
+                    }
+                    continue;
+                }
+                Marco::Para(variable, recorrido, cuerpo, _, _) => {
+                    if let Some(valor) = recorrido.next() {
+                        self.entorno.definir(variable.clone(), valor);
+                        let cuerpo = cuerpo.clone();
+                        corrutina.pila.push(Marco::Bloque(cuerpo, 0));
+                    } else {
+                        self.desapilar(&mut corrutina.pila);
+                    }
+                    continue;
+                }
+                Marco::Caso(_) => {
+                    self.desapilar(&mut corrutina.pila);
+                    continue;
+                }
+            };
+            
+            match sentencia {
+                Sentencia::Esperar(espera, _) => {
+                    let despertar = match espera {
+                        Espera::Segundos(expr) => {
+                            let segundos = self.evaluar_intervalo(&expr, "esperar")?;
+                            if segundos < 0.0 {
+                                return Err(Diagnostico::new(
+                                    Codigo::IntervaloInvalido,
+                                    format!(
+                                        "Intervalo inválido para 'esperar': {} segundos, no puede ser negativo",
+                                        segundos
+                                    ),
+                                    expr.posicion(),
+                                ));
+                            }
+                            Despertar::Tiempo(self.reloj.tiempo() + segundos)
+                        }
+                        Espera::Hasta(condicion) => Despertar::Condicion(condicion),
+                    };
+                    corrutina.despertar = Some(despertar);
+                    corrutina.frame_suspension = self.reloj.frame;
+                    return Ok(false);
+                }
+                
+                Sentencia::Si(ramas, sino, _) => {
+                    let mut elegido = sino;
+                    for rama in ramas {
+                        if self.evaluar_expresion(&rama.condicion)?.es_verdadero() {
+                            elegido = Some(rama.cuerpo);
+                            break;
+                        }
+                    }
+                    if let Some(bloque) = elegido {
+                        corrutina.pila.push(Marco::Bloque(bloque, 0));
+                    }
+                }
+                
+                Sentencia::Mientras(condicion, cuerpo, etiqueta, _) => {
+                    corrutina.pila.push(Marco::Mientras(condicion, cuerpo, etiqueta));
+                }
+                
+                Sentencia::Segun(valor, casos, pos) => {
+                    let valor = self.evaluar_expresion(&valor)?;
+                    let (cuerpo, ligaduras) = self.elegir_caso(&valor, &casos, &pos)?;
+                    // Como la variable de un `para`, tapan a las del mismo nombre
+                    let anteriores = ligaduras
+                        .into_iter()
+                        .map(|(nombre, valor)| {
+                            let anterior = self.entorno.quitar(&nombre);
+                            self.entorno.definir(nombre.clone(), valor);
+                            (nombre, anterior)
+                        })
+                        .collect();
+                    corrutina.pila.push(Marco::Caso(anteriores));
+                    corrutina.pila.push(Marco::Bloque(cuerpo.clone(), 0));
+                }
+                
+                Sentencia::Para(variable, iteracion, cuerpo, etiqueta, _) => {
+                    let recorrido = self.evaluar_iteracion(&iteracion)?;
+                    // La variable tapa a la del mismo nombre mientras dura el bucle
+                    let anterior = self.entorno.quitar(&variable);
+                    corrutina.pila.push(Marco::Para(variable, recorrido, cuerpo, etiqueta, anterior));
+                }
+                
+                Sentencia::Romper(salto, _) => self.saltar(&mut corrutina.pila, &salto, true),
+                
+                Sentencia::Continuar(salto, _) => self.saltar(&mut corrutina.pila, &salto, false),
+                
+                // Un `intentar` se ejecuta entero, pero puede salir del
+                // bucle que lo rodea
+                otra => match self.ejecutar_sentencia(&otra)? {
+                    Flujo::Retornar(_) => return Ok(true), // `retornar` termina la corrutina
+                    Flujo::Romper(salto) => self.saltar(&mut corrutina.pila, &salto, true),
+                    Flujo::Continuar(salto) => self.saltar(&mut corrutina.pila, &salto, false),
+                    Flujo::Normal => {}
+                },
+            }
+        }
+        
+        Ok(true)
+    }
+    
+    /// Desapila los marcos de una corrutina hasta el bucle al que se dirige
+    /// un `romper` (que también lo desapila) o un `continuar` (que lo deja
+    /// para su siguiente vuelta)
+    fn saltar(&mut self, pila: &mut Vec<Marco>, salto: &Option<String>, romper: bool) {
+        while let Some(marco) = pila.last() {
+            let etiqueta = match marco {
+                Marco::Mientras(_, _, etiqueta) | Marco::Para(_, _, _, etiqueta, _) => Some(etiqueta),
+                Marco::Bloque(..) | Marco::Caso(_) => None,
+            };
+            if etiqueta.is_some_and(|etiqueta| Flujo::es_para(salto, etiqueta)) {
+                if romper {
+                    self.desapilar(pila);
+                }
+                return;
+            }
+            self.desapilar(pila);
+        }
+    }
+    
+    /// Quita el último marco de una corrutina. Al salir de un `para` o de
+    /// un caso de `segun`, sus variables recuperan el valor que tenían antes.
+    fn desapilar(&mut self, pila: &mut Vec<Marco>) {
+        let anteriores = match pila.pop() {
+            Some(Marco::Para(variable, _, _, _, anterior)) => vec![(variable, anterior)],
+            Some(Marco::Caso(anteriores)) => anteriores,
+            _ => return,
+        };
+        for (variable, anterior) in anteriores {
+            match anterior {
+                Some(valor) => self.entorno.definir(variable, valor),
+                None => {
+                    self.entorno.quitar(&variable);
+                }
+            }
+        }
+    }
+    
+    /// Ejecuta las sentencias de un bloque hasta que una sale de él
+    fn ejecutar_bloque(&mut self, sentencias: &[Sentencia]) -> Result<Flujo, Diagnostico> {
+        for sentencia in sentencias {
+            let flujo = self.ejecutar_sentencia(sentencia)?;
+            if flujo != Flujo::Normal || self.cambiando_escena() {
+                return Ok(flujo);
+            }
+        }
+        Ok(Flujo::Normal)
+    }
+    
+    /// Si hay un `cambiar_escena` pendiente: lo que queda de la escena no
+    /// se ejecuta, salvo `al_iniciar` y `al_salir`
+    fn cambiando_escena(&self) -> bool {
+        self.escena_siguiente.is_some()
+    }
+    
     /// Ejecuta una sentencia
-    fn ejecutar_sentencia(&mut self, sentencia: &Sentencia) -> Result<Option<Valor>, String> {
+    fn ejecutar_sentencia(&mut self, sentencia: &Sentencia) -> Result<Flujo, Diagnostico> {
         match sentencia {
-            Sentencia::Mostrar(expr, pos) => {
+            Sentencia::Mostrar(expr, _) => {
                 let valor = self.evaluar_expresion(expr)?;
                 let texto = format!("📺 {}", valor);
                 self.salida.push(texto.clone());
                 println!("{}", texto);
-                Ok(None)
+                Ok(Flujo::Normal)
             }
             
-            Sentencia::Var(nombre, expr, pos) => {
-                let valor = self.evaluar_expresion(expr)?;
+            Sentencia::Var(nombre, expr, _) | Sentencia::Constante(nombre, expr, _, _) => {
+                let valor = match (expr, self.evaluar_expresion(expr)?) {
+                    // La función anónima toma el nombre de la variable, que
+                    // la muestra y le permite llamarse a sí misma
+                    (Expresion::FuncionAnonima(..), Valor::Funcion(funcion)) => Valor::Funcion(Rc::new(FuncionDefinida {
+                        nombre: Some(nombre.clone()),
+                        ..(*funcion).clone()
+                    })),
+                    (_, valor) => valor,
+                };
                 self.entorno.definir(nombre.clone(), valor);
-                Ok(None)
+                Ok(Flujo::Normal)
             }
             
             Sentencia::Asignacion(nombre, expr, pos) => {
                 let valor = self.evaluar_expresion(expr)?;
                 self.entorno.asignar(nombre, valor).map_err(|e| {
-                    format!("{} en {}", e, pos)
+                    Diagnostico::new(Codigo::VariableNoDefinida, e, pos)
+                        .con_sugerencia(format!("declárala antes con 'var {} = ...'", nombre))
                 })?;
-                Ok(None)
+                Ok(Flujo::Normal)
+            }
+            
+            Sentencia::AsignacionIndice(lista, indice, valor, _) => {
+                let valor = self.evaluar_expresion(valor)?;
+                self.asignar_elemento(lista, indice, valor)?;
+                Ok(Flujo::Normal)
+            }
+            
+            Sentencia::AsignacionCampo(registro, campo, valor, pos) => {
+                let valor = self.evaluar_expresion(valor)?;
+                self.asignar_campo(registro, campo, valor, pos)?;
+                Ok(Flujo::Normal)
+            }
+            
+            Sentencia::Si(ramas, sino, _) => {
+                // La primera rama cuya condición se cumple, o el `sino`
+                let mut elegido = sino.as_ref();
+                for rama in ramas {
+                    if self.evaluar_expresion(&rama.condicion)?.es_verdadero() {
+                        elegido = Some(&rama.cuerpo);
+                        break;
+                    }
+                }
+                
+                match elegido {
+                    Some(bloque) => self.ejecutar_bloque(bloque),
+                    None => Ok(Flujo::Normal),
+                }
+            }
+            
+            Sentencia::Mientras(condicion, cuerpo, etiqueta, _) => {
+                while !self.cambiando_escena() && self.evaluar_expresion(condicion)?.es_verdadero() {
+                    match self.ejecutar_bloque(cuerpo)? {
+                        Flujo::Romper(salto) if Flujo::es_para(&salto, etiqueta) => break,
+                        Flujo::Continuar(salto) if Flujo::es_para(&salto, etiqueta) => continue,
+                        Flujo::Normal => {}
+                        otro => return Ok(otro),
+                    }
+                }
+                Ok(Flujo::Normal)
+            }
+            
+            Sentencia::Para(variable, iteracion, cuerpo, etiqueta, _) => {
+                let recorrido = self.evaluar_iteracion(iteracion)?;
+                
+                // La variable del bucle solo existe dentro de él
+                self.entorno.abrir_ambito(HashMap::new());
+                let resultado = self.ejecutar_para(variable, recorrido, cuerpo, etiqueta);
+                self.entorno.cerrar_ambito();
+                resultado
+            }
+            
+            Sentencia::Segun(valor, casos, pos) => {
+                let valor = self.evaluar_expresion(valor)?;
+                let (cuerpo, ligaduras) = self.elegir_caso(&valor, casos, pos)?;
+                
+                // Los valores que nombra el patrón solo existen en su caso
+                self.entorno.abrir_ambito(ligaduras);
+                let resultado = self.ejecutar_bloque(cuerpo);
+                self.entorno.cerrar_ambito();
+                resultado
+            }
+            
+            Sentencia::Romper(etiqueta, _) => Ok(Flujo::Romper(etiqueta.clone())),
+            
+            Sentencia::Continuar(etiqueta, _) => Ok(Flujo::Continuar(etiqueta.clone())),
+            
+            Sentencia::Retornar(valor_opt, _) => {
+                let valor = if let Some(expr) = valor_opt {
+                    self.evaluar_expresion(expr)?
+                } else {
+                    Valor::Nulo
+                };
+                Ok(Flujo::Retornar(valor))
+            }
+            
+            Sentencia::Esperar(_, pos) => Err(Diagnostico::new(
+                Codigo::FueraDeContexto,
+                "'esperar' solo puede usarse dentro de una corrutina",
+                pos,
+            )),
+            
+            Sentencia::Emitir(nombre, argumentos, pos) => {
+                let mut datos = Vec::new();
+                for arg in argumentos {
+                    datos.push(self.evaluar_expresion(arg)?);
+                }
+                self.eventos.emitir(Evento {
+                    nombre: nombre.clone(),
+                    datos,
+                    pos: pos.clone(),
+                });
+                Ok(Flujo::Normal)
             }
             
-            Sentencia::Si(condicion, entonces, sino, pos) => {
-                let valor_condicion = self.evaluar_expresion(condicion)?;
-                
-                if valor_condicion.es_verdadero() {
-                    for sentencia in entonces {
-                        if let Some(retorno) = self.ejecutar_sentencia(sentencia)? {
-                            return Ok(Some(retorno));
-                        }
-                    }
-                } else if let Some(bloque_sino) = sino {
-                    for sentencia in bloque_sino {
-                        if let Some(retorno) = self.ejecutar_sentencia(sentencia)? {
-                            return Ok(Some(retorno));
-                        }
-                    }
-                }
-                
-                Ok(None)
+            Sentencia::Escuchar(evento, parametros, cuerpo, pos) => {
+                self.eventos.escuchar(Oyente {
+                    evento: evento.clone(),
+                    manejador: Manejador::Bloque(parametros.clone(), cuerpo.clone()),
+                    pos: pos.clone(),
+                });
+                Ok(Flujo::Normal)
             }
             
-            Sentencia::Mientras(condicion, cuerpo, pos) => {
-                while self.evaluar_expresion(condicion)?.es_verdadero() {
-                    for sentencia in cuerpo {
-                        if let Some(retorno) = self.ejecutar_sentencia(sentencia)? {
-                            return Ok(Some(retorno));
-                        }
-                    }
-                }
-                Ok(None)
+            Sentencia::EscucharFuncion(evento, manejador, pos) => {
+                let funcion = self.funcion_de(manejador, "escuchar")?;
+                self.eventos.escuchar(Oyente {
+                    evento: evento.clone(),
+                    manejador: Manejador::Funcion(funcion),
+                    pos: pos.clone(),
+                });
+                Ok(Flujo::Normal)
             }
             
-            Sentencia::Para(variable, inicio, fin, cuerpo, pos) => {
-                let valor_inicio = self.evaluar_expresion(inicio)?.a_numero().map_err(|e| {
-                    format!("Inicio de 'para' debe ser número: {} en {}", e, pos)
-                })?;
+            Sentencia::Intentar(cuerpo, captura, finalmente, _) => {
+                let mut resultado = self.ejecutar_bloque(cuerpo);
                 
-                let valor_fin = self.evaluar_expresion(fin)?.a_numero().map_err(|e| {
-                    format!("Fin de 'para' debe ser número: {} en {}", e, pos)
-                })?;
+                if let (Err(error), Some(captura)) = (&resultado, captura) {
+                    let valor = self.valor_de_error(error);
+                    let variables = captura.variable.iter().map(|v| (v.clone(), valor.clone())).collect();
+                    self.entorno.abrir_ambito(variables);
+                    resultado = self.ejecutar_bloque(&captura.cuerpo);
+                    self.entorno.cerrar_ambito();
+                }
                 
-                for i in valor_inicio..valor_fin {
-                    self.entorno.definir(variable.clone(), Valor::Numero(i));
-                    
-                    for sentencia in cuerpo {
-                        if let Some(retorno) = self.ejecutar_sentencia(sentencia)? {
-                            return Ok(Some(retorno));
-                        }
+                // `finalmente` se ejecuta siempre; si sale con un error o
+                // un salto propio, sustituye al del bloque
+                if let Some(finalmente) = finalmente {
+                    let lanzado = self.lanzado.take();
+                    match self.ejecutar_bloque(finalmente)? {
+                        Flujo::Normal => self.lanzado = lanzado,
+                        otro => return Ok(otro),
                     }
                 }
-                
-                Ok(None)
+                resultado
             }
             
-            Sentencia::Retornar(valor_opt, pos) => {
-                let valor = if let Some(expr) = valor_opt {
-                    self.evaluar_expresion(expr)?
-                } else {
-                    Valor::Nulo
+            Sentencia::Lanzar(valor, pos) => {
+                let valor = self.evaluar_expresion(valor)?;
+                let mensaje = match &valor {
+                    // Un error capturado que se vuelve a lanzar
+                    Valor::Estructura(nombre, campos) if nombre == "Error" => campos
+                        .iter()
+                        .find(|(campo, _)| campo == "mensaje")
+                        .map(|(_, mensaje)| mensaje.to_string())
+                        .unwrap_or_else(|| valor.to_string()),
+                    otro => otro.to_string(),
                 };
-                Ok(Some(valor))
+                self.lanzado = Some(valor);
+                Err(Diagnostico::new(
+                    Codigo::ErrorNoCapturado,
+                    format!("Error no capturado: {}", mensaje),
+                    pos,
+                )
+                .con_sugerencia("captúralo con 'intentar { ... } capturar e { ... }'"))
             }
             
-            Sentencia::Expresion(expr) => {
+            Sentencia::Expresion(expr, _) => {
                 self.evaluar_expresion(expr)?;
-                Ok(None)
+                Ok(Flujo::Normal)
+            }
+        }
+    }
+    
+    /// Valor que recibe un `capturar`: el de `lanzar`, o para un fallo del
+    /// runtime un `Error { mensaje, codigo, linea, columna }`
+    fn valor_de_error(&mut self, error: &Diagnostico) -> Valor {
+        let lanzado = self.lanzado.take();
+        if let (Codigo::ErrorNoCapturado, Some(valor)) = (error.codigo, lanzado) {
+            return valor;
+        }
+        let (linea, columna) = error.pos.as_ref().map_or((0, 0), |pos| (pos.linea, pos.columna));
+        Valor::Estructura(
+            "Error".to_string(),
+            vec![
+                ("mensaje".to_string(), Valor::Texto(error.mensaje.clone())),
+                ("codigo".to_string(), Valor::Texto(error.codigo.texto().to_string())),
+                ("linea".to_string(), Valor::Numero(linea as i64)),
+                ("columna".to_string(), Valor::Numero(columna as i64)),
+            ],
+        )
+    }
+    
+    /// Ejecuta las vueltas de un bucle `para` en el ámbito ya abierto
+    fn ejecutar_para(
+        &mut self,
+        variable: &str,
+        recorrido: Recorrido,
+        cuerpo: &[Sentencia],
+        etiqueta: &Option<String>,
+    ) -> Result<Flujo, Diagnostico> {
+        for valor in recorrido {
+            if self.cambiando_escena() {
+                break;
+            }
+            self.entorno.definir(variable.to_string(), valor);
+            
+            match self.ejecutar_bloque(cuerpo)? {
+                Flujo::Romper(salto) if Flujo::es_para(&salto, etiqueta) => break,
+                Flujo::Continuar(salto) if Flujo::es_para(&salto, etiqueta) => continue,
+                Flujo::Normal => {}
+                otro => return Ok(otro),
+            }
+        }
+        Ok(Flujo::Normal)
+    }
+    
+    /// Evalúa la cabecera de un bucle `para`: los límites y el paso de un
+    /// rango o la colección a recorrer
+    fn evaluar_iteracion(&mut self, iteracion: &Iteracion) -> Result<Recorrido, Diagnostico> {
+        let (inicio, fin, inclusivo, expr_paso) = match iteracion {
+            Iteracion::Coleccion(expr) => {
+                return match self.evaluar_expresion(expr)? {
+                    Valor::Texto(texto) => {
+                        let letras: Vec<Valor> = texto.chars().map(|c| Valor::Texto(c.to_string())).collect();
+                        Ok(Recorrido::Elementos(letras.into_iter()))
+                    }
+                    // Cambiar la lista dentro del bucle no altera las vueltas
+                    Valor::Lista(lista) => Ok(Recorrido::Elementos(lista.borrow().clone().into_iter())),
+                    Valor::Mapa(mapa) => Ok(Recorrido::Elementos(mapa.borrow().claves().into_iter())),
+                    otro => Err(Diagnostico::new(
+                        Codigo::TiposIncompatibles,
+                        format!("No se puede recorrer {} con 'para ... en'", otro),
+                        expr.posicion(),
+                    )
+                    .con_nota("solo se pueden recorrer textos, listas y mapas")),
+                };
+            }
+            Iteracion::Rango { inicio, fin, inclusivo, paso } => (inicio, fin, *inclusivo, paso.as_ref()),
+        };
+        
+        let limite = |ejecutor: &mut Self, expr: &Expresion, nombre: &str| -> Result<Valor, Diagnostico> {
+            let valor = ejecutor.evaluar_expresion(expr)?;
+            if valor.como_medida().is_none() {
+                return Err(Diagnostico::new(
+                    Codigo::TiposIncompatibles,
+                    format!("{} de 'para' debe ser número: {}", nombre, valor),
+                    expr.posicion(),
+                ));
+            }
+            Ok(valor)
+        };
+        let valor_inicio = limite(self, inicio, "Inicio")?;
+        let valor_fin = limite(self, fin, "Fin")?;
+        let valor_paso = match expr_paso {
+            Some(expr) => Some(limite(self, expr, "Paso")?),
+            None => None,
+        };
+        
+        let cero = expr_paso.unwrap_or(fin).posicion();
+        let paso_nulo = || {
+            Diagnostico::new(Codigo::IntervaloInvalido, "Paso inválido para 'para': no puede ser cero", cero)
+                .con_sugerencia("usa un paso negativo para contar hacia atrás")
+        };
+        
+        // Con todos los valores enteros se cuenta sin redondeos
+        if let (Valor::Numero(desde), Valor::Numero(hasta)) = (&valor_inicio, &valor_fin) {
+            let paso = match valor_paso {
+                None => 1,
+                Some(Valor::Numero(0)) => return Err(paso_nulo()),
+                Some(Valor::Numero(n)) => n,
+                Some(_) => 0,
+            };
+            if paso != 0 {
+                return Ok(Recorrido::Enteros { siguiente: Some(*desde), fin: *hasta, paso, inclusivo });
+            }
+        }
+        
+        let (desde, dimension) = valor_inicio.como_medida().unwrap_or_default();
+        let (hasta, dimension_fin) = valor_fin.como_medida().unwrap_or_default();
+        let (paso, dimension_paso) = match &valor_paso {
+            Some(valor) => valor.como_medida().unwrap_or_default(),
+            None => (1.0, dimension),
+        };
+        for (dimension_otra, expr) in [(dimension_fin, fin), (dimension_paso, expr_paso.unwrap_or(fin))] {
+            if dimension_otra != dimension {
+                return Err(Diagnostico::new(
+                    Codigo::TiposIncompatibles,
+                    format!(
+                        "Unidades incompatibles en 'para': empieza en {} y recibe {}",
+                        dimension.describir(),
+                        dimension_otra.describir()
+                    ),
+                    expr.posicion(),
+                )
+                .con_etiqueta(inicio.posicion(), dimension.describir()));
             }
         }
+        if paso == 0.0 {
+            return Err(paso_nulo());
+        }
+        
+        Ok(Recorrido::Decimales { inicio: desde, vuelta: 0, fin: hasta, paso, inclusivo, dimension })
     }
     
     /// Evalúa una expresión
-    fn evaluar_expresion(&mut self, expr: &Expresion) -> Result<Valor, String> {
+    fn evaluar_expresion(&mut self, expr: &Expresion) -> Result<Valor, Diagnostico> {
         match expr {
             Expresion::Numero(n, _) => Ok(Valor::Numero(*n)),
             
             Expresion::Decimal(d, _) => Ok(Valor::Decimal(*d)),
             
+            Expresion::Medida(valor, dimension, _) => Ok(Valor::Medida(*valor, *dimension)),
+            
             Expresion::Texto(s, _) => Ok(Valor::Texto(s.clone())),
             
+            Expresion::Interpolacion(fragmentos, _) => {
+                let mut texto = String::new();
+                for fragmento in fragmentos {
+                    match fragmento {
+                        Fragmento::Texto(literal) => texto.push_str(literal),
+                        Fragmento::Expresion(expr) => texto.push_str(&self.evaluar_expresion(expr)?.to_string()),
+                    }
+                }
+                Ok(Valor::Texto(texto))
+            }
+            
             Expresion::Booleano(b, _) => Ok(Valor::Booleano(*b)),
             
-            Expresion::Variable(nombre, pos) => {
-                self.entorno.obtener(nombre).ok_or_else(|| {
-                    format!("Variable '{}' no definida en {}", nombre, pos)
+            Expresion::Lista(elementos, _) => {
+                let mut valores = Vec::with_capacity(elementos.len());
+                for elemento in elementos {
+                    valores.push(self.evaluar_expresion(elemento)?);
+                }
+                Ok(Valor::lista(valores))
+            }
+            
+            Expresion::Mapa(entradas, _) => {
+                let mut mapa = Mapa::default();
+                for (clave, valor) in entradas {
+                    let valor_clave = self.evaluar_expresion(clave)?;
+                    clave_valida(&valor_clave, clave.posicion())?;
+                    let valor = self.evaluar_expresion(valor)?;
+                    mapa.insertar(valor_clave, valor);
+                }
+                Ok(Valor::Mapa(Rc::new(RefCell::new(mapa))))
+            }
+            
+            Expresion::Construccion(nombre, campos, pos) => self.construir(nombre, campos, pos),
+            
+            Expresion::Segun(valor, casos, pos) => {
+                let valor = self.evaluar_expresion(valor)?;
+                let (cuerpo, ligaduras) = self.elegir_caso(&valor, casos, pos)?;
+                self.entorno.abrir_ambito(ligaduras);
+                let resultado = self.evaluar_expresion(cuerpo);
+                self.entorno.cerrar_ambito();
+                resultado
+            }
+            
+            Expresion::Campo(registro, campo, pos) => {
+                let valor = self.evaluar_expresion(registro)?;
+                let (nombre, campos) = self.campos_de(valor, registro)?;
+                campos
+                    .into_iter()
+                    .find(|(nombre, _)| nombre == campo)
+                    .map(|(_, valor)| valor)
+                    .ok_or_else(|| self.campo_desconocido(&nombre, campo, pos))
+            }
+            
+            Expresion::Indice(base, indice, _) => {
+                let valor = self.evaluar_expresion(base)?;
+                let posicion = self.evaluar_expresion(indice)?;
+                match valor {
+                    Valor::Lista(elementos) => {
+                        let elementos = elementos.borrow();
+                        let i = indice_valido(&posicion, elementos.len(), false, "elementos", indice.posicion())?;
+                        Ok(elementos[i].clone())
+                    }
+                    Valor::Texto(texto) => {
+                        let letras: Vec<char> = texto.chars().collect();
+                        let i = indice_valido(&posicion, letras.len(), false, "letras", indice.posicion())?;
+                        Ok(Valor::Texto(letras[i].to_string()))
+                    }
+                    Valor::Mapa(mapa) => mapa
+                        .borrow()
+                        .obtener(&posicion)
+                        .cloned()
+                        .ok_or_else(|| clave_no_encontrada(&posicion, indice.posicion())),
+                    otro => Err(Diagnostico::new(
+                        Codigo::TiposIncompatibles,
+                        format!("No se puede indexar {}", otro),
+                        base.posicion(),
+                    )
+                    .con_nota("solo se pueden indexar listas, textos y mapas")),
+                }
+            }
+            
+            Expresion::Rebanada(base, desde, hasta, pos) => {
+                let valor = self.evaluar_expresion(base)?;
+                let (longitud, unidad) = match &valor {
+                    Valor::Lista(elementos) => (elementos.borrow().len(), "elementos"),
+                    Valor::Texto(texto) => (texto.chars().count(), "letras"),
+                    otro => return Err(Diagnostico::new(
+                        Codigo::TiposIncompatibles,
+                        format!("No se puede rebanar {}", otro),
+                        base.posicion(),
+                    )
+                    .con_nota("solo se pueden rebanar listas y textos")),
+                };
+                
+                let mut extremo = |expr: &Option<Box<Expresion>>, defecto: usize| match expr {
+                    Some(expr) => {
+                        let valor = self.evaluar_expresion(expr)?;
+                        indice_valido(&valor, longitud, true, unidad, expr.posicion())
+                    }
+                    None => Ok(defecto),
+                };
+                let inicio = extremo(desde, 0)?;
+                let fin = extremo(hasta, longitud)?;
+                if inicio > fin {
+                    return Err(Diagnostico::new(
+                        Codigo::IndiceFueraDeRango,
+                        format!("Rebanada inválida: empieza en {} y termina antes, en {}", inicio, fin),
+                        pos,
+                    ));
+                }
+                
+                Ok(match valor {
+                    Valor::Lista(elementos) => Valor::lista(elementos.borrow()[inicio..fin].to_vec()),
+                    Valor::Texto(texto) => Valor::Texto(texto.chars().skip(inicio).take(fin - inicio).collect()),
+                    _ => unreachable!(),
                 })
             }
             
+            Expresion::Variable(nombre, pos) => {
+                if let Some(valor) = self.entorno.obtener(nombre) {
+                    return Ok(valor);
+                }
+                if self.variantes.contains_key(nombre) {
+                    return self.construir_variante(nombre, Vec::new(), pos);
+                }
+                if let Some(funcion) = self.funciones.get(nombre) {
+                    return Ok(Valor::Funcion(funcion.clone()));
+                }
+                Err(Diagnostico::new(
+                    Codigo::VariableNoDefinida,
+                    format!("Variable '{}' no definida", nombre),
+                    pos,
+                ))
+            }
+            
             Expresion::Binaria(izq, op, der, pos) => {
                 let valor_izq = self.evaluar_expresion(izq)?;
                 let valor_der = self.evaluar_expresion(der)?;
                 
-                self.aplicar_operador_binario(valor_izq, op, valor_der, pos)
+                // Con los tramos de cada operando se ve qué lado no encaja
+                self.aplicar_operador_binario(valor_izq, op, valor_der, pos).map_err(|error| {
+                    if error.codigo == Codigo::TiposIncompatibles {
+                        error
+                            .con_etiqueta(izq.posicion(), "operando izquierdo")
+                            .con_etiqueta(der.posicion(), "operando derecho")
+                    } else {
+                        error
+                    }
+                })
             }
             
             Expresion::Unaria(op, expr, pos) => {
@@ -285,9 +1541,14 @@ impl Ejecutor {
                 
                 match op {
                     OperadorUnario::Negacion => match valor {
-                        Valor::Numero(n) => Ok(Valor::Numero(-n)),
+                        Valor::Numero(n) => entero(n.checked_neg(), pos),
                         Valor::Decimal(d) => Ok(Valor::Decimal(-d)),
-                        _ => Err(format!("Operador '-' no aplicable a {} en {}", valor, pos)),
+                        Valor::Medida(valor, dimension) => Ok(Valor::Medida(-valor, dimension)),
+                        _ => Err(Diagnostico::new(
+                            Codigo::TiposIncompatibles,
+                            format!("Operador '-' no aplicable a {}", valor),
+                            pos,
+                        )),
                     },
                     OperadorUnario::No => {
                         Ok(Valor::Booleano(!valor.es_verdadero()))
@@ -298,7 +1559,286 @@ impl Ejecutor {
             Expresion::Llamada(nombre, argumentos, pos) => {
                 self.llamar_funcion(nombre, argumentos, pos)
             }
+            
+            Expresion::LlamadaValor(funcion, argumentos, pos) => {
+                let funcion = self.funcion_de(funcion, "llamar")?;
+                let valores = self.evaluar_argumentos(argumentos)?;
+                self.invocar(&funcion, valores, pos)
+            }
+            
+            Expresion::FuncionAnonima(parametros, _, cuerpo, _) => {
+                Ok(Valor::Funcion(Rc::new(FuncionDefinida {
+                    nombre: None,
+                    parametros: parametros.clone(),
+                    cuerpo: cuerpo.clone(),
+                    es_corrutina: false,
+                    captura: Some(self.entorno.clone()),
+                })))
+            }
+            
+            Expresion::Temporizador(programacion, cuerpo, _) => {
+                self.programar(programacion, cuerpo)
+            }
+        }
+    }
+    
+    /// Construye una estructura con sus campos en el orden de la
+    /// declaración; los valores se evalúan en el orden en que se escriben
+    fn construir(&mut self, nombre: &str, campos: &[(String, Expresion)], pos: &Posicion) -> Result<Valor, Diagnostico> {
+        let Some(estructura) = self.estructuras.get(nombre).cloned() else {
+            return Err(Diagnostico::new(
+                Codigo::TipoDesconocido,
+                format!("Estructura desconocida: '{}'", nombre),
+                pos,
+            ));
+        };
+        
+        let mut dados = Vec::with_capacity(campos.len());
+        for (campo, expr) in campos {
+            if !estructura.campos.iter().any(|c| &c.nombre == campo) {
+                return Err(Diagnostico::new(
+                    Codigo::CamposIncorrectos,
+                    format!("La estructura '{}' no tiene un campo '{}'", nombre, campo),
+                    expr.posicion(),
+                ));
+            }
+            dados.push((campo.clone(), self.evaluar_expresion(expr)?));
+        }
+        
+        let mut valores = Vec::with_capacity(estructura.campos.len());
+        for campo in &estructura.campos {
+            let Some(i) = dados.iter().position(|(dado, _)| *dado == campo.nombre) else {
+                return Err(Diagnostico::new(
+                    Codigo::CamposIncorrectos,
+                    format!("Falta el campo '{}' al construir '{}'", campo.nombre, nombre),
+                    pos,
+                ));
+            };
+            valores.push(dados.swap_remove(i));
+        }
+        Ok(Valor::Estructura(nombre.to_string(), valores))
+    }
+    
+    /// Variante `nombre` de su enumeración con sus valores
+    fn construir_variante(&self, nombre: &str, valores: Vec<Valor>, pos: &Posicion) -> Result<Valor, Diagnostico> {
+        let enumeracion = &self.enumeraciones[&self.variantes[nombre]];
+        let variante = enumeracion.variantes.iter().find(|v| v.nombre == nombre).unwrap();
+        if valores.len() != variante.campos.len() {
+            return Err(Diagnostico::new(
+                Codigo::CamposIncorrectos,
+                format!(
+                    "La variante '{}' lleva {} valores, se dieron {}",
+                    nombre,
+                    variante.campos.len(),
+                    valores.len()
+                ),
+                pos,
+            )
+            .con_etiqueta(&variante.pos, "declarada aquí"));
+        }
+        Ok(Valor::Variante(enumeracion.nombre.clone(), nombre.to_string(), valores))
+    }
+    
+    /// Primer caso de un `segun` cuyo patrón acepta `valor`, con los
+    /// valores que nombra el patrón
+    fn elegir_caso<'c, T>(
+        &mut self,
+        valor: &Valor,
+        casos: &'c [Caso<T>],
+        pos: &Posicion,
+    ) -> Result<(&'c T, HashMap<String, Valor>), Diagnostico> {
+        for caso in casos {
+            let ligaduras = match &caso.patron {
+                Patron::Otro(_) => Some(HashMap::new()),
+                Patron::Literal(literal) => {
+                    let esperado = self.evaluar_expresion(literal)?;
+                    let igual = self.aplicar_operador_binario(valor.clone(), &OperadorBinario::Igual, esperado, literal.posicion())?;
+                    igual.es_verdadero().then(HashMap::new)
+                }
+                Patron::Variante(nombre, nombres, _) => match valor {
+                    Valor::Variante(_, variante, valores) if variante == nombre => Some(
+                        nombres
+                            .iter()
+                            .flatten()
+                            .zip(valores)
+                            .filter(|(nombre, _)| *nombre != "_")
+                            .map(|(nombre, valor)| (nombre.clone(), valor.clone()))
+                            .collect(),
+                    ),
+                    _ => None,
+                },
+            };
+            if let Some(ligaduras) = ligaduras {
+                return Ok((&caso.cuerpo, ligaduras));
+            }
+        }
+        Err(Diagnostico::new(
+            Codigo::SinCaso,
+            format!("Ningún caso de 'segun' coincide con {}", valor),
+            pos,
+        ))
+    }
+    
+    /// Nombre y campos de la estructura `valor`, que es el valor de `expr`
+    fn campos_de(&self, valor: Valor, expr: &Expresion) -> Result<(String, Vec<(String, Valor)>), Diagnostico> {
+        match valor {
+            Valor::Estructura(nombre, campos) => Ok((nombre, campos)),
+            otro => Err(Diagnostico::new(
+                Codigo::TiposIncompatibles,
+                format!("No se puede acceder a un campo de {}", otro),
+                expr.posicion(),
+            )
+            .con_nota("solo las estructuras tienen campos")),
+        }
+    }
+    
+    fn campo_desconocido(&self, estructura: &str, campo: &str, pos: &Posicion) -> Diagnostico {
+        let campos: Vec<&str> = self.estructuras[estructura].campos.iter().map(|c| c.nombre.as_str()).collect();
+        Diagnostico::new(
+            Codigo::CampoDesconocido,
+            format!("La estructura '{}' no tiene un campo '{}'", estructura, campo),
+            pos,
+        )
+        .con_nota(format!("sus campos son: {}", campos.join(", ")))
+    }
+    
+    /// Cambia el campo de la estructura guardada en `registro`. Las
+    /// estructuras son valores, así que la copia cambiada se vuelve a
+    /// guardar donde estaba: en la variable, en el campo de otra estructura
+    /// o en el elemento de una lista o un mapa.
+    fn asignar_campo(
+        &mut self,
+        registro: &Expresion,
+        campo: &str,
+        valor: Valor,
+        pos: &Posicion,
+    ) -> Result<(), Diagnostico> {
+        let actual = self.evaluar_expresion(registro)?;
+        let (nombre, mut campos) = self.campos_de(actual, registro)?;
+        match campos.iter_mut().find(|(nombre, _)| nombre == campo) {
+            Some((_, anterior)) => *anterior = valor,
+            None => return Err(self.campo_desconocido(&nombre, campo, pos)),
+        }
+        let nuevo = Valor::Estructura(nombre, campos);
+        
+        match registro {
+            Expresion::Variable(variable, pos) => self
+                .entorno
+                .asignar(variable, nuevo)
+                .map_err(|e| Diagnostico::new(Codigo::VariableNoDefinida, e, pos)),
+            Expresion::Campo(padre, campo_padre, pos) => self.asignar_campo(padre, campo_padre, nuevo, pos),
+            Expresion::Indice(lista, indice, _) => self.asignar_elemento(lista, indice, nuevo),
+            // El parser no deja cambiar campos de valores temporales
+            _ => Ok(()),
+        }
+    }
+    
+    /// Cambia en su sitio el elemento `indice` de la lista o el mapa `lista`
+    fn asignar_elemento(&mut self, lista: &Expresion, indice: &Expresion, valor: Valor) -> Result<(), Diagnostico> {
+        let destino = self.evaluar_expresion(lista)?;
+        let posicion = self.evaluar_expresion(indice)?;
+        sin_ciclo(&destino, &valor, lista.posicion())?;
+        match destino {
+            Valor::Lista(elementos) => {
+                let mut elementos = elementos.borrow_mut();
+                let i = indice_valido(&posicion, elementos.len(), false, "elementos", indice.posicion())?;
+                elementos[i] = valor;
+                Ok(())
+            }
+            Valor::Mapa(mapa) => {
+                clave_valida(&posicion, indice.posicion())?;
+                mapa.borrow_mut().insertar(posicion, valor);
+                Ok(())
+            }
+            otro => Err(Diagnostico::new(
+                Codigo::TiposIncompatibles,
+                format!("No se puede asignar a un elemento de {}", otro),
+                lista.posicion(),
+            )
+            .con_nota("solo se pueden modificar los elementos de una lista o un mapa")),
+        }
+    }
+    
+    /// Programa un bloque en el reloj de frames y devuelve su temporizador
+    fn programar(
+        &mut self,
+        programacion: &Programacion,
+        cuerpo: &[Sentencia],
+    ) -> Result<Valor, Diagnostico> {
+        let ahora = self.reloj.tiempo();
+        
+        let (vencimiento, periodo) = match programacion {
+            Programacion::Despues(expr) => {
+                let segundos = self.evaluar_intervalo(expr, "despues")?;
+                if segundos < 0.0 {
+                    return Err(Diagnostico::new(
+                        Codigo::IntervaloInvalido,
+                        format!(
+                            "Intervalo inválido para 'despues': {} segundos, no puede ser negativo",
+                            segundos
+                        ),
+                        expr.posicion(),
+                    ));
+                }
+                (ahora + segundos, Periodo::Unico)
+            }
+            Programacion::Cada(expr) => {
+                let segundos = self.evaluar_intervalo(expr, "cada")?;
+                if segundos <= 0.0 {
+                    return Err(Diagnostico::new(
+                        Codigo::IntervaloInvalido,
+                        format!(
+                            "Intervalo inválido para 'cada': {} segundos, debe ser mayor que cero",
+                            segundos
+                        ),
+                        expr.posicion(),
+                    )
+                    .con_sugerencia("usa 'cada frame' para ejecutar el bloque en cada frame"));
+                }
+                (ahora + segundos, Periodo::Segundos(segundos))
+            }
+            Programacion::CadaFrame => (ahora + self.reloj.delta, Periodo::Frame),
+        };
+        
+        let id = self.planificador.programar(vencimiento, periodo, cuerpo.to_vec());
+        Ok(Valor::Temporizador(id))
+    }
+    
+    /// Evalúa el intervalo de un temporizador en segundos; si lleva unidad
+    /// debe ser un tiempo
+    fn evaluar_intervalo(&mut self, expr: &Expresion, contexto: &str) -> Result<f64, Diagnostico> {
+        let valor = match self.evaluar_expresion(expr)? {
+            Valor::Medida(segundos, Dimension::TIEMPO) => Valor::Decimal(segundos),
+            Valor::Medida(_, dimension) => {
+                return Err(Diagnostico::new(
+                    Codigo::TiposIncompatibles,
+                    format!(
+                        "Intervalo de '{}' debe ser un tiempo, no {}",
+                        contexto,
+                        dimension.describir()
+                    ),
+                    expr.posicion(),
+                ));
+            }
+            valor => valor,
+        };
+        let segundos = valor.a_decimal().map_err(|e| {
+            Diagnostico::new(
+                Codigo::TiposIncompatibles,
+                format!("Intervalo de '{}' debe ser numérico: {}", contexto, e),
+                expr.posicion(),
+            )
+        })?;
+        
+        if !segundos.is_finite() {
+            return Err(Diagnostico::new(
+                Codigo::IntervaloInvalido,
+                format!("Intervalo inválido para '{}': {}", contexto, segundos),
+                expr.posicion(),
+            ));
         }
+        
+        Ok(segundos)
     }
     
     /// Aplica un operador binario
@@ -308,76 +1848,82 @@ impl Ejecutor {
         op: &OperadorBinario,
         der: Valor,
         pos: &Posicion,
-    ) -> Result<Valor, String> {
+    ) -> Result<Valor, Diagnostico> {
+        if matches!(izq, Valor::Medida(..)) || matches!(der, Valor::Medida(..)) {
+            if let Some(resultado) = operar_medidas(&izq, op, &der, pos) {
+                return resultado;
+            }
+        }
+        
         match op {
             OperadorBinario::Suma => match (&izq, &der) {
-                (Valor::Numero(a), Valor::Numero(b)) => Ok(Valor::Numero(a + b)),
+                (Valor::Numero(a), Valor::Numero(b)) => entero(a.checked_add(*b), pos),
                 (Valor::Decimal(a), Valor::Decimal(b)) => Ok(Valor::Decimal(a + b)),
                 (Valor::Numero(a), Valor::Decimal(b)) => Ok(Valor::Decimal(*a as f64 + b)),
                 (Valor::Decimal(a), Valor::Numero(b)) => Ok(Valor::Decimal(a + *b as f64)),
                 (Valor::Texto(a), Valor::Texto(b)) => Ok(Valor::Texto(format!("{}{}", a, b))),
                 (Valor::Texto(a), b) => Ok(Valor::Texto(format!("{}{}", a, b))),
                 (a, Valor::Texto(b)) => Ok(Valor::Texto(format!("{}{}", a, b))),
-                _ => Err(format!("Operador '+' no aplicable a {} y {} en {}", izq, der, pos)),
+                _ => Err(no_aplicable("+", &izq, &der, pos)),
             },
             
             OperadorBinario::Resta => match (&izq, &der) {
-                (Valor::Numero(a), Valor::Numero(b)) => Ok(Valor::Numero(a - b)),
+                (Valor::Numero(a), Valor::Numero(b)) => entero(a.checked_sub(*b), pos),
                 (Valor::Decimal(a), Valor::Decimal(b)) => Ok(Valor::Decimal(a - b)),
                 (Valor::Numero(a), Valor::Decimal(b)) => Ok(Valor::Decimal(*a as f64 - b)),
                 (Valor::Decimal(a), Valor::Numero(b)) => Ok(Valor::Decimal(a - *b as f64)),
-                _ => Err(format!("Operador '-' no aplicable a {} y {} en {}", izq, der, pos)),
+                _ => Err(no_aplicable("-", &izq, &der, pos)),
             },
             
             OperadorBinario::Multiplicacion => match (&izq, &der) {
-                (Valor::Numero(a), Valor::Numero(b)) => Ok(Valor::Numero(a * b)),
+                (Valor::Numero(a), Valor::Numero(b)) => entero(a.checked_mul(*b), pos),
                 (Valor::Decimal(a), Valor::Decimal(b)) => Ok(Valor::Decimal(a * b)),
                 (Valor::Numero(a), Valor::Decimal(b)) => Ok(Valor::Decimal(*a as f64 * b)),
                 (Valor::Decimal(a), Valor::Numero(b)) => Ok(Valor::Decimal(a * *b as f64)),
-                _ => Err(format!("Operador '*' no aplicable a {} y {} en {}", izq, der, pos)),
+                _ => Err(no_aplicable("*", &izq, &der, pos)),
             },
             
             OperadorBinario::Division => match (&izq, &der) {
                 (Valor::Numero(a), Valor::Numero(b)) => {
                     if *b == 0 {
-                        Err(format!("División por cero en {}", pos))
+                        Err(Diagnostico::new(Codigo::DivisionPorCero, "División por cero", pos))
                     } else {
-                        Ok(Valor::Numero(a / b))
+                        entero(a.checked_div(*b), pos)
                     }
                 }
                 (Valor::Decimal(a), Valor::Decimal(b)) => {
                     if *b == 0.0 {
-                        Err(format!("División por cero en {}", pos))
+                        Err(Diagnostico::new(Codigo::DivisionPorCero, "División por cero", pos))
                     } else {
                         Ok(Valor::Decimal(a / b))
                     }
                 }
                 (Valor::Numero(a), Valor::Decimal(b)) => {
                     if *b == 0.0 {
-                        Err(format!("División por cero en {}", pos))
+                        Err(Diagnostico::new(Codigo::DivisionPorCero, "División por cero", pos))
                     } else {
                         Ok(Valor::Decimal(*a as f64 / b))
                     }
                 }
                 (Valor::Decimal(a), Valor::Numero(b)) => {
                     if *b == 0 {
-                        Err(format!("División por cero en {}", pos))
+                        Err(Diagnostico::new(Codigo::DivisionPorCero, "División por cero", pos))
                     } else {
                         Ok(Valor::Decimal(a / *b as f64))
                     }
                 }
-                _ => Err(format!("Operador '/' no aplicable a {} y {} en {}", izq, der, pos)),
+                _ => Err(no_aplicable("/", &izq, &der, pos)),
             },
             
             OperadorBinario::Modulo => match (&izq, &der) {
                 (Valor::Numero(a), Valor::Numero(b)) => {
                     if *b == 0 {
-                        Err(format!("Módulo por cero en {}", pos))
+                        Err(Diagnostico::new(Codigo::DivisionPorCero, "Módulo por cero", pos))
                     } else {
-                        Ok(Valor::Numero(a % b))
+                        entero(a.checked_rem(*b), pos)
                     }
                 }
-                _ => Err(format!("Operador '%' no aplicable a {} y {} en {}", izq, der, pos)),
+                _ => Err(no_aplicable("%", &izq, &der, pos)),
             },
             
             OperadorBinario::Igual => Ok(Valor::Booleano(izq == der)),
@@ -389,7 +1935,7 @@ impl Ejecutor {
                 (Valor::Decimal(a), Valor::Decimal(b)) => Ok(Valor::Booleano(a < b)),
                 (Valor::Numero(a), Valor::Decimal(b)) => Ok(Valor::Booleano((*a as f64) < *b)),
                 (Valor::Decimal(a), Valor::Numero(b)) => Ok(Valor::Booleano(*a < (*b as f64))),
-                _ => Err(format!("Operador '<' no aplicable a {} y {} en {}", izq, der, pos)),
+                _ => Err(no_aplicable("<", &izq, &der, pos)),
             },
             
             OperadorBinario::MenorIgual => match (&izq, &der) {
@@ -397,7 +1943,7 @@ impl Ejecutor {
                 (Valor::Decimal(a), Valor::Decimal(b)) => Ok(Valor::Booleano(a <= b)),
                 (Valor::Numero(a), Valor::Decimal(b)) => Ok(Valor::Booleano((*a as f64) <= *b)),
                 (Valor::Decimal(a), Valor::Numero(b)) => Ok(Valor::Booleano(*a <= (*b as f64))),
-                _ => Err(format!("Operador '<=' no aplicable a {} y {} en {}", izq, der, pos)),
+                _ => Err(no_aplicable("<=", &izq, &der, pos)),
             },
             
             OperadorBinario::Mayor => match (&izq, &der) {
@@ -405,7 +1951,7 @@ impl Ejecutor {
                 (Valor::Decimal(a), Valor::Decimal(b)) => Ok(Valor::Booleano(a > b)),
                 (Valor::Numero(a), Valor::Decimal(b)) => Ok(Valor::Booleano((*a as f64) > *b)),
                 (Valor::Decimal(a), Valor::Numero(b)) => Ok(Valor::Booleano(*a > (*b as f64))),
-                _ => Err(format!("Operador '>' no aplicable a {} y {} en {}", izq, der, pos)),
+                _ => Err(no_aplicable(">", &izq, &der, pos)),
             },
             
             OperadorBinario::MayorIgual => match (&izq, &der) {
@@ -413,7 +1959,7 @@ impl Ejecutor {
                 (Valor::Decimal(a), Valor::Decimal(b)) => Ok(Valor::Booleano(a >= b)),
                 (Valor::Numero(a), Valor::Decimal(b)) => Ok(Valor::Booleano((*a as f64) >= *b)),
                 (Valor::Decimal(a), Valor::Numero(b)) => Ok(Valor::Booleano(*a >= (*b as f64))),
-                _ => Err(format!("Operador '>=' no aplicable a {} y {} en {}", izq, der, pos)),
+                _ => Err(no_aplicable(">=", &izq, &der, pos)),
             },
             
             OperadorBinario::Y => {
@@ -432,48 +1978,538 @@ impl Ejecutor {
         nombre: &str,
         argumentos: &[Expresion],
         pos: &Posicion,
-    ) -> Result<Valor, String> {
-        let funcion = self.funciones.get(nombre).cloned().ok_or_else(|| {
-            format!("Función '{}' no definida en {}", nombre, pos)
-        })?;
+    ) -> Result<Valor, Diagnostico> {
+        // Una variable con una función tapa a la función declarada
+        let funcion = match self.entorno.obtener(nombre) {
+            Some(Valor::Funcion(funcion)) => Some(funcion),
+            _ => self.funciones.get(nombre).cloned(),
+        };
+        let funcion = match funcion {
+            Some(funcion) => funcion,
+            None if self.estructuras.contains_key(nombre) && argumentos.is_empty() => {
+                return self.construir(nombre, &[], pos);
+            }
+            None if self.estructuras.contains_key(nombre) => {
+                return Err(Diagnostico::new(
+                    Codigo::CamposIncorrectos,
+                    format!("Los campos de '{}' se nombran al construirla", nombre),
+                    pos,
+                )
+                .con_sugerencia(format!("escribe {}(campo: valor, ...)", nombre)));
+            }
+            None if self.variantes.contains_key(nombre) => {
+                let mut valores = Vec::with_capacity(argumentos.len());
+                for arg in argumentos {
+                    valores.push(self.evaluar_expresion(arg)?);
+                }
+                return self.construir_variante(nombre, valores, pos);
+            }
+            None => return self.llamar_nativa(nombre, argumentos, pos),
+        };
         
-        if argumentos.len() != funcion.parametros.len() {
-            return Err(format!(
-                "Función '{}' espera {} argumentos, se proporcionaron {} en {}",
-                nombre,
-                funcion.parametros.len(),
-                argumentos.len(),
-                pos
+        let valores = self.evaluar_argumentos(argumentos)?;
+        self.invocar(&funcion, valores, pos)
+    }
+    
+    /// Llama a una función con los valores de sus argumentos. Una función
+    /// declarada se ejecuta sobre el entorno de quien la llama; una anónima,
+    /// sobre su copia del ámbito en que se creó.
+    fn invocar(
+        &mut self,
+        funcion: &Rc<FuncionDefinida>,
+        valores: Vec<Valor>,
+        pos: &Posicion,
+    ) -> Result<Valor, Diagnostico> {
+        if valores.len() != funcion.parametros.len() {
+            return Err(Diagnostico::new(
+                Codigo::ArgumentosIncorrectos,
+                format!(
+                    "Función {} espera {} argumentos, se proporcionaron {}",
+                    funcion.describir(),
+                    funcion.parametros.len(),
+                    valores.len()
+                ),
+                pos,
             ));
         }
         
-        // Crear nuevo entorno para la función
-        let entorno_anterior = self.entorno.clone();
-        self.entorno = Entorno::con_padre(entorno_anterior.clone());
+        let mut locales: HashMap<String, Valor> = funcion
+            .parametros
+            .iter()
+            .map(|parametro| parametro.nombre.clone())
+            .zip(valores)
+            .collect();
         
-        // Evaluar y asignar argumentos
-        for (i, arg) in argumentos.iter().enumerate() {
-            let valor = self.evaluar_expresion(arg)?;
-            self.entorno.definir(funcion.parametros[i].nombre.clone(), valor);
+        let nombre = funcion.nombre.as_deref().unwrap_or("anónima");
+        if funcion.es_corrutina {
+            return self.iniciar_corrutina(nombre, locales, funcion.cuerpo.clone());
         }
         
-        // Ejecutar cuerpo de la función
-        let mut resultado = Valor::Nulo;
-        for sentencia in &funcion.cuerpo {
-            if let Some(retorno) = self.ejecutar_sentencia(sentencia)? {
-                resultado = retorno;
-                break;
+        let entorno = match &funcion.captura {
+            Some(captura) => {
+                // Así una anónima guardada en una variable puede llamarse
+                // a sí misma, aunque la variable no existiera al crearla
+                if let Some(nombre) = &funcion.nombre {
+                    locales.entry(nombre.clone()).or_insert_with(|| Valor::Funcion(funcion.clone()));
+                }
+                captura.clone()
             }
-        }
+            None => self.entorno.clone(),
+        };
+        let entorno_anterior = std::mem::replace(&mut self.entorno, entorno);
+        self.entorno.abrir_ambito(locales);
         
-        // Restaurar entorno
+        // El parser impide que un `romper` o `continuar` salga de la función
+        let resultado = self.ejecutar_bloque(&funcion.cuerpo);
+        
+        self.entorno.cerrar_ambito();
         self.entorno = entorno_anterior;
         
-        Ok(resultado)
+        match resultado? {
+            Flujo::Retornar(valor) => Ok(valor),
+            _ => Ok(Valor::Nulo),
+        }
+    }
+    
+    /// Evalúa los argumentos de una llamada, en orden
+    fn evaluar_argumentos(&mut self, argumentos: &[Expresion]) -> Result<Vec<Valor>, Diagnostico> {
+        argumentos.iter().map(|arg| self.evaluar_expresion(arg)).collect()
+    }
+    
+    /// Evalúa una expresión que debe dar una función, para `contexto`
+    fn funcion_de(&mut self, expr: &Expresion, contexto: &str) -> Result<Rc<FuncionDefinida>, Diagnostico> {
+        match self.evaluar_expresion(expr)? {
+            Valor::Funcion(funcion) => Ok(funcion),
+            otro => Err(Diagnostico::new(
+                Codigo::TiposIncompatibles,
+                format!("'{}' espera una función, no {}", contexto, otro),
+                expr.posicion(),
+            )),
+        }
+    }
+    
+    /// Llama a una función nativa del runtime
+    fn llamar_nativa(
+        &mut self,
+        nombre: &str,
+        argumentos: &[Expresion],
+        pos: &Posicion,
+    ) -> Result<Valor, Diagnostico> {
+        let mut valores = Vec::new();
+        for arg in argumentos {
+            valores.push(self.evaluar_expresion(arg)?);
+        }
+        
+        if let Some(firma) = colecciones::firma(nombre) {
+            return self.llamar_coleccion(firma, valores, pos);
+        }
+        
+        match nombre {
+            "cambiar_escena" => {
+                let destino = match valores.as_slice() {
+                    [Valor::Texto(destino)] => destino.clone(),
+                    _ => return Err(Diagnostico::new(
+                        Codigo::ArgumentosIncorrectos,
+                        "'cambiar_escena' espera el nombre de una escena",
+                        pos,
+                    )),
+                };
+                
+                if !self.escenas.contains_key(&destino) {
+                    return Err(Diagnostico::new(
+                        Codigo::EscenaNoDefinida,
+                        format!("Escena '{}' no definida", destino),
+                        pos,
+                    ));
+                }
+                
+                // La transición se aplica al terminar la escena en curso
+                self.escena_siguiente = Some(destino);
+                Ok(Valor::Nulo)
+            }
+            
+            "cancelar" => match valores.as_slice() {
+                [Valor::Temporizador(id)] => Ok(Valor::Booleano(self.planificador.cancelar(*id))),
+                [Valor::Corrutina(id)] => Ok(Valor::Booleano(self.planificador.cancelar_corrutina(*id))),
+                _ => Err(Diagnostico::new(
+                    Codigo::ArgumentosIncorrectos,
+                    "'cancelar' espera un temporizador o una corrutina",
+                    pos,
+                )),
+            },
+            
+            "agregar" => match valores.as_slice() {
+                [coleccion @ Valor::Lista(lista), valor] => {
+                    sin_ciclo(coleccion, valor, argumentos[1].posicion())?;
+                    lista.borrow_mut().push(valor.clone());
+                    Ok(Valor::Nulo)
+                }
+                _ => Err(Diagnostico::new(
+                    Codigo::ArgumentosIncorrectos,
+                    "'agregar' espera una lista y el valor a añadir al final",
+                    pos,
+                )),
+            },
+            
+            "insertar" => match valores.as_slice() {
+                [coleccion @ Valor::Lista(lista), indice, valor] => {
+                    sin_ciclo(coleccion, valor, argumentos[2].posicion())?;
+                    let mut lista = lista.borrow_mut();
+                    let i = indice_valido(indice, lista.len(), true, "elementos", argumentos[1].posicion())?;
+                    lista.insert(i, valor.clone());
+                    Ok(Valor::Nulo)
+                }
+                _ => Err(Diagnostico::new(
+                    Codigo::ArgumentosIncorrectos,
+                    "'insertar' espera una lista, la posición y el valor a insertar",
+                    pos,
+                )),
+            },
+            
+            "quitar" => match valores.as_slice() {
+                [Valor::Lista(lista), indice] => {
+                    let mut lista = lista.borrow_mut();
+                    let i = indice_valido(indice, lista.len(), false, "elementos", argumentos[1].posicion())?;
+                    Ok(lista.remove(i))
+                }
+                [Valor::Mapa(mapa), clave] => mapa
+                    .borrow_mut()
+                    .quitar(clave)
+                    .ok_or_else(|| clave_no_encontrada(clave, argumentos[1].posicion())),
+                _ => Err(Diagnostico::new(
+                    Codigo::ArgumentosIncorrectos,
+                    "'quitar' espera una lista y la posición del elemento a quitar, o un mapa y una clave",
+                    pos,
+                )),
+            },
+            
+            "longitud" => match valores.as_slice() {
+                [Valor::Lista(lista)] => Ok(Valor::Numero(lista.borrow().len() as i64)),
+                [Valor::Texto(texto)] => Ok(Valor::Numero(texto.chars().count() as i64)),
+                [Valor::Mapa(mapa)] => Ok(Valor::Numero(mapa.borrow().entradas.len() as i64)),
+                _ => Err(Diagnostico::new(
+                    Codigo::ArgumentosIncorrectos,
+                    "'longitud' espera una lista, un texto o un mapa",
+                    pos,
+                )),
+            },
+            
+            "contiene" => match valores.as_slice() {
+                [Valor::Lista(lista), valor] => Ok(Valor::Booleano(lista.borrow().contains(valor))),
+                [Valor::Texto(texto), Valor::Texto(parte)] => Ok(Valor::Booleano(texto.contains(parte.as_str()))),
+                _ => Err(Diagnostico::new(
+                    Codigo::ArgumentosIncorrectos,
+                    "'contiene' espera una lista y un valor, o dos textos",
+                    pos,
+                )),
+            },
+            
+            "claves" => match valores.as_slice() {
+                [Valor::Mapa(mapa)] => Ok(Valor::lista(mapa.borrow().claves())),
+                _ => Err(Diagnostico::new(Codigo::ArgumentosIncorrectos, "'claves' espera un mapa", pos)),
+            },
+            
+            "valores" => match valores.as_slice() {
+                [Valor::Mapa(mapa)] => Ok(Valor::lista(mapa.borrow().valores())),
+                _ => Err(Diagnostico::new(Codigo::ArgumentosIncorrectos, "'valores' espera un mapa", pos)),
+            },
+            
+            "tiene" => match valores.as_slice() {
+                [Valor::Mapa(mapa), clave] => Ok(Valor::Booleano(mapa.borrow().obtener(clave).is_some())),
+                _ => Err(Diagnostico::new(
+                    Codigo::ArgumentosIncorrectos,
+                    "'tiene' espera un mapa y una clave",
+                    pos,
+                )),
+            },
+            
+            "tiempo" => {
+                if !valores.is_empty() {
+                    return Err(Diagnostico::new(
+                        Codigo::ArgumentosIncorrectos,
+                        "'tiempo' no recibe argumentos",
+                        pos,
+                    ));
+                }
+                Ok(Valor::Decimal(self.reloj.tiempo()))
+            }
+            
+            "estado_de" => {
+                let nombre = match valores.as_slice() {
+                    [Valor::Texto(nombre)] => nombre,
+                    _ => return Err(Diagnostico::new(
+                        Codigo::ArgumentosIncorrectos,
+                        "'estado_de' espera el nombre de una máquina",
+                        pos,
+                    )),
+                };
+                
+                self.maquinas
+                    .iter()
+                    .find(|m| &m.definicion.nombre == nombre)
+                    .map(|m| Valor::Texto(m.estado().nombre.clone()))
+                    .ok_or_else(|| {
+                        Diagnostico::new(
+                            Codigo::MaquinaNoActiva,
+                            format!("Máquina '{}' no activa", nombre),
+                            pos,
+                        )
+                    })
+            }
+            
+            _ => match self.entorno.obtener(nombre) {
+                Some(valor) => Err(Diagnostico::new(
+                    Codigo::TiposIncompatibles,
+                    format!("'{}' no es una función: vale {}", nombre, valor),
+                    pos,
+                )),
+                None => Err(Diagnostico::new(
+                    Codigo::FuncionNoDefinida,
+                    format!("Función '{}' no definida", nombre),
+                    pos,
+                )),
+            },
+        }
+    }
+    
+    /// Llama a una función de colecciones (ver `colecciones.rs`). Recorre
+    /// una copia de la lista, así que la función puede modificarla.
+    fn llamar_coleccion(&mut self, firma: &Firma, valores: Vec<Valor>, pos: &Posicion) -> Result<Valor, Diagnostico> {
+        let incorrectos = || {
+            Diagnostico::new(
+                Codigo::ArgumentosIncorrectos,
+                format!("'{}' espera {}", firma.nombre, firma.uso),
+                pos,
+            )
+        };
+        if valores.len() < firma.minimo || valores.len() > firma.maximo {
+            return Err(incorrectos());
+        }
+        let mut listas = Vec::with_capacity(firma.listas);
+        for valor in &valores[..firma.listas] {
+            match valor {
+                Valor::Lista(lista) => listas.push(lista.borrow().clone()),
+                _ => return Err(incorrectos()),
+            }
+        }
+        let funcion = match firma.funcion.and_then(|(i, _)| valores.get(i)) {
+            Some(Valor::Funcion(funcion)) => Some(funcion.clone()),
+            Some(_) => return Err(incorrectos()),
+            None => None,
+        };
+        let elementos = listas.swap_remove(0);
+        
+        // Llama a la función recibida; solo falta en `ordenar` sin
+        // comparador, `enumerar` y `zip`, que no la usan
+        let llamar = |ejecutor: &mut Self, argumentos: Vec<Valor>| match &funcion {
+            Some(funcion) => ejecutor.invocar(funcion, argumentos, pos),
+            None => Err(incorrectos()),
+        };
+        
+        match firma.nombre {
+            "mapear" => {
+                let mut resultado = Vec::with_capacity(elementos.len());
+                for elemento in elementos {
+                    resultado.push(llamar(self, vec![elemento])?);
+                }
+                Ok(Valor::lista(resultado))
+            }
+            "filtrar" => {
+                let mut resultado = Vec::new();
+                for elemento in elementos {
+                    if llamar(self, vec![elemento.clone()])?.es_verdadero() {
+                        resultado.push(elemento);
+                    }
+                }
+                Ok(Valor::lista(resultado))
+            }
+            "reducir" => {
+                let mut acumulado = valores[1].clone();
+                for elemento in elementos {
+                    acumulado = llamar(self, vec![acumulado, elemento])?;
+                }
+                Ok(acumulado)
+            }
+            "ordenar" => {
+                let mut antes = |a: &Valor, b: &Valor| match (&funcion, a, b) {
+                    (Some(_), _, _) => llamar(self, vec![a.clone(), b.clone()]).map(|v| v.es_verdadero()),
+                    (None, Valor::Texto(a), Valor::Texto(b)) => Ok(a < b),
+                    (None, _, _) => self
+                        .aplicar_operador_binario(a.clone(), &OperadorBinario::Menor, b.clone(), pos)
+                        .map(|v| v.es_verdadero()),
+                };
+                Ok(Valor::lista(colecciones::ordenar(elementos, &mut antes)?))
+            }
+            "buscar" => {
+                for elemento in elementos {
+                    if llamar(self, vec![elemento.clone()])?.es_verdadero() {
+                        return Ok(elemento);
+                    }
+                }
+                Ok(Valor::Nulo)
+            }
+            "cualquiera" | "todos" => {
+                // `cualquiera` para en el primero que la cumple y `todos`
+                // en el primero que no
+                let buscado = firma.nombre == "cualquiera";
+                for elemento in elementos {
+                    if llamar(self, vec![elemento])?.es_verdadero() == buscado {
+                        return Ok(Valor::Booleano(buscado));
+                    }
+                }
+                Ok(Valor::Booleano(!buscado))
+            }
+            "enumerar" => Ok(Valor::lista(
+                elementos
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, elemento)| Valor::lista(vec![Valor::Numero(i as i64), elemento]))
+                    .collect(),
+            )),
+            "zip" => Ok(Valor::lista(
+                elementos
+                    .into_iter()
+                    .zip(listas.swap_remove(0))
+                    .map(|(a, b)| Valor::lista(vec![a, b]))
+                    .collect(),
+            )),
+            _ => unreachable!("función de colecciones sin implementar: {}", firma.nombre),
+        }
+    }
+    
+    /// Transiciones aplicadas por las máquinas de estados, en orden
+    #[allow(dead_code)]
+    pub fn transiciones(&self) -> &[RegistroTransicion] {
+        &self.transiciones
     }
     
     /// Obtiene la salida generada
+    #[allow(dead_code)]
     pub fn obtener_salida(&self) -> &[String] {
         &self.salida
     }
 }
+
+/// Aplica un operador a una medida y un número u otra medida, o devuelve
+/// `None` si el operador no trata con medidas. Sumar, restar, módulo y
+/// comparar exigen la misma dimensión; multiplicar y dividir la combinan.
+fn operar_medidas(izq: &Valor, op: &OperadorBinario, der: &Valor, pos: &Posicion) -> Option<Result<Valor, Diagnostico>> {
+    let (a, dim_a) = izq.como_medida()?;
+    let (b, dim_b) = der.como_medida()?;
+    
+    let resultado = match op {
+        OperadorBinario::Multiplicacion => Valor::medida(a * b, dim_a.por(dim_b)),
+        OperadorBinario::Division | OperadorBinario::Modulo if b == 0.0 => {
+            let mensaje = if *op == OperadorBinario::Division { "División por cero" } else { "Módulo por cero" };
+            return Some(Err(Diagnostico::new(Codigo::DivisionPorCero, mensaje, pos)));
+        }
+        OperadorBinario::Division => Valor::medida(a / b, dim_a.entre(dim_b)),
+        OperadorBinario::Y | OperadorBinario::O => return None,
+        OperadorBinario::Igual | OperadorBinario::Diferente if dim_a != dim_b => return None,
+        _ if dim_a != dim_b => {
+            return Some(Err(Diagnostico::new(
+                Codigo::TiposIncompatibles,
+                format!(
+                    "Unidades incompatibles en '{}': {} y {}",
+                    op,
+                    dim_a.describir(),
+                    dim_b.describir()
+                ),
+                pos,
+            )));
+        }
+        OperadorBinario::Suma => Valor::medida(a + b, dim_a),
+        OperadorBinario::Resta => Valor::medida(a - b, dim_a),
+        OperadorBinario::Modulo => Valor::medida(a % b, dim_a),
+        OperadorBinario::Igual => Valor::Booleano(a == b),
+        OperadorBinario::Diferente => Valor::Booleano(a != b),
+        OperadorBinario::Menor => Valor::Booleano(a < b),
+        OperadorBinario::MenorIgual => Valor::Booleano(a <= b),
+        OperadorBinario::Mayor => Valor::Booleano(a > b),
+        OperadorBinario::MayorIgual => Valor::Booleano(a >= b),
+    };
+    Some(Ok(resultado))
+}
+
+/// Posición de `valor` como índice de una secuencia de `longitud`
+/// elementos (o letras, según `unidad`). Los extremos de una rebanada y la
+/// posición de `insertar` pueden valer también `longitud`.
+fn indice_valido(valor: &Valor, longitud: usize, extremo: bool, unidad: &str, pos: &Posicion) -> Result<usize, Diagnostico> {
+    let Valor::Numero(indice) = valor else {
+        return Err(Diagnostico::new(
+            Codigo::TiposIncompatibles,
+            format!("El índice debe ser un número entero, no {}", valor),
+            pos,
+        ));
+    };
+    
+    let limite = if extremo { longitud + 1 } else { longitud };
+    match usize::try_from(*indice) {
+        Ok(i) if i < limite => Ok(i),
+        _ => {
+            let error = Diagnostico::new(
+                Codigo::IndiceFueraDeRango,
+                format!("Índice {} fuera de rango: hay {} {}", indice, longitud, unidad),
+                pos,
+            );
+            Err(match limite {
+                0 => error.con_nota("no hay ningún elemento"),
+                _ => error.con_nota(format!("los índices válidos van de 0 a {}", limite - 1)),
+            })
+        }
+    }
+}
+
+/// Comprueba que `valor` pueda ser clave de un mapa
+fn clave_valida(valor: &Valor, pos: &Posicion) -> Result<(), Diagnostico> {
+    match valor {
+        Valor::Texto(_) | Valor::Numero(_) | Valor::Booleano(_) => Ok(()),
+        otro => Err(Diagnostico::new(
+            Codigo::TiposIncompatibles,
+            format!("{} no puede ser clave de un mapa", otro),
+            pos,
+        )
+        .con_nota("las claves son textos, números enteros o booleanos")),
+    }
+}
+
+/// Impide meter una lista o un mapa dentro de sí mismo, directamente o a
+/// través de otros valores: no se podría mostrar ni comparar
+fn sin_ciclo(coleccion: &Valor, valor: &Valor, pos: &Posicion) -> Result<(), Diagnostico> {
+    if !valor.contiene_coleccion(coleccion) {
+        return Ok(());
+    }
+    let mensaje = match coleccion {
+        Valor::Mapa(_) => "Un mapa no puede contenerse a sí mismo",
+        _ => "Una lista no puede contenerse a sí misma",
+    };
+    Err(Diagnostico::new(Codigo::ColeccionCircular, mensaje, pos)
+        .con_nota("el valor es la propia colección o la lleva dentro"))
+}
+
+fn clave_no_encontrada(clave: &Valor, pos: &Posicion) -> Diagnostico {
+    let clave = match clave {
+        Valor::Texto(texto) => format!("\"{}\"", texto),
+        otro => otro.to_string(),
+    };
+    Diagnostico::new(Codigo::ClaveNoEncontrada, format!("Clave {} no encontrada en el mapa", clave), pos)
+        .con_sugerencia(format!("comprueba antes si existe con 'tiene(mapa, {})'", clave))
+}
+
+/// Resultado de una operación entre enteros; `None` si se sale del rango
+fn entero(resultado: Option<i64>, pos: &Posicion) -> Result<Valor, Diagnostico> {
+    resultado.map(Valor::Numero).ok_or_else(|| {
+        Diagnostico::new(Codigo::Desbordamiento, "El resultado no cabe en un número entero", pos)
+            .con_nota(format!("los enteros van de {} a {}", i64::MIN, i64::MAX))
+            .con_sugerencia("opera con decimales si necesitas valores más grandes")
+    })
+}
+
+/// Error de un operador binario aplicado a valores que no admite
+fn no_aplicable(operador: &str, izq: &Valor, der: &Valor, pos: &Posicion) -> Diagnostico {
+    Diagnostico::new(
+        Codigo::TiposIncompatibles,
+        format!("Operador '{}' no aplicable a {} y {}", operador, izq, der),
+        pos,
+    )
+}
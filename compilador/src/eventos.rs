@@ -0,0 +1,93 @@
+// eventos.rs - Bus de eventos de V-Code
+// `emitir` encola eventos y el runtime los entrega al final de cada frame.
+// Orden de entrega: los eventos en orden de emisión y, para cada evento,
+// los oyentes en orden de registro. Lo que emite un oyente se entrega en
+// la misma fase, después de lo que ya estaba pendiente.
+
+use crate::ast::{Posicion, Sentencia};
+use crate::ejecutor::{FuncionDefinida, Valor};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+/// Eventos que pueden entregarse en un mismo frame antes de suponer que
+/// los oyentes se reemiten en bucle
+pub const MAX_EVENTOS_POR_FRAME: usize = 10_000;
+
+/// Evento emitido pendiente de entrega
+#[derive(Debug, Clone)]
+pub struct Evento {
+    pub nombre: String,
+    pub datos: Vec<Valor>,
+    pub pos: Posicion,
+}
+
+/// Manejador registrado con `escuchar`
+#[derive(Debug, Clone)]
+pub struct Oyente {
+    pub evento: String,
+    pub manejador: Manejador,
+    pub pos: Posicion,
+}
+
+/// Lo que se ejecuta al entregar un evento: un bloque con sus parámetros
+/// (`escuchar "golpe" (fuerza) { ... }`) o una función (`escuchar "golpe" al_golpear`)
+#[derive(Debug, Clone)]
+pub enum Manejador {
+    Bloque(Vec<String>, Vec<Sentencia>),
+    Funcion(Rc<FuncionDefinida>),
+}
+
+impl Oyente {
+    /// Cuántos datos espera del evento
+    pub fn aridad(&self) -> usize {
+        match &self.manejador {
+            Manejador::Bloque(parametros, _) => parametros.len(),
+            Manejador::Funcion(funcion) => funcion.parametros.len(),
+        }
+    }
+}
+
+/// Oyentes y eventos pendientes de la escena en curso
+#[derive(Debug)]
+pub struct BusEventos {
+    oyentes: Vec<Oyente>,
+    cola: VecDeque<Evento>,
+}
+
+impl BusEventos {
+    pub fn new() -> Self {
+        BusEventos {
+            oyentes: Vec::new(),
+            cola: VecDeque::new(),
+        }
+    }
+    
+    pub fn escuchar(&mut self, oyente: Oyente) {
+        self.oyentes.push(oyente);
+    }
+    
+    pub fn emitir(&mut self, evento: Evento) {
+        self.cola.push_back(evento);
+    }
+    
+    /// Saca el siguiente evento pendiente
+    pub fn siguiente(&mut self) -> Option<Evento> {
+        self.cola.pop_front()
+    }
+    
+    /// Oyentes de un evento, en orden de registro. Se devuelven copias para
+    /// que un oyente pueda registrar otros sin afectar a la entrega en curso.
+    pub fn oyentes_de(&self, nombre: &str) -> Vec<Oyente> {
+        self.oyentes
+            .iter()
+            .filter(|oyente| oyente.evento == nombre)
+            .cloned()
+            .collect()
+    }
+    
+    /// Descarta oyentes y eventos (al salir de una escena)
+    pub fn limpiar(&mut self) {
+        self.oyentes.clear();
+        self.cola.clear();
+    }
+}
@@ -0,0 +1,200 @@
+// idiomas.rs - Conjuntos de palabras reservadas de V-Code
+// El español es el conjunto por defecto. Un archivo puede escribirse con
+// las palabras reservadas en inglés declarándolo en su primera línea
+// (`@language en`, o `@idioma en`) o con la opción `--idioma en`.
+
+use crate::lexer::TipoToken;
+
+/// Conjunto de palabras reservadas con el que se escribe un archivo
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Idioma {
+    #[default]
+    Espanol,
+    Ingles,
+}
+
+/// Palabras reservadas en español; la primera grafía de cada token es la
+/// que se usa al traducir, las siguientes son alias con tilde
+const ESPANOL: &[(&str, TipoToken)] = &[
+    ("escena", TipoToken::Escena),
+    ("mostrar", TipoToken::Mostrar),
+    ("var", TipoToken::Var),
+    ("constante", TipoToken::Constante),
+    ("si", TipoToken::Si),
+    ("sí", TipoToken::Si),
+    ("sino", TipoToken::Sino),
+    ("mientras", TipoToken::Mientras),
+    ("para", TipoToken::Para),
+    ("funcion", TipoToken::Funcion),
+    ("función", TipoToken::Funcion),
+    ("corrutina", TipoToken::Corrutina),
+    ("esperar", TipoToken::Esperar),
+    ("hasta", TipoToken::Hasta),
+    ("retornar", TipoToken::Retornar),
+    ("romper", TipoToken::Romper),
+    ("continuar", TipoToken::Continuar),
+    ("verdadero", TipoToken::Verdadero),
+    ("falso", TipoToken::Falso),
+    ("cada", TipoToken::Cada),
+    ("frame", TipoToken::Frame),
+    ("cuando", TipoToken::Cuando),
+    ("despues", TipoToken::Despues),
+    ("después", TipoToken::Despues),
+    ("emitir", TipoToken::Emitir),
+    ("escuchar", TipoToken::Escuchar),
+    ("al_iniciar", TipoToken::AlIniciar),
+    ("al_salir", TipoToken::AlSalir),
+    ("al_entrar", TipoToken::AlEntrar),
+    ("maquina", TipoToken::Maquina),
+    ("máquina", TipoToken::Maquina),
+    ("transicion", TipoToken::Transicion),
+    ("transición", TipoToken::Transicion),
+    ("estructura", TipoToken::Estructura),
+    ("enumeracion", TipoToken::Enumeracion),
+    ("enumeración", TipoToken::Enumeracion),
+    ("segun", TipoToken::Segun),
+    ("según", TipoToken::Segun),
+    ("intentar", TipoToken::Intentar),
+    ("capturar", TipoToken::Capturar),
+    ("finalmente", TipoToken::Finalmente),
+    ("lanzar", TipoToken::Lanzar),
+    ("y", TipoToken::Y),
+    ("o", TipoToken::O),
+    ("no", TipoToken::No),
+    ("numero", TipoToken::TipoNumero),
+    ("número", TipoToken::TipoNumero),
+    ("decimal", TipoToken::TipoDecimal),
+    ("texto", TipoToken::TipoTexto),
+    ("booleano", TipoToken::TipoBooleano),
+    ("vector3", TipoToken::TipoVector3),
+    ("pose", TipoToken::TipoPose),
+    ("mano", TipoToken::TipoMano),
+    ("controlador", TipoToken::TipoControlador),
+    ("lista", TipoToken::TipoLista),
+    ("mapa", TipoToken::TipoMapa),
+];
+
+/// Palabras reservadas en inglés
+const INGLES: &[(&str, TipoToken)] = &[
+    ("scene", TipoToken::Escena),
+    ("show", TipoToken::Mostrar),
+    ("var", TipoToken::Var),
+    ("const", TipoToken::Constante),
+    ("if", TipoToken::Si),
+    ("else", TipoToken::Sino),
+    ("while", TipoToken::Mientras),
+    ("for", TipoToken::Para),
+    ("function", TipoToken::Funcion),
+    ("coroutine", TipoToken::Corrutina),
+    ("wait", TipoToken::Esperar),
+    ("until", TipoToken::Hasta),
+    ("return", TipoToken::Retornar),
+    ("break", TipoToken::Romper),
+    ("continue", TipoToken::Continuar),
+    ("true", TipoToken::Verdadero),
+    ("false", TipoToken::Falso),
+    ("every", TipoToken::Cada),
+    ("frame", TipoToken::Frame),
+    ("when", TipoToken::Cuando),
+    ("after", TipoToken::Despues),
+    ("emit", TipoToken::Emitir),
+    ("listen", TipoToken::Escuchar),
+    ("on_start", TipoToken::AlIniciar),
+    ("on_exit", TipoToken::AlSalir),
+    ("on_enter", TipoToken::AlEntrar),
+    ("machine", TipoToken::Maquina),
+    ("transition", TipoToken::Transicion),
+    ("struct", TipoToken::Estructura),
+    ("enum", TipoToken::Enumeracion),
+    ("match", TipoToken::Segun),
+    ("try", TipoToken::Intentar),
+    ("catch", TipoToken::Capturar),
+    ("finally", TipoToken::Finalmente),
+    ("throw", TipoToken::Lanzar),
+    ("and", TipoToken::Y),
+    ("or", TipoToken::O),
+    ("not", TipoToken::No),
+    ("number", TipoToken::TipoNumero),
+    ("decimal", TipoToken::TipoDecimal),
+    ("text", TipoToken::TipoTexto),
+    ("boolean", TipoToken::TipoBooleano),
+    ("vector3", TipoToken::TipoVector3),
+    ("pose", TipoToken::TipoPose),
+    ("hand", TipoToken::TipoMano),
+    ("controller", TipoToken::TipoControlador),
+    ("list", TipoToken::TipoLista),
+    ("map", TipoToken::TipoMapa),
+];
+
+/// Palabras contextuales (español, inglés): solo son palabras clave en su
+/// posición (`estado X { }`, `transicion a X`, `@inicial`, `para x en`,
+/// `paso 2`, `caso X =>`, `otro =>`) y en cualquier otra son nombres. El
+/// parser acepta ambas grafías en cualquier idioma.
+const CONTEXTUALES: &[(&str, &str)] = &[
+    ("estado", "state"),
+    ("a", "to"),
+    ("inicial", "initial"),
+    ("en", "in"),
+    ("paso", "step"),
+    ("caso", "case"),
+    ("otro", "default"),
+];
+
+impl Idioma {
+    /// Busca un idioma por su código (`es`, `en`)
+    pub fn buscar(codigo: &str) -> Option<Idioma> {
+        match codigo {
+            "es" => Some(Idioma::Espanol),
+            "en" => Some(Idioma::Ingles),
+            _ => None,
+        }
+    }
+    
+    /// Código del idioma, como se escribe en el pragma
+    pub fn codigo(&self) -> &'static str {
+        match self {
+            Idioma::Espanol => "es",
+            Idioma::Ingles => "en",
+        }
+    }
+    
+    fn tabla(&self) -> &'static [(&'static str, TipoToken)] {
+        match self {
+            Idioma::Espanol => ESPANOL,
+            Idioma::Ingles => INGLES,
+        }
+    }
+    
+    /// Token de una palabra reservada, o `None` si `palabra` es un nombre
+    pub fn palabra_reservada(&self, palabra: &str) -> Option<TipoToken> {
+        self.tabla()
+            .iter()
+            .find(|(grafia, _)| *grafia == palabra)
+            .map(|(_, tipo)| tipo.clone())
+    }
+    
+    /// Grafía de un token de palabra reservada en este idioma
+    pub fn escribir(&self, tipo: &TipoToken) -> Option<&'static str> {
+        self.tabla()
+            .iter()
+            .find(|(_, otro)| otro == tipo)
+            .map(|(grafia, _)| *grafia)
+    }
+    
+    /// Grafía en este idioma de la palabra contextual `palabra`, escrita
+    /// en cualquiera de los dos
+    pub fn contextual(&self, palabra: &str) -> Option<&'static str> {
+        CONTEXTUALES
+            .iter()
+            .find(|(espanol, ingles)| *espanol == palabra || *ingles == palabra)
+            .map(|(espanol, ingles)| match self {
+                Idioma::Espanol => *espanol,
+                Idioma::Ingles => *ingles,
+            })
+    }
+}
+
+/// Si `palabra` es la palabra contextual `espanol` en algún idioma
+pub fn es_palabra_contextual(palabra: &str, espanol: &str) -> bool {
+    Idioma::Espanol.contextual(palabra) == Some(espanol)
+}
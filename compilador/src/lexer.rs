@@ -1,10 +1,19 @@
 // lexer.rs - Analizador Léxico para V-Code
 // Convierte código fuente en tokens
+//
+// Los errores léxicos no detienen el análisis: se registran, se emite un
+// token de error (o el token que el autor probablemente quiso escribir) y
+// se sigue leyendo.
 
-use crate::ast::Posicion;
+use crate::ast::{ArchivoId, Posicion};
+use crate::diagnostico::{Codigo, Diagnostico};
+use crate::idiomas::Idioma;
+use crate::unidades::{self, Unidad};
 use std::fmt;
+use unicode_ident::{is_xid_continue, is_xid_start};
+use unicode_normalization::UnicodeNormalization;
 
-/// Token - unidad léxica básica
+/// Token - unidad léxica básica; `pos` cubre todo su lexema
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub tipo: TipoToken,
@@ -18,6 +27,13 @@ impl Token {
     }
 }
 
+/// Trozo de un texto con interpolación
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParteDeTexto {
+    Literal(String),
+    Codigo(Vec<Token>), // Tokens de la expresión entre llaves, terminados en `Eof`
+}
+
 /// Tipos de tokens
 #[derive(Debug, Clone, PartialEq)]
 pub enum TipoToken {
@@ -31,12 +47,32 @@ pub enum TipoToken {
     Mientras,
     Para,
     Funcion,
+    Corrutina,
+    Esperar,
+    Hasta,
     Retornar,
+    Romper,
+    Continuar,
     Verdadero,
     Falso,
     Cada,
     Frame,
     Cuando,
+    Despues,
+    Emitir,
+    Escuchar,
+    AlIniciar,
+    AlSalir,
+    AlEntrar,
+    Maquina,
+    Transicion,
+    Estructura,
+    Enumeracion,
+    Segun,
+    Intentar,
+    Capturar,
+    Finalmente,
+    Lanzar,
     
     // Tipos
     TipoNumero,
@@ -47,11 +83,15 @@ pub enum TipoToken {
     TipoPose,
     TipoMano,
     TipoControlador,
+    TipoLista,
+    TipoMapa,
     
     // Literales
     Numero(i64),
     Decimal(f64),
+    Medida(f64, &'static Unidad), // 1.5m, 20ms: valor en la unidad escrita
     Texto(String),
+    TextoInterpolado(Vec<ParteDeTexto>), // "Puntos: {puntos}"
     
     // Identificadores
     Identificador(String),
@@ -78,12 +118,17 @@ pub enum TipoToken {
     ParentesisCierra,
     LlaveAbre,
     LlaveCierra,
+    CorcheteAbre,
+    CorcheteCierra,
     Coma,
     DosPuntos,
     Punto,
+    Arroba,
+    Flecha, // `=>` de los casos de `segun`
     
     // Especiales
-    NuevaLinea,
+    Documentacion(String), // Una línea `///`, sin las barras
+    Error(String), // Caracteres no válidos, ya informados como diagnóstico
     Eof,
 }
 
@@ -98,11 +143,61 @@ impl fmt::Display for TipoToken {
             TipoToken::Mientras => write!(f, "mientras"),
             TipoToken::Para => write!(f, "para"),
             TipoToken::Funcion => write!(f, "funcion"),
+            TipoToken::Corrutina => write!(f, "corrutina"),
+            TipoToken::Esperar => write!(f, "esperar"),
+            TipoToken::Hasta => write!(f, "hasta"),
             TipoToken::Retornar => write!(f, "retornar"),
+            TipoToken::Romper => write!(f, "romper"),
+            TipoToken::Continuar => write!(f, "continuar"),
+            TipoToken::Cada => write!(f, "cada"),
+            TipoToken::Frame => write!(f, "frame"),
+            TipoToken::Despues => write!(f, "despues"),
+            TipoToken::Emitir => write!(f, "emitir"),
+            TipoToken::Escuchar => write!(f, "escuchar"),
+            TipoToken::AlIniciar => write!(f, "al_iniciar"),
+            TipoToken::AlSalir => write!(f, "al_salir"),
+            TipoToken::AlEntrar => write!(f, "al_entrar"),
+            TipoToken::Maquina => write!(f, "maquina"),
+            TipoToken::Transicion => write!(f, "transicion"),
+            TipoToken::Estructura => write!(f, "estructura"),
+            TipoToken::Enumeracion => write!(f, "enumeracion"),
+            TipoToken::Segun => write!(f, "segun"),
+            TipoToken::Intentar => write!(f, "intentar"),
+            TipoToken::Capturar => write!(f, "capturar"),
+            TipoToken::Finalmente => write!(f, "finalmente"),
+            TipoToken::Lanzar => write!(f, "lanzar"),
             TipoToken::Numero(n) => write!(f, "número {}", n),
             TipoToken::Decimal(d) => write!(f, "decimal {}", d),
+            TipoToken::Medida(valor, unidad) => write!(f, "medida {}{}", valor, unidad.simbolo),
             TipoToken::Texto(s) => write!(f, "texto \"{}\"", s),
+            TipoToken::TextoInterpolado(_) => write!(f, "texto con interpolación"),
             TipoToken::Identificador(id) => write!(f, "identificador '{}'", id),
+            TipoToken::Documentacion(_) => write!(f, "comentario de documentación"),
+            TipoToken::Error(texto) => write!(f, "'{}' no válido", texto),
+            TipoToken::Suma => write!(f, "'+'"),
+            TipoToken::Resta => write!(f, "'-'"),
+            TipoToken::Multiplicacion => write!(f, "'*'"),
+            TipoToken::Division => write!(f, "'/'"),
+            TipoToken::Modulo => write!(f, "'%'"),
+            TipoToken::Asignacion => write!(f, "'='"),
+            TipoToken::Igual => write!(f, "'=='"),
+            TipoToken::Diferente => write!(f, "'!='"),
+            TipoToken::Menor => write!(f, "'<'"),
+            TipoToken::MenorIgual => write!(f, "'<='"),
+            TipoToken::Mayor => write!(f, "'>'"),
+            TipoToken::MayorIgual => write!(f, "'>='"),
+            TipoToken::ParentesisAbre => write!(f, "'('"),
+            TipoToken::ParentesisCierra => write!(f, "')'"),
+            TipoToken::LlaveAbre => write!(f, "'{{'"),
+            TipoToken::LlaveCierra => write!(f, "'}}'"),
+            TipoToken::CorcheteAbre => write!(f, "'['"),
+            TipoToken::CorcheteCierra => write!(f, "']'"),
+            TipoToken::Coma => write!(f, "','"),
+            TipoToken::DosPuntos => write!(f, "':'"),
+            TipoToken::Punto => write!(f, "'.'"),
+            TipoToken::Flecha => write!(f, "'=>'"),
+            TipoToken::Arroba => write!(f, "'@'"),
+            TipoToken::Eof => write!(f, "el final del archivo"),
             _ => write!(f, "{:?}", self),
         }
     }
@@ -112,23 +207,61 @@ impl fmt::Display for TipoToken {
 pub struct Lexer {
     entrada: Vec<char>,
     posicion: usize,
-    linea: usize,
-    columna: usize,
+    linea: u32,
+    columna: u32,
+    byte: u32, // Desplazamiento en bytes de `posicion` en el texto original
+    archivo: ArchivoId,
+    idioma: Idioma,
+    pragma: Option<Posicion>, // Tramo del pragma de idioma, si lo hay
+    diagnosticos: Vec<Diagnostico>,
 }
 
 impl Lexer {
     pub fn new(entrada: &str) -> Self {
+        Lexer::con_archivo(entrada, ArchivoId::default())
+    }
+    
+    /// Crea un lexer cuyas posiciones pertenecen al archivo `archivo`
+    pub fn con_archivo(entrada: &str, archivo: ArchivoId) -> Self {
         Lexer {
             entrada: entrada.chars().collect(),
             posicion: 0,
             linea: 1,
             columna: 1,
+            byte: 0,
+            archivo,
+            idioma: Idioma::default(),
+            pragma: None,
+            diagnosticos: Vec::new(),
         }
     }
     
-    /// Tokeniza toda la entrada
-    pub fn tokenizar(&mut self) -> Result<Vec<Token>, String> {
+    /// Idioma de las palabras reservadas si el archivo no declara otro
+    pub fn fijar_idioma(&mut self, idioma: Idioma) {
+        self.idioma = idioma;
+    }
+    
+    /// Tramo del pragma de idioma del archivo, si tiene
+    pub fn pragma(&self) -> Option<&Posicion> {
+        self.pragma.as_ref()
+    }
+    
+    /// Tokeniza toda la entrada; si hay errores los devuelve todos
+    pub fn tokenizar(&mut self) -> Result<Vec<Token>, Vec<Diagnostico>> {
+        let (tokens, diagnosticos) = self.tokenizar_parcial();
+        if diagnosticos.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(diagnosticos)
+        }
+    }
+    
+    /// Tokeniza toda la entrada sin detenerse en los errores. Devuelve los
+    /// tokens, con tokens `Error` donde había caracteres no válidos, junto
+    /// con todos los diagnósticos léxicos.
+    pub fn tokenizar_parcial(&mut self) -> (Vec<Token>, Vec<Diagnostico>) {
         let mut tokens = Vec::new();
+        self.leer_pragma();
         
         loop {
             self.saltar_espacios_y_comentarios();
@@ -142,15 +275,21 @@ impl Lexer {
                 break;
             }
             
-            let token = self.siguiente_token()?;
+            // El tramo del token acaba donde se quedó el lexer
+            let mut token = self.siguiente_token();
+            token.pos = token.pos.hasta(&self.posicion_actual());
             tokens.push(token);
         }
         
-        Ok(tokens)
+        (tokens, std::mem::take(&mut self.diagnosticos))
+    }
+    
+    fn reportar(&mut self, diagnostico: Diagnostico) {
+        self.diagnosticos.push(diagnostico);
     }
     
     /// Obtiene el siguiente token
-    fn siguiente_token(&mut self) -> Result<Token, String> {
+    fn siguiente_token(&mut self) -> Token {
         let pos = self.posicion_actual();
         let c = self.actual();
         
@@ -158,112 +297,172 @@ impl Lexer {
             // Operadores y delimitadores de un carácter
             '+' => {
                 self.avanzar();
-                Ok(Token::new(TipoToken::Suma, "+".to_string(), pos))
+                Token::new(TipoToken::Suma, "+".to_string(), pos)
             }
             '-' => {
                 self.avanzar();
-                Ok(Token::new(TipoToken::Resta, "-".to_string(), pos))
+                Token::new(TipoToken::Resta, "-".to_string(), pos)
             }
             '*' => {
                 self.avanzar();
-                Ok(Token::new(TipoToken::Multiplicacion, "*".to_string(), pos))
+                Token::new(TipoToken::Multiplicacion, "*".to_string(), pos)
             }
+            '/' if self.empieza_por("///") => self.leer_documentacion(),
             '/' => {
                 self.avanzar();
-                Ok(Token::new(TipoToken::Division, "/".to_string(), pos))
+                Token::new(TipoToken::Division, "/".to_string(), pos)
             }
             '%' => {
                 self.avanzar();
-                Ok(Token::new(TipoToken::Modulo, "%".to_string(), pos))
+                Token::new(TipoToken::Modulo, "%".to_string(), pos)
             }
             '(' => {
                 self.avanzar();
-                Ok(Token::new(TipoToken::ParentesisAbre, "(".to_string(), pos))
+                Token::new(TipoToken::ParentesisAbre, "(".to_string(), pos)
             }
             ')' => {
                 self.avanzar();
-                Ok(Token::new(TipoToken::ParentesisCierra, ")".to_string(), pos))
+                Token::new(TipoToken::ParentesisCierra, ")".to_string(), pos)
             }
             '{' => {
                 self.avanzar();
-                Ok(Token::new(TipoToken::LlaveAbre, "{".to_string(), pos))
+                Token::new(TipoToken::LlaveAbre, "{".to_string(), pos)
             }
             '}' => {
                 self.avanzar();
-                Ok(Token::new(TipoToken::LlaveCierra, "}".to_string(), pos))
+                Token::new(TipoToken::LlaveCierra, "}".to_string(), pos)
+            }
+            '[' => {
+                self.avanzar();
+                Token::new(TipoToken::CorcheteAbre, "[".to_string(), pos)
+            }
+            ']' => {
+                self.avanzar();
+                Token::new(TipoToken::CorcheteCierra, "]".to_string(), pos)
             }
             ',' => {
                 self.avanzar();
-                Ok(Token::new(TipoToken::Coma, ",".to_string(), pos))
+                Token::new(TipoToken::Coma, ",".to_string(), pos)
             }
             ':' => {
                 self.avanzar();
-                Ok(Token::new(TipoToken::DosPuntos, ":".to_string(), pos))
+                Token::new(TipoToken::DosPuntos, ":".to_string(), pos)
             }
             '.' => {
                 self.avanzar();
-                Ok(Token::new(TipoToken::Punto, ".".to_string(), pos))
+                Token::new(TipoToken::Punto, ".".to_string(), pos)
+            }
+            '@' => {
+                self.avanzar();
+                Token::new(TipoToken::Arroba, "@".to_string(), pos)
             }
             
             // Operadores de dos caracteres
             '=' => {
                 self.avanzar();
                 if self.coincidir('=') {
-                    Ok(Token::new(TipoToken::Igual, "==".to_string(), pos))
+                    Token::new(TipoToken::Igual, "==".to_string(), pos)
+                } else if self.coincidir('>') {
+                    Token::new(TipoToken::Flecha, "=>".to_string(), pos)
                 } else {
-                    Ok(Token::new(TipoToken::Asignacion, "=".to_string(), pos))
+                    Token::new(TipoToken::Asignacion, "=".to_string(), pos)
                 }
             }
             '!' => {
                 self.avanzar();
                 if self.coincidir('=') {
-                    Ok(Token::new(TipoToken::Diferente, "!=".to_string(), pos))
+                    Token::new(TipoToken::Diferente, "!=".to_string(), pos)
                 } else {
-                    Err(format!("Carácter inesperado '!' en {}", pos))
+                    // Se trata como `no` para seguir analizando
+                    self.reportar(
+                        Diagnostico::new(Codigo::CaracterInesperado, "'!' no es un operador de V-Code", &pos)
+                            .con_sugerencia("usa 'no' para negar o '!=' para comparar"),
+                    );
+                    Token::new(TipoToken::No, "!".to_string(), pos)
                 }
             }
             '<' => {
                 self.avanzar();
                 if self.coincidir('=') {
-                    Ok(Token::new(TipoToken::MenorIgual, "<=".to_string(), pos))
+                    Token::new(TipoToken::MenorIgual, "<=".to_string(), pos)
                 } else {
-                    Ok(Token::new(TipoToken::Menor, "<".to_string(), pos))
+                    Token::new(TipoToken::Menor, "<".to_string(), pos)
                 }
             }
             '>' => {
                 self.avanzar();
                 if self.coincidir('=') {
-                    Ok(Token::new(TipoToken::MayorIgual, ">=".to_string(), pos))
+                    Token::new(TipoToken::MayorIgual, ">=".to_string(), pos)
                 } else {
-                    Ok(Token::new(TipoToken::Mayor, ">".to_string(), pos))
+                    Token::new(TipoToken::Mayor, ">".to_string(), pos)
                 }
             }
             
             // Strings
-            '"' => self.leer_string(),
+            '"' => self.leer_string(&['"']),
+            
+            // Comillas tipográficas pegadas desde un documento: se leen como
+            // texto para no arrastrar errores
+            '“' | '”' | '„' | '«' | '‘' | '’' => {
+                let cierres: &[char] = match c {
+                    '‘' | '’' => &['’', '‘'],
+                    '«' => &['»'],
+                    _ => &['”', '“', '"'],
+                };
+                self.reportar(
+                    Diagnostico::new(
+                        Codigo::CaracterInesperado,
+                        format!("Comilla tipográfica '{}' en lugar de comillas rectas", c),
+                        &pos,
+                    )
+                    .con_nota("suele ocurrir al copiar código desde un procesador de textos")
+                    .con_sugerencia("escribe los textos entre comillas dobles rectas: \"así\""),
+                );
+                self.leer_string(cierres)
+            }
             
             // Números
             '0'..='9' => self.leer_numero(),
             
             // Identificadores y palabras reservadas
-            'a'..='z' | 'A'..='Z' | '_' => self.leer_identificador(),
+            c if es_inicio_de_identificador(c) => self.leer_identificador(),
             
-            _ => Err(format!("Carácter inesperado '{}' en {}", c, pos)),
+            _ => {
+                // Los caracteres no válidos seguidos forman un único error
+                let mut texto = String::new();
+                while !self.fin() && !self.actual().is_whitespace() && !es_inicio_de_token(self.actual()) {
+                    texto.push(self.actual());
+                    self.avanzar();
+                }
+                self.reportar(Diagnostico::new(
+                    Codigo::CaracterInesperado,
+                    format!("Carácter inesperado '{}'", texto),
+                    &pos.hasta(&self.posicion_actual()),
+                ));
+                Token::new(TipoToken::Error(texto.clone()), texto, pos)
+            }
         }
     }
     
-    /// Lee un string
-    fn leer_string(&mut self) -> Result<Token, String> {
+    /// Lee un texto hasta uno de los caracteres de `cierres`. Un texto sin
+    /// cerrar se informa y, si ocupaba varias líneas, se corta al final de
+    /// la primera, que es donde casi siempre faltaba la comilla. Cada `{...}`
+    /// del texto se lee como los tokens de una expresión interpolada.
+    fn leer_string(&mut self, cierres: &[char]) -> Token {
         let pos = self.posicion_actual();
+        let inicio = self.posicion;
         self.avanzar(); // Saltar comilla inicial
         
+        let mut partes = Vec::new();
         let mut valor = String::new();
+        let mut primer_salto = None; // Estado del lexer y texto leído al primer '\n'
         
-        while !self.fin() && self.actual() != '"' {
+        while !self.fin() && !cierres.contains(&self.actual()) {
             if self.actual() == '\\' {
+                let barra = self.posicion_actual();
                 self.avanzar();
                 if self.fin() {
-                    return Err(format!("String sin terminar en {}", pos));
+                    break;
                 }
                 
                 // Escapes básicos
@@ -272,11 +471,36 @@ impl Lexer {
                     't' => valor.push('\t'),
                     '\\' => valor.push('\\'),
                     '"' => valor.push('"'),
+                    '{' => valor.push('{'),
+                    '}' => valor.push('}'),
+                    'u' => {
+                        valor.extend(self.leer_escape_unicode(&barra));
+                        continue;
+                    }
                     _ => {
                         valor.push('\\');
                         valor.push(self.actual());
                     }
                 }
+            } else if self.actual() == '{' {
+                let apertura = self.posicion_actual();
+                self.avanzar();
+                if !valor.is_empty() {
+                    partes.push(ParteDeTexto::Literal(std::mem::take(&mut valor)));
+                }
+                if let Some(tokens) = self.leer_interpolacion(apertura, cierres) {
+                    partes.push(ParteDeTexto::Codigo(tokens));
+                }
+                continue;
+            } else if self.actual() == '\n' {
+                if primer_salto.is_none() {
+                    primer_salto = Some((self.posicion, self.posicion_actual(), partes.len(), valor.clone()));
+                }
+                valor.push('\n');
+                self.avanzar();
+                self.linea += 1;
+                self.columna = 1;
+                continue;
             } else {
                 valor.push(self.actual());
             }
@@ -284,93 +508,366 @@ impl Lexer {
         }
         
         if self.fin() {
-            return Err(format!("String sin terminar en {}", pos));
+            let mut error = Diagnostico::new(
+                Codigo::TextoSinTerminar,
+                "String sin terminar: llega hasta el final del archivo",
+                &pos,
+            );
+            if let Some((posicion, fin_de_linea, num_partes, texto)) = primer_salto {
+                error = error.con_etiqueta(&fin_de_linea, "¿falta la comilla de cierre aquí?");
+                self.posicion = posicion;
+                self.linea = fin_de_linea.linea;
+                self.columna = fin_de_linea.columna;
+                self.byte = fin_de_linea.byte;
+                partes.truncate(num_partes);
+                valor = texto;
+            }
+            self.reportar(error);
+        } else {
+            self.avanzar(); // Saltar comilla final
         }
         
-        self.avanzar(); // Saltar comilla final
+        if partes.is_empty() {
+            return Token::new(
+                TipoToken::Texto(valor.clone()),
+                format!("\"{}\"", valor),
+                pos,
+            );
+        }
+        if !valor.is_empty() {
+            partes.push(ParteDeTexto::Literal(valor));
+        }
+        let lexema = self.entrada[inicio..self.posicion].iter().collect();
+        Token::new(TipoToken::TextoInterpolado(partes), lexema, pos)
+    }
+    
+    /// Lee un escape `\u{1F600}` ya pasada la barra, con el código del
+    /// carácter en hexadecimal (de 1 a 6 dígitos)
+    fn leer_escape_unicode(&mut self, barra: &Posicion) -> Option<char> {
+        self.avanzar(); // Saltar 'u'
+        if self.fin() || self.actual() != '{' {
+            self.reportar(
+                Diagnostico::new(Codigo::EscapeInvalido, "Se esperaba '{' tras '\\u'", &barra.hasta(&self.posicion_actual()))
+                    .con_sugerencia("escribe el código del carácter entre llaves, como en \\u{00F1}"),
+            );
+            return None;
+        }
+        self.avanzar();
         
-        Ok(Token::new(
-            TipoToken::Texto(valor.clone()),
-            format!("\"{}\"", valor),
-            pos,
-        ))
+        let mut codigo = String::new();
+        while !self.fin() && self.actual().is_ascii_hexdigit() {
+            codigo.push(self.actual());
+            self.avanzar();
+        }
+        if self.fin() || self.actual() != '}' {
+            self.reportar(Diagnostico::new(
+                Codigo::EscapeInvalido,
+                "Falta '}' para cerrar el escape '\\u{...}'",
+                &barra.hasta(&self.posicion_actual()),
+            ));
+            return None;
+        }
+        self.avanzar();
+        
+        let tramo = barra.hasta(&self.posicion_actual());
+        if codigo.is_empty() || codigo.len() > 6 {
+            self.reportar(Diagnostico::new(
+                Codigo::EscapeInvalido,
+                "El escape '\\u{...}' lleva de 1 a 6 dígitos hexadecimales",
+                &tramo,
+            ));
+            return None;
+        }
+        let caracter = u32::from_str_radix(&codigo, 16).ok().and_then(char::from_u32);
+        if caracter.is_none() {
+            self.reportar(
+                Diagnostico::new(
+                    Codigo::EscapeInvalido,
+                    format!("'{}' no es un carácter Unicode válido", codigo),
+                    &tramo,
+                )
+                .con_nota("los códigos van de 0 a 10FFFF, sin los sustitutos D800 a DFFF"),
+            );
+        }
+        caracter
+    }
+    
+    /// Lee los tokens de la expresión de un `{...}` dentro de un texto, ya
+    /// pasada la `{`, terminados en `Eof`. Devuelve `None` si está vacía.
+    /// La expresión puede llevar textos (`{m["oro"]}`) y llaves
+    /// (`{ {"a": 1}["a"] }`). Un salto de línea, o una comilla que no abre
+    /// un texto anidado, antes de la `}` la dan por cerrada.
+    fn leer_interpolacion(&mut self, apertura: Posicion, cierres: &[char]) -> Option<Vec<Token>> {
+        let mut tokens = Vec::new();
+        let mut llaves = 0; // `{` abiertas dentro de la expresión
+        loop {
+            while !self.fin() && matches!(self.actual(), ' ' | '\t') {
+                self.avanzar();
+            }
+            let cierra = !self.fin() && cierres.contains(&self.actual()) && !self.abre_texto_anidado();
+            if self.fin() || self.actual() == '\n' || cierra {
+                self.reportar(
+                    Diagnostico::new(
+                        Codigo::InterpolacionInvalida,
+                        "Falta '}' para cerrar la interpolación",
+                        &apertura,
+                    )
+                    .con_etiqueta(&self.posicion_actual(), "se esperaba '}' aquí")
+                    .con_sugerencia("escribe '\\{' para mostrar una llave literal"),
+                );
+                break;
+            }
+            if self.actual() == '}' && llaves == 0 {
+                self.avanzar();
+                break;
+            }
+            let mut token = self.siguiente_token();
+            match token.tipo {
+                TipoToken::LlaveAbre => llaves += 1,
+                TipoToken::LlaveCierra => llaves -= 1,
+                _ => {}
+            }
+            token.pos = token.pos.hasta(&self.posicion_actual());
+            tokens.push(token);
+        }
+        
+        if tokens.is_empty() {
+            self.reportar(
+                Diagnostico::new(
+                    Codigo::InterpolacionInvalida,
+                    "Interpolación vacía: falta la expresión entre '{' y '}'",
+                    &apertura.hasta(&self.posicion_actual()),
+                )
+                .con_sugerencia("escribe '\\{' y '\\}' para mostrar llaves literales"),
+            );
+            return None;
+        }
+        tokens.push(Token::new(TipoToken::Eof, String::new(), self.posicion_actual()));
+        Some(tokens)
+    }
+    
+    /// Si la comilla actual, dentro de una interpolación, abre un texto
+    /// anidado en vez de cerrar el de fuera: lo abre si en la misma línea
+    /// tiene su comilla de cierre y, después, la `}` de la interpolación
+    fn abre_texto_anidado(&self) -> bool {
+        if self.actual() != '"' {
+            return false;
+        }
+        let resto = self.entrada[self.posicion + 1..].iter().take_while(|c| **c != '\n');
+        let mut escapado = false;
+        let mut cerrado = false;
+        for &c in resto {
+            match c {
+                '}' if cerrado => return true,
+                _ if cerrado => {}
+                _ if escapado => escapado = false,
+                '\\' => escapado = true,
+                '"' => cerrado = true,
+                _ => {}
+            }
+        }
+        false
     }
     
-    /// Lee un número
-    fn leer_numero(&mut self) -> Result<Token, String> {
+    /// Lee un número: entero decimal, hexadecimal (`0xFF`) o binario
+    /// (`0b1010`), o decimal con parte fraccionaria y/o exponente (`1.5e-3`).
+    /// Los dígitos pueden separarse con `_` (`1_000_000`), y un número en
+    /// base diez puede llevar una unidad pegada (`1.5m`, `45grados`).
+    fn leer_numero(&mut self) -> Token {
         let pos = self.posicion_actual();
-        let mut numero = String::new();
+        let inicio = self.posicion;
         
-        while !self.fin() && self.actual().is_ascii_digit() {
-            numero.push(self.actual());
+        let base = match (self.actual(), self.siguiente()) {
+            ('0', Some('x' | 'X')) => 16,
+            ('0', Some('b' | 'B')) => 2,
+            _ => 10,
+        };
+        if base != 10 {
+            self.avanzar();
             self.avanzar();
         }
         
-        // Verificar si es decimal
-        if !self.fin() && self.actual() == '.' && self.siguiente().is_some() && self.siguiente().unwrap().is_ascii_digit() {
-            numero.push('.');
-            self.avanzar();
-            
-            while !self.fin() && self.actual().is_ascii_digit() {
-                numero.push(self.actual());
+        let mut numero = self.leer_digitos(base);
+        let mut es_decimal = false;
+        
+        if base == 10 {
+            // Parte fraccionaria
+            if !self.fin() && self.actual() == '.' && self.siguiente().is_some_and(|c| c.is_ascii_digit()) {
                 self.avanzar();
+                numero.push('.');
+                numero.push_str(&self.leer_digitos(10));
+                es_decimal = true;
             }
             
-            let valor: f64 = numero.parse().map_err(|_| {
-                format!("Número decimal inválido '{}' en {}", numero, pos)
-            })?;
-            
-            Ok(Token::new(TipoToken::Decimal(valor), numero, pos))
+            // Exponente: `e` seguida de dígitos, con signo opcional
+            if !self.fin() && matches!(self.actual(), 'e' | 'E') {
+                let signo = matches!(self.siguiente(), Some('+' | '-'));
+                let primer_digito = self.entrada.get(self.posicion + 1 + signo as usize);
+                if primer_digito.is_some_and(|c| c.is_ascii_digit()) {
+                    self.avanzar();
+                    numero.push('e');
+                    if signo {
+                        numero.push(self.actual());
+                        self.avanzar();
+                    }
+                    numero.push_str(&self.leer_digitos(10));
+                    es_decimal = true;
+                }
+            }
+        }
+        
+        // Unidad de medida pegada al número
+        let mut unidad = None;
+        if base == 10 && !self.fin() && es_inicio_de_identificador(self.actual()) {
+            let simbolo = self.leer_palabra();
+            unidad = unidades::buscar(&simbolo);
+            if unidad.is_none() {
+                self.reportar(
+                    Diagnostico::new(
+                        Codigo::UnidadDesconocida,
+                        format!("Unidad desconocida '{}'", simbolo),
+                        &pos.hasta(&self.posicion_actual()),
+                    )
+                    .con_nota(format!("las unidades disponibles son: {}", unidades::simbolos()))
+                    .con_sugerencia("si es un nombre, sepáralo del número con un espacio"),
+                );
+            }
+        }
+        
+        let lexema: String = self.entrada[inicio..self.posicion].iter().collect();
+        let tramo = pos.hasta(&self.posicion_actual());
+        
+        if lexema.ends_with('_') {
+            self.reportar(
+                Diagnostico::new(
+                    Codigo::NumeroInvalido,
+                    format!("Separador '_' al final del número '{}'", lexema),
+                    &tramo,
+                )
+                .con_sugerencia("el separador solo puede ir entre dígitos, como en 1_000"),
+            );
+        }
+        
+        if numero.is_empty() {
+            let prefijo = if base == 16 { "hexadecimal" } else { "binario" };
+            self.reportar(Diagnostico::new(
+                Codigo::NumeroInvalido,
+                format!("Número {} sin dígitos '{}'", prefijo, lexema),
+                &tramo,
+            ));
+            return Token::new(TipoToken::Numero(0), lexema, pos);
+        }
+        
+        if es_decimal || unidad.is_some() {
+            let valor: f64 = numero.parse().unwrap_or(f64::INFINITY);
+            if valor.is_infinite() {
+                self.reportar(
+                    Diagnostico::new(
+                        Codigo::NumeroInvalido,
+                        format!("El número decimal '{}' es demasiado grande", lexema),
+                        &tramo,
+                    )
+                    .con_nota(format!("el mayor decimal representable es {:e}", f64::MAX)),
+                );
+            }
+            let tipo = match unidad {
+                Some(unidad) => TipoToken::Medida(valor, unidad),
+                None => TipoToken::Decimal(valor),
+            };
+            Token::new(tipo, lexema, pos)
         } else {
-            let valor: i64 = numero.parse().map_err(|_| {
-                format!("Número entero inválido '{}' en {}", numero, pos)
-            })?;
-            
-            Ok(Token::new(TipoToken::Numero(valor), numero, pos))
+            let valor = i64::from_str_radix(&numero, base).unwrap_or_else(|_| {
+                self.reportar(
+                    Diagnostico::new(
+                        Codigo::NumeroInvalido,
+                        format!("El número entero '{}' no cabe en 64 bits", lexema),
+                        &tramo,
+                    )
+                    .con_nota(format!("el mayor entero representable es {}", i64::MAX))
+                    .con_sugerencia("escribe un decimal, como 1.0e20, si necesitas valores más grandes"),
+                );
+                0
+            });
+            Token::new(TipoToken::Numero(valor), lexema, pos)
         }
     }
     
-    /// Lee un identificador o palabra reservada
-    fn leer_identificador(&mut self) -> Result<Token, String> {
+    /// Lee dígitos de la base `base` y separadores `_`, y devuelve solo los
+    /// dígitos. Un `_` solo forma parte del número tras un dígito.
+    fn leer_digitos(&mut self, base: u32) -> String {
+        let mut digitos = String::new();
+        while !self.fin() {
+            let c = self.actual();
+            if c.is_digit(base) {
+                digitos.push(c);
+            } else if c != '_' || (digitos.is_empty() && base == 10) {
+                break;
+            }
+            self.avanzar();
+        }
+        digitos
+    }
+    
+    /// Lee un identificador o una palabra reservada del idioma del archivo
+    fn leer_identificador(&mut self) -> Token {
         let pos = self.posicion_actual();
-        let mut id = String::new();
+        let id = self.leer_palabra();
         
-        while !self.fin() && (self.actual().is_alphanumeric() || self.actual() == '_') {
-            id.push(self.actual());
+        let tipo = self
+            .idioma
+            .palabra_reservada(&id)
+            .unwrap_or_else(|| TipoToken::Identificador(id.clone()));
+        
+        Token::new(tipo, id, pos)
+    }
+    
+    /// Lee una palabra. Sigue Unicode XID y se normaliza a NFC, así que
+    /// `área` escrito con tilde compuesta o combinada es el mismo nombre.
+    fn leer_palabra(&mut self) -> String {
+        let mut palabra = String::new();
+        while !self.fin() && is_xid_continue(self.actual()) {
+            palabra.push(self.actual());
             self.avanzar();
         }
+        palabra.nfc().collect()
+    }
+    
+    /// Lee el pragma de idioma (`@idioma en`, `@language en`) si el archivo
+    /// empieza por él, y fija con él las palabras reservadas del archivo
+    fn leer_pragma(&mut self) {
+        self.saltar_espacios_y_comentarios();
+        if self.fin() || self.actual() != '@' {
+            return;
+        }
         
-        let tipo = match id.as_str() {
-            "escena" => TipoToken::Escena,
-            "mostrar" => TipoToken::Mostrar,
-            "var" => TipoToken::Var,
-            "constante" => TipoToken::Constante,
-            "si" => TipoToken::Si,
-            "sino" => TipoToken::Sino,
-            "mientras" => TipoToken::Mientras,
-            "para" => TipoToken::Para,
-            "funcion" => TipoToken::Funcion,
-            "retornar" => TipoToken::Retornar,
-            "verdadero" => TipoToken::Verdadero,
-            "falso" => TipoToken::Falso,
-            "cada" => TipoToken::Cada,
-            "frame" => TipoToken::Frame,
-            "cuando" => TipoToken::Cuando,
-            "y" => TipoToken::Y,
-            "o" => TipoToken::O,
-            "no" => TipoToken::No,
-            "numero" => TipoToken::TipoNumero,
-            "decimal" => TipoToken::TipoDecimal,
-            "texto" => TipoToken::TipoTexto,
-            "booleano" => TipoToken::TipoBooleano,
-            "vector3" => TipoToken::TipoVector3,
-            "pose" => TipoToken::TipoPose,
-            "mano" => TipoToken::TipoMano,
-            "controlador" => TipoToken::TipoControlador,
-            _ => TipoToken::Identificador(id.clone()),
-        };
+        let inicio = self.posicion_actual();
+        let posicion = self.posicion;
+        self.avanzar();
+        if !matches!(self.leer_palabra().as_str(), "idioma" | "language") {
+            // Es un atributo (`@inicial`): se deja para el parser
+            self.posicion = posicion;
+            self.columna = inicio.columna;
+            self.byte = inicio.byte;
+            return;
+        }
         
-        Ok(Token::new(tipo, id, pos))
+        while !self.fin() && matches!(self.actual(), ' ' | '\t') {
+            self.avanzar();
+        }
+        let pos_idioma = self.posicion_actual();
+        let codigo = self.leer_palabra();
+        match Idioma::buscar(&codigo) {
+            Some(idioma) => self.idioma = idioma,
+            None => self.reportar(
+                Diagnostico::new(
+                    Codigo::IdiomaDesconocido,
+                    format!("Idioma desconocido '{}'", codigo),
+                    &pos_idioma.hasta(&self.posicion_actual()),
+                )
+                .con_sugerencia("usa 'es' para español o 'en' para inglés"),
+            ),
+        }
+        self.pragma = Some(inicio.hasta(&self.posicion_actual()));
     }
     
     /// Salta espacios en blanco y comentarios
@@ -385,17 +882,77 @@ impl Lexer {
                     self.linea += 1;
                     self.columna = 1;
                 }
+                // `///` es documentación y se convierte en token; `////` no
+                '/' if self.empieza_por("///") && !self.empieza_por("////") => break,
                 '/' if self.siguiente() == Some('/') => {
                     // Comentario de línea
                     while !self.fin() && self.actual() != '\n' {
                         self.avanzar();
                     }
                 }
+                '/' if self.siguiente() == Some('*') => self.saltar_comentario_de_bloque(),
                 _ => break,
             }
         }
     }
     
+    /// Salta un comentario `/* ... */`, que puede contener otros anidados.
+    /// Si no se cierra se informa en su apertura y en la de cada comentario
+    /// anidado que sigue abierto.
+    fn saltar_comentario_de_bloque(&mut self) {
+        let mut abiertos = Vec::new();
+        while !self.fin() {
+            if self.empieza_por("/*") {
+                abiertos.push(self.posicion_actual());
+                self.avanzar();
+                self.avanzar();
+            } else if self.empieza_por("*/") {
+                self.avanzar();
+                self.avanzar();
+                abiertos.pop();
+                if abiertos.is_empty() {
+                    return;
+                }
+            } else if self.actual() == '\n' {
+                self.avanzar();
+                self.linea += 1;
+                self.columna = 1;
+            } else {
+                self.avanzar();
+            }
+        }
+        
+        let mut error = Diagnostico::new(
+            Codigo::ComentarioSinTerminar,
+            "Comentario '/*' sin terminar: llega hasta el final del archivo",
+            &abiertos[0],
+        );
+        for anidado in &abiertos[1..] {
+            error = error.con_etiqueta(anidado, "este comentario anidado tampoco se cierra");
+        }
+        self.reportar(error.con_nota("los comentarios '/* */' se anidan: cada '/*' necesita su '*/'"));
+    }
+    
+    /// Lee una línea de documentación `///`
+    fn leer_documentacion(&mut self) -> Token {
+        let pos = self.posicion_actual();
+        let mut linea = String::new();
+        while !self.fin() && self.actual() != '\n' {
+            linea.push(self.actual());
+            self.avanzar();
+        }
+        let texto = linea[3..].strip_prefix(' ').unwrap_or(&linea[3..]).trim_end().to_string();
+        Token::new(TipoToken::Documentacion(texto), linea, pos)
+    }
+    
+    /// Si el texto desde la posición actual empieza por `texto`
+    fn empieza_por(&self, texto: &str) -> bool {
+        texto
+            .chars()
+            .enumerate()
+            .all(|(i, c)| self.entrada.get(self.posicion + i) == Some(&c))
+    }
+    
     /// Obtiene el carácter actual
     fn actual(&self) -> char {
         self.entrada[self.posicion]
@@ -413,6 +970,7 @@ impl Lexer {
     /// Avanza una posición
     fn avanzar(&mut self) {
         if !self.fin() {
+            self.byte += self.actual().len_utf8() as u32;
             self.posicion += 1;
             self.columna += 1;
         }
@@ -435,6 +993,22 @@ impl Lexer {
     
     /// Obtiene la posición actual
     fn posicion_actual(&self) -> Posicion {
-        Posicion::new(self.linea, self.columna)
+        Posicion::punto(self.archivo, self.linea, self.columna, self.byte)
     }
 }
+
+/// Si `c` puede empezar un token; el resto de caracteres no son válidos
+/// fuera de textos y comentarios
+fn es_inicio_de_token(c: char) -> bool {
+    matches!(
+        c,
+        '+' | '-' | '*' | '/' | '%' | '(' | ')' | '{' | '}' | '[' | ']' | ',' | ':' | '.' | '@'
+            | '=' | '!' | '<' | '>'
+            | '"' | '“' | '”' | '„' | '«' | '‘' | '’'
+            | '0'..='9'
+    ) || es_inicio_de_identificador(c)
+}
+
+fn es_inicio_de_identificador(c: char) -> bool {
+    c == '_' || is_xid_start(c)
+}
@@ -1,43 +1,66 @@
 // main.rs - Punto de entrada del compilador V-Code
 
 mod ast;
+mod diagnostico;
+mod idiomas;
+mod unidades;
 mod lexer;
+mod traductor;
 mod parser;
+mod semantico;
 mod ejecutor;
+mod colecciones;
+mod eventos;
+mod maquinas;
+mod planificador;
 
 use std::env;
 use std::fs;
 use std::process;
 
+use diagnostico::{Codigo, Diagnostico};
+use idiomas::Idioma;
 use lexer::Lexer;
 use parser::Parser;
 use ejecutor::Ejecutor;
 
 fn main() {
-    // Banner
-    println!("🚀 V-Code v0.1.0 - Lenguaje de Programación para VR");
-    println!("   Desarrollado por Paolo Gonzalez");
-    println!("   https://github.com/PaoloGonzalez776/V-Code");
-    println!();
-    
     // Obtener argumentos
     let args: Vec<String> = env::args().collect();
+    let comando = leer_argumentos(&args[1..]);
     
-    if args.len() < 2 {
-        eprintln!("❌ Error: No se especificó archivo de entrada");
-        eprintln!();
-        eprintln!("Uso: {} <archivo.vc>", args[0]);
-        eprintln!();
-        eprintln!("Ejemplo:");
-        eprintln!("  {} ejemplos/hola.vc", args[0]);
-        process::exit(1);
+    // La traducción se escribe en la salida estándar, así que va sin banner
+    if !matches!(comando, Ok(Comando::Traducir(..))) {
+        println!("🚀 V-Code v0.1.0 - Lenguaje de Programación para VR");
+        println!("   Desarrollado por Paolo Gonzalez");
+        println!("   https://github.com/PaoloGonzalez776/V-Code");
+        println!();
     }
     
-    let archivo = &args[1];
+    let (archivo, opciones) = match comando {
+        Ok(Comando::Ejecutar(archivo, opciones)) => (archivo, opciones),
+        Ok(Comando::Traducir(archivo, origen, destino)) => {
+            traducir_archivo(&archivo, origen, destino);
+            return;
+        }
+        Ok(Comando::Explicar(codigo)) => {
+            if let Err(e) = explicar(codigo.as_deref()) {
+                eprintln!("❌ Error: {}", e);
+                process::exit(1);
+            }
+            return;
+        }
+        Err(e) => {
+            eprintln!("❌ Error: {}", e);
+            eprintln!();
+            mostrar_uso(&args[0]);
+            process::exit(1);
+        }
+    };
     
     // Leer archivo
     println!("📂 Leyendo archivo: {}", archivo);
-    let codigo = match fs::read_to_string(archivo) {
+    let codigo = match fs::read_to_string(&archivo) {
         Ok(contenido) => contenido,
         Err(e) => {
             eprintln!("❌ Error al leer archivo '{}': {}", archivo, e);
@@ -46,10 +69,15 @@ fn main() {
     };
     
     // Ejecutar
-    if let Err(e) = ejecutar(&codigo) {
-        eprintln!();
-        eprintln!("❌ Error de ejecución:");
-        eprintln!("   {}", e);
+    if let Err(errores) = ejecutar_con(&codigo, &opciones) {
+        for e in &errores {
+            eprintln!();
+            eprint!("❌ {}", e.renderizar(&codigo, &archivo));
+        }
+        if errores.len() > 1 {
+            eprintln!();
+            eprintln!("❌ {} errores", errores.len());
+        }
         process::exit(1);
     }
     
@@ -57,27 +85,186 @@ fn main() {
     println!("✅ Ejecución completada exitosamente");
 }
 
-fn ejecutar(codigo: &str) -> Result<(), String> {
+/// Opciones de ejecución recibidas por línea de comandos
+#[derive(Debug, Default)]
+struct Opciones {
+    escena: Option<String>,
+    frames: Option<u64>,
+    traza_maquinas: bool,
+    idioma: Idioma, // Palabras reservadas si el archivo no declara su idioma
+}
+
+/// Lo que se pidió por línea de comandos
+#[derive(Debug)]
+enum Comando {
+    Ejecutar(String, Opciones),
+    Explicar(Option<String>), // `explicar [código]`
+    Traducir(String, Idioma, Idioma), // Archivo, idioma de origen y de destino
+}
+
+/// Interpreta los argumentos:
+/// `<archivo.vc> [--escena Nombre] [--frames N] [--traza-maquinas] [--idioma es|en]`,
+/// `explicar [código]` o `traducir <archivo.vc> <es|en> [--idioma es|en]`
+fn leer_argumentos(args: &[String]) -> Result<Comando, String> {
+    if args.first().map(String::as_str) == Some("explicar") {
+        if args.len() > 2 {
+            return Err(format!("Argumento inesperado '{}'", args[2]));
+        }
+        return Ok(Comando::Explicar(args.get(1).cloned()));
+    }
+    
+    if args.first().map(String::as_str) == Some("traducir") {
+        let mut posicionales = Vec::new();
+        let mut origen = Idioma::default();
+        let mut i = 1;
+        while i < args.len() {
+            if args[i] == "--idioma" {
+                i += 1;
+                origen = leer_idioma(args.get(i))?;
+            } else {
+                posicionales.push(&args[i]);
+            }
+            i += 1;
+        }
+        return match posicionales[..] {
+            [archivo, destino] => Ok(Comando::Traducir(archivo.clone(), origen, leer_idioma(Some(destino))?)),
+            [_] | [] => Err("Uso: traducir <archivo.vc> <es|en>".to_string()),
+            [_, _, otro, ..] => Err(format!("Argumento inesperado '{}'", otro)),
+        };
+    }
+    
+    let mut archivo = None;
+    let mut opciones = Opciones::default();
+    
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--escena" => {
+                i += 1;
+                let nombre = args.get(i).ok_or("Falta el nombre de escena después de '--escena'")?;
+                opciones.escena = Some(nombre.clone());
+            }
+            "--frames" => {
+                i += 1;
+                let frames = args.get(i).ok_or("Falta el número de frames después de '--frames'")?;
+                let frames = frames.parse().map_err(|_| {
+                    format!("Número de frames inválido '{}'", frames)
+                })?;
+                opciones.frames = Some(frames);
+            }
+            "--traza-maquinas" => opciones.traza_maquinas = true,
+            "--idioma" => {
+                i += 1;
+                opciones.idioma = leer_idioma(args.get(i))?;
+            }
+            opcion if opcion.starts_with("--") => {
+                return Err(format!("Opción desconocida '{}'", opcion));
+            }
+            ruta => {
+                if archivo.is_some() {
+                    return Err(format!("Argumento inesperado '{}'", ruta));
+                }
+                archivo = Some(ruta.to_string());
+            }
+        }
+        i += 1;
+    }
+    
+    let archivo = archivo.ok_or("No se especificó archivo de entrada")?;
+    Ok(Comando::Ejecutar(archivo, opciones))
+}
+
+/// Interpreta el código de idioma que sigue a una opción
+fn leer_idioma(codigo: Option<&String>) -> Result<Idioma, String> {
+    let codigo = codigo.ok_or("Falta el idioma: 'es' o 'en'")?;
+    Idioma::buscar(codigo).ok_or_else(|| format!("Idioma desconocido '{}': usa 'es' o 'en'", codigo))
+}
+
+/// Traduce un archivo a otro conjunto de palabras reservadas y escribe el
+/// resultado en la salida estándar
+fn traducir_archivo(archivo: &str, origen: Idioma, destino: Idioma) {
+    let codigo = match fs::read_to_string(archivo) {
+        Ok(contenido) => contenido,
+        Err(e) => {
+            eprintln!("❌ Error al leer archivo '{}': {}", archivo, e);
+            process::exit(1);
+        }
+    };
+    
+    match traductor::traducir(&codigo, origen, destino) {
+        Ok(traduccion) => print!("{}", traduccion),
+        Err(errores) => {
+            for e in &errores {
+                eprint!("❌ {}", e.renderizar(&codigo, archivo));
+            }
+            process::exit(1);
+        }
+    }
+}
+
+/// Muestra la explicación de un código de error, o la lista de códigos
+fn explicar(codigo: Option<&str>) -> Result<(), String> {
+    let Some(texto) = codigo else {
+        println!("Códigos de error:");
+        for codigo in Codigo::TODOS {
+            println!("  {}  {}", codigo.texto(), codigo.titulo());
+        }
+        return Ok(());
+    };
+    
+    let codigo = Codigo::buscar(texto)
+        .ok_or_else(|| format!("Código de error desconocido '{}'", texto))?;
+    println!("{}: {} (error {})", codigo.texto(), codigo.titulo(), codigo.fase());
+    println!();
+    println!("{}", codigo.explicacion());
+    Ok(())
+}
+
+fn mostrar_uso(programa: &str) {
+    eprintln!("Uso: {} <archivo.vc> [opciones]", programa);
+    eprintln!("     {} explicar [código]", programa);
+    eprintln!("     {} traducir <archivo.vc> <es|en>", programa);
+    eprintln!();
+    eprintln!("Opciones:");
+    eprintln!("  --escena <Nombre>   Escena de entrada (por defecto la marcada con @inicial)");
+    eprintln!("  --frames <N>        Máximo de frames a simular por escena (por defecto {})", planificador::FRAMES_MAXIMOS);
+    eprintln!("  --traza-maquinas    Muestra cada transición de las máquinas de estados");
+    eprintln!("  --idioma <es|en>    Palabras reservadas si el archivo no declara '@idioma' (por defecto es)");
+    eprintln!();
+    eprintln!("Ejemplo:");
+    eprintln!("  {} ejemplos/hola.vc", programa);
+}
+
+#[cfg(test)]
+fn ejecutar(codigo: &str) -> Result<(), Vec<Diagnostico>> {
+    ejecutar_con(codigo, &Opciones::default())
+}
+
+/// Ejecuta el código; si falla devuelve todos los errores encontrados
+fn ejecutar_con(codigo: &str, opciones: &Opciones) -> Result<(), Vec<Diagnostico>> {
     // Fase 1: Análisis léxico
     println!("🔍 Fase 1: Análisis léxico...");
     let mut lexer = Lexer::new(codigo);
-    let tokens = lexer.tokenizar().map_err(|e| {
-        format!("Error léxico: {}", e)
-    })?;
+    lexer.fijar_idioma(opciones.idioma);
+    let tokens = lexer.tokenizar()?;
     
     println!("   ✓ {} tokens generados", tokens.len());
     
     // Fase 2: Análisis sintáctico
     println!("🔍 Fase 2: Análisis sintáctico...");
     let mut parser = Parser::new(tokens);
-    let programa = parser.parsear().map_err(|e| {
-        format!("Error sintáctico: {}", e)
-    })?;
+    let programa = parser.parsear()?;
     
     println!("   ✓ AST generado correctamente");
     
-    // Fase 3: Ejecución
-    println!("🔍 Fase 3: Ejecución...");
+    // Fase 3: Análisis semántico
+    println!("🔍 Fase 3: Análisis semántico...");
+    semantico::analizar(&programa)?;
+    
+    println!("   ✓ Sin errores semánticos");
+    
+    // Fase 4: Ejecución
+    println!("🔍 Fase 4: Ejecución...");
     println!();
     println!("─────────────────────────────────");
     println!("📺 Salida del programa:");
@@ -85,9 +272,14 @@ fn ejecutar(codigo: &str) -> Result<(), String> {
     println!();
     
     let mut ejecutor = Ejecutor::new();
-    ejecutor.ejecutar(&programa).map_err(|e| {
-        format!("Error de ejecución: {}", e)
-    })?;
+    if let Some(escena) = &opciones.escena {
+        ejecutor.fijar_escena_inicial(escena);
+    }
+    if let Some(frames) = opciones.frames {
+        ejecutor.fijar_frames_maximos(frames);
+    }
+    ejecutor.fijar_traza_maquinas(opciones.traza_maquinas);
+    ejecutor.ejecutar(&programa).map_err(|e| vec![e])?;
     
     println!();
     println!("─────────────────────────────────");
@@ -98,6 +290,31 @@ fn ejecutar(codigo: &str) -> Result<(), String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ast::Declaracion;
+    use lexer::TipoToken;
+    
+    /// Ejecuta el código y devuelve la salida sin el prefijo de `mostrar`
+    fn salida(codigo: &str, escena: Option<&str>) -> Result<Vec<String>, String> {
+        diagnosticar(codigo, escena).map_err(|errores| {
+            errores.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n")
+        })
+    }
+    
+    fn diagnosticar(codigo: &str, escena: Option<&str>) -> Result<Vec<String>, Vec<Diagnostico>> {
+        let tokens = Lexer::new(codigo).tokenizar()?;
+        let programa = Parser::new(tokens).parsear()?;
+        semantico::analizar(&programa)?;
+        let mut ejecutor = Ejecutor::new();
+        if let Some(escena) = escena {
+            ejecutor.fijar_escena_inicial(escena);
+        }
+        ejecutor.ejecutar(&programa).map_err(|e| vec![e])?;
+        Ok(ejecutor
+            .obtener_salida()
+            .iter()
+            .map(|linea| linea.trim_start_matches("📺 ").to_string())
+            .collect())
+    }
     
     #[test]
     fn test_hola_mundo() {
@@ -169,4 +386,1749 @@ mod tests {
         
         assert!(ejecutar(codigo).is_ok());
     }
+    
+    #[test]
+    fn test_escena_inicial_y_transiciones() {
+        let codigo = r#"
+            escena Menu {
+                mostrar "menu"
+                al_salir {
+                    mostrar "saliendo de menu"
+                }
+            }
+            
+            @inicial
+            escena Carga {
+                mostrar "cuerpo"
+                cambiar_escena("Menu")
+                al_iniciar {
+                    mostrar "iniciada"
+                }
+                al_salir {
+                    mostrar "limpieza"
+                }
+            }
+        "#;
+        
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec!["cuerpo", "iniciada", "limpieza", "menu", "saliendo de menu"]
+        );
+        assert_eq!(salida(codigo, Some("Menu")).unwrap(), vec!["menu", "saliendo de menu"]);
+        assert!(salida(codigo, Some("Inexistente")).is_err());
+    }
+    
+    #[test]
+    fn test_cambiar_escena_desconocida() {
+        let codigo = r#"
+            escena Principal {
+                cambiar_escena("Nivel2")
+            }
+        "#;
+        
+        let error = salida(codigo, None).unwrap_err();
+        assert!(error.contains("Escena 'Nivel2' no definida"));
+    }
+    
+    #[test]
+    fn test_temporizadores() {
+        let codigo = r#"
+            escena Principal {
+                var ticks = 0
+                var frames = 0
+                
+                var latido = cada 0.5 {
+                    ticks = ticks + 1
+                    mostrar "tick " + ticks + " en " + tiempo()
+                }
+                
+                despues 1.2 {
+                    cancelar(latido)
+                    mostrar "cancelado en " + tiempo()
+                }
+                
+                var contador = cada frame {
+                    frames = frames + 1
+                    si frames == 3 {
+                        cancelar(contador)
+                        mostrar "frames: " + frames
+                    }
+                }
+            }
+        "#;
+        
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec!["frames: 3", "tick 1 en 0.5", "tick 2 en 1", "cancelado en 1.2"]
+        );
+    }
+    
+    #[test]
+    fn test_intervalo_invalido() {
+        let codigo = r#"
+            escena Principal {
+                cada 0 {
+                    mostrar "nunca"
+                }
+            }
+        "#;
+        
+        let error = salida(codigo, None).unwrap_err();
+        assert!(error.contains("Intervalo inválido para 'cada'"));
+        assert!(error.contains("línea 3, columna 22"));
+    }
+    
+    #[test]
+    fn test_corrutinas() {
+        let codigo = r#"
+            corrutina tutorial(pasos: numero) {
+                mostrar "Bienvenido"
+                esperar 1
+                para i = 1, pasos + 1 {
+                    mostrar "Paso " + i + " en " + tiempo()
+                    esperar 0.5
+                }
+                esperar hasta agarrado
+                mostrar "Agarrado en " + tiempo()
+            }
+            
+            escena Principal {
+                var agarrado = falso
+                tutorial(2)
+                
+                despues 3 {
+                    agarrado = verdadero
+                }
+            }
+        "#;
+        
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec!["Bienvenido", "Paso 1 en 1", "Paso 2 en 1.5", "Agarrado en 3"]
+        );
+    }
+    
+    #[test]
+    fn test_esperar_fuera_de_corrutina() {
+        let codigo = r#"
+            funcion lenta() {
+                esperar 1
+            }
+            
+            escena Principal {
+                lenta()
+            }
+        "#;
+        
+        let error = salida(codigo, None).unwrap_err();
+        assert!(error.contains("'esperar' solo puede usarse dentro de una corrutina"));
+    }
+    
+    #[test]
+    fn test_bus_de_eventos() {
+        let codigo = r#"
+            escena Principal {
+                emitir "puerta_abierta"("norte", 2)
+                mostrar "emitido"
+                
+                escuchar "puerta_abierta" (puerta, piso) {
+                    mostrar "primero: " + puerta + " " + piso
+                    emitir "alarma"
+                }
+                
+                escuchar "alarma" {
+                    mostrar "alarma en " + tiempo()
+                }
+                
+                escuchar "puerta_abierta" (puerta, piso) {
+                    mostrar "segundo: " + puerta
+                }
+                
+                despues 1 {
+                    emitir "alarma"
+                }
+            }
+        "#;
+        
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec![
+                "emitido",
+                "primero: norte 2",
+                "segundo: norte",
+                "alarma en 0",
+                "alarma en 1",
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_evento_con_datos_incorrectos() {
+        let codigo = r#"
+            escena Principal {
+                escuchar "golpe" (fuerza) {
+                    mostrar fuerza
+                }
+                emitir "golpe"
+            }
+        "#;
+        
+        let error = salida(codigo, None).unwrap_err();
+        assert!(error.contains("lleva 0 datos, pero el oyente en línea 3, columna 17 espera 1"));
+    }
+    
+    #[test]
+    fn test_maquina_de_estados() {
+        let codigo = r#"
+            escena Principal {
+                var cercania = 0
+                cada frame {
+                    cercania = cercania + 1
+                }
+                despues 0.1 {
+                    mostrar "estado: " + estado_de("Puerta")
+                }
+                
+                maquina Puerta {
+                    estado Cerrada {
+                        al_entrar {
+                            mostrar "cerrada"
+                        }
+                        al_salir {
+                            mostrar "saliendo de cerrada"
+                        }
+                        transicion a Abierta cuando cercania >= 3
+                    }
+                    estado Abierta {
+                        al_entrar {
+                            mostrar "abierta en frame " + cercania
+                        }
+                        cada frame {
+                            cercania = cercania + 10
+                        }
+                        transicion a Bloqueada cuando cercania > 20
+                    }
+                    estado Bloqueada {
+                        al_entrar {
+                            mostrar "bloqueada"
+                        }
+                    }
+                }
+            }
+        "#;
+        
+        let tokens = Lexer::new(codigo).tokenizar().unwrap();
+        let programa = Parser::new(tokens).parsear().unwrap();
+        let mut ejecutor = Ejecutor::new();
+        ejecutor.fijar_frames_maximos(20);
+        ejecutor.ejecutar(&programa).unwrap();
+        
+        let lineas: Vec<&str> = ejecutor
+            .obtener_salida()
+            .iter()
+            .map(|linea| linea.trim_start_matches("📺 "))
+            .collect();
+        assert_eq!(
+            lineas,
+            vec![
+                "cerrada",
+                "saliendo de cerrada",
+                "abierta en frame 3",
+                "bloqueada",
+                "estado: Bloqueada",
+            ]
+        );
+        
+        let transiciones: Vec<String> = ejecutor
+            .transiciones()
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        assert_eq!(
+            transiciones,
+            vec![
+                "[frame 3 | 0.03 s] Puerta: Cerrada → Abierta",
+                "[frame 5 | 0.06 s] Puerta: Abierta → Bloqueada",
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_maquina_invalida() {
+        let destino_desconocido = r#"
+            escena Principal {
+                maquina Npc {
+                    estado Quieto {
+                        transicion a Huir cuando verdadero
+                    }
+                }
+            }
+        "#;
+        let error = salida(destino_desconocido, None).unwrap_err();
+        assert!(error.contains("La máquina 'Npc' no tiene un estado 'Huir' en línea 5, columna 25"));
+        
+        let estado_duplicado = r#"
+            escena Principal {
+                maquina Npc {
+                    estado Quieto { }
+                    estado Quieto { }
+                }
+            }
+        "#;
+        let error = salida(estado_duplicado, None).unwrap_err();
+        assert!(error.contains("ya tiene un estado 'Quieto', duplicado en línea 5"));
+        
+        // `estado` sigue siendo un nombre válido fuera de una máquina
+        let nombre = r#"
+            escena Principal {
+                var estado = 1
+                mostrar estado
+            }
+        "#;
+        assert_eq!(salida(nombre, None).unwrap(), vec!["1"]);
+    }
+    
+    #[test]
+    fn test_diagnostico_con_codigo() {
+        let codigo = "escena Principal {\n    funcion (x) { }\n}\n";
+        let error = &diagnosticar(codigo, None).unwrap_err()[0];
+        assert_eq!(error.codigo.texto(), "E0101");
+        assert_eq!(error.fase(), diagnostico::Fase::Sintactica);
+        
+        let codigo = "escena Principal {\n    var total = 2\n    mostrar total / ceros\n}\n";
+        let error = &diagnosticar(codigo, None).unwrap_err()[0];
+        assert_eq!(error.codigo, Codigo::VariableNoDefinida);
+        assert_eq!(
+            error.renderizar(codigo, "demo.vc"),
+            "error de ejecución [E0301]: Variable 'ceros' no definida\n\
+             \x20 --> demo.vc:3:21\n\
+             \x20 |\n\
+             3 |     mostrar total / ceros\n\
+             \x20 |                     ^^^^^\n\
+             \x20 = ayuda: `vcode explicar E0301` para más detalles\n"
+        );
+    }
+    
+    #[test]
+    fn test_diagnostico_con_etiqueta() {
+        let codigo = "escena Principal {\n    escuchar \"golpe\" (fuerza) { }\n    emitir \"golpe\"\n}\n";
+        let error = &diagnosticar(codigo, None).unwrap_err()[0];
+        let renderizado = error.renderizar(codigo, "demo.vc");
+        assert!(renderizado.contains(
+            "2 |     escuchar \"golpe\" (fuerza) { }\n  |     ----------------------------- oyente registrado aquí\n"
+        ));
+        assert!(renderizado.contains("3 |     emitir \"golpe\"\n  |     ^^^^^^^^^^^^^^\n"));
+    }
+    
+    #[test]
+    fn test_explicar_codigos() {
+        let args: Vec<String> = vec!["explicar".into(), "e0102".into()];
+        match leer_argumentos(&args).unwrap() {
+            Comando::Explicar(Some(codigo)) => {
+                assert_eq!(Codigo::buscar(&codigo), Some(Codigo::NombreEsperado));
+            }
+            otro => panic!("comando inesperado: {:?}", otro),
+        }
+        
+        assert!(explicar(Some("E9999")).is_err());
+        for codigo in Codigo::TODOS {
+            assert_eq!(Codigo::buscar(codigo.texto()), Some(codigo));
+            assert!(!codigo.explicacion().is_empty());
+        }
+    }
+    
+    #[test]
+    fn test_recuperacion_de_errores_sintacticos() {
+        let codigo = r#"
+escena Principal {
+    var x =
+    mostrar "ok"
+    si x > 1 {
+        mostrar (1 +
+    }
+    var y = 3
+    mostrar y +
+}
+
+funcion sin_nombre( {
+}
+
+escena Otra {
+    mostrar "bien"
+}
+"#;
+        let tokens = Lexer::new(codigo).tokenizar().unwrap();
+        let (programa, errores) = Parser::new(tokens).parsear_parcial();
+        
+        let lineas: Vec<(u32, &str)> = errores
+            .iter()
+            .map(|e| (e.pos.as_ref().unwrap().linea, e.codigo.texto()))
+            .collect();
+        assert_eq!(lineas, vec![(4, "E0101"), (7, "E0101"), (10, "E0101"), (12, "E0102")]);
+        
+        // El programa parcial conserva lo que se pudo analizar
+        assert_eq!(programa.declaraciones.len(), 2);
+        match &programa.declaraciones[0] {
+            Declaracion::Escena(escena) => {
+                assert_eq!(escena.nombre, "Principal");
+                assert_eq!(escena.cuerpo.len(), 3);
+            }
+            otra => panic!("declaración inesperada: {:?}", otra),
+        }
+        
+        // Sin recuperación parcial, `parsear` devuelve todos los errores
+        let tokens = Lexer::new(codigo).tokenizar().unwrap();
+        assert_eq!(Parser::new(tokens).parsear().unwrap_err().len(), 4);
+    }
+    
+    #[test]
+    fn test_recuperacion_llave_sin_cerrar() {
+        let codigo = r#"
+escena Principal {
+    si verdadero {
+        mostrar 1
+}
+
+escena Otra {
+    maquina Luz {
+        estado Apagada {
+            transicion Encendida cuando verdadero
+            al_entrar { mostrar "apagada" }
+        }
+        estado Apagada { }
+    }
+}
+"#;
+        let tokens = Lexer::new(codigo).tokenizar().unwrap();
+        let (programa, errores) = Parser::new(tokens).parsear_parcial();
+        
+        let mensajes: Vec<String> = errores.iter().map(|e| e.to_string()).collect();
+        assert_eq!(mensajes.len(), 3, "{:?}", mensajes);
+        assert!(mensajes[0].contains("Se esperaba '}' al final de escena, se encontró escena en línea 7"));
+        assert!(mensajes[1].contains("Se esperaba 'a' después de 'transicion'"));
+        assert!(mensajes[2].contains("ya tiene un estado 'Apagada', duplicado en línea 13"));
+        assert_eq!(programa.declaraciones.len(), 2);
+    }
+    
+    #[test]
+    fn test_recuperacion_de_errores_lexicos() {
+        let codigo = "escena Principal {
+    var a = 3 $ 4
+    si !a { }
+    mostrar “hola”
+    var b = 99999999999999999999
+    mostrar \"sin cerrar
+    mostrar a
+}
+";
+        let (tokens, errores) = Lexer::new(codigo).tokenizar_parcial();
+        
+        let resumen: Vec<(u32, &str)> = errores
+            .iter()
+            .map(|e| (e.pos.as_ref().unwrap().linea, e.codigo.texto()))
+            .collect();
+        assert_eq!(
+            resumen,
+            vec![(2, "E0001"), (3, "E0001"), (4, "E0001"), (5, "E0003"), (6, "E0002")]
+        );
+        assert!(errores[1].to_string().contains("'!' no es un operador de V-Code"));
+        assert!(errores[2].to_string().contains("Comilla tipográfica '“'"));
+        let etiqueta = &errores[4].etiquetas[0].pos;
+        assert_eq!((etiqueta.linea, etiqueta.columna), (6, 24));
+        
+        // Los errores dejan tokens utilizables: un token de error, `!` como
+        // `no`, el texto entre comillas tipográficas y el texto sin cerrar
+        // cortado al final de su línea
+        assert!(tokens.iter().any(|t| t.tipo == TipoToken::Error("$".to_string())));
+        assert!(tokens.iter().any(|t| t.tipo == TipoToken::No));
+        assert!(tokens.iter().any(|t| t.tipo == TipoToken::Texto("hola".to_string())));
+        assert!(tokens.iter().any(|t| t.tipo == TipoToken::Texto("sin cerrar".to_string())));
+        let ultimo_mostrar = tokens.iter().rfind(|t| t.tipo == TipoToken::Mostrar).unwrap();
+        assert_eq!((ultimo_mostrar.pos.linea, ultimo_mostrar.pos.columna), (7, 5));
+        
+        let error = salida(codigo, None).unwrap_err();
+        assert_eq!(error.lines().count(), 5);
+    }
+    
+    #[test]
+    fn test_tramos_de_tokens_y_nodos() {
+        let codigo = "// señal\nfuncion area(lado: decimal) {\n    mostrar lado * 2\n    avisar(lado)\n}\n";
+        let tokens = Lexer::new(codigo).tokenizar().unwrap();
+        
+        // Los bytes apuntan al texto original aunque haya caracteres de
+        // varios bytes antes
+        let area = &tokens[1];
+        assert_eq!((area.pos.linea, area.pos.columna, area.pos.columna_fin), (2, 9, 13));
+        assert_eq!(&codigo[area.pos.byte as usize..area.pos.byte_fin as usize], "area");
+        
+        let programa = Parser::new(tokens).parsear().unwrap();
+        let Declaracion::Funcion(funcion) = &programa.declaraciones[0] else {
+            panic!("se esperaba una función");
+        };
+        let texto = |pos: &ast::Posicion| &codigo[pos.byte as usize..pos.byte_fin as usize];
+        assert_eq!(texto(&funcion.parametros[0].pos), "lado: decimal");
+        assert!(texto(&funcion.pos).starts_with("funcion area(") && texto(&funcion.pos).ends_with('}'));
+        let ast::Sentencia::Mostrar(producto, pos) = &funcion.cuerpo[0] else {
+            panic!("se esperaba 'mostrar'");
+        };
+        assert_eq!(texto(pos), "mostrar lado * 2");
+        assert_eq!(texto(producto.posicion()), "lado * 2");
+        let ast::Sentencia::Expresion(_, pos) = &funcion.cuerpo[1] else {
+            panic!("se esperaba una expresión");
+        };
+        assert_eq!(texto(pos), "avisar(lado)");
+        
+        let codigo = "escena Principal {\n    var t = 2 - \"a\"\n}\n";
+        let error = &diagnosticar(codigo, None).unwrap_err()[0];
+        assert!(error.renderizar(codigo, "demo.vc").contains(
+            "2 |     var t = 2 - \"a\"\n\
+             \x20 |             ^^^^^^^\n\
+             \x20 |             - operando izquierdo\n\
+             \x20 |                 --- operando derecho\n"
+        ));
+    }
+    
+    #[test]
+    fn test_identificadores_unicode() {
+        let codigo = r#"
+            función área(lado: número): número {
+                retornar lado * lado
+            }
+            
+            escena Principal {
+                var ñandú = 3
+                sí ñandú > 2 {
+                    mostrar área(ñandú)
+                }
+                después 1 {
+                    mostrar "fin"
+                }
+            }
+        "#;
+        assert_eq!(salida(codigo, None).unwrap(), vec!["9", "fin"]);
+        
+        // La misma letra compuesta o con tilde combinada es el mismo nombre
+        let codigo = "escena Principal {\n    var \u{e1}rea = 4\n    mostrar a\u{301}rea\n}\n";
+        assert_eq!(salida(codigo, None).unwrap(), vec!["4"]);
+        
+        // Las columnas cuentan caracteres, no bytes
+        let tokens = Lexer::new("var ñandú = 3").tokenizar().unwrap();
+        assert_eq!(tokens[1].tipo, TipoToken::Identificador("ñandú".to_string()));
+        assert_eq!((tokens[2].pos.columna, tokens[2].pos.byte), (11, 12));
+        
+        let error = salida("escena Principal {\n    mostrar €\n}\n", None).unwrap_err();
+        assert!(error.contains("Carácter inesperado '€' en línea 2, columna 13"));
+    }
+    
+    #[test]
+    fn test_palabras_reservadas_en_ingles() {
+        let codigo = r#"
+            function square(side: number): number {
+                return side * side
+            }
+            
+            scene Main {
+                var y = 3
+                if y > 2 and not false {
+                    show square(y)
+                } else {
+                    show "small"
+                }
+                machine Door {
+                    state Closed {
+                        transition to Open when y > 1
+                    }
+                    state Open {
+                        on_enter { show "open" }
+                    }
+                }
+            }
+        "#;
+        let con_pragma = format!("@language en\n{}", codigo);
+        assert_eq!(salida(&con_pragma, None).unwrap(), vec!["9", "open"]);
+        
+        // Sin pragma el idioma lo da la opción `--idioma`
+        let mut lexer = Lexer::new(codigo);
+        lexer.fijar_idioma(Idioma::Ingles);
+        assert!(lexer.tokenizar().unwrap().iter().any(|t| t.tipo == TipoToken::Funcion));
+        assert!(Lexer::new(codigo).tokenizar().unwrap().iter().all(|t| t.tipo != TipoToken::Funcion));
+        
+        // Traducir y volver deja el archivo igual; `y` sigue siendo un nombre
+        let espanol = traductor::traducir(codigo, Idioma::Ingles, Idioma::Espanol).unwrap();
+        assert!(espanol.contains("funcion square(side: numero): numero {"));
+        assert!(espanol.contains("si y > 2 y no falso {"));
+        assert!(espanol.contains("transicion a Open cuando y > 1"));
+        assert_eq!(salida(&espanol, None).unwrap(), vec!["9", "open"]);
+        let ingles = traductor::traducir(&espanol, Idioma::Espanol, Idioma::Ingles).unwrap();
+        assert_eq!(ingles, format!("@language en\n{}", codigo));
+        assert_eq!(traductor::traducir(&ingles, Idioma::Espanol, Idioma::Espanol).unwrap(), espanol);
+        
+        let errores = traductor::traducir("escena A {\n    var show = 1\n}\n", Idioma::Espanol, Idioma::Ingles)
+            .unwrap_err();
+        assert_eq!(errores[0].codigo, Codigo::NombreReservado);
+        
+        let error = salida("@idioma fr\nescena A { }\n", None).unwrap_err();
+        assert!(error.contains("[E0004] Idioma desconocido 'fr' en línea 1, columna 9"));
+        
+        let args: Vec<String> = vec!["traducir".into(), "a.vc".into(), "en".into()];
+        assert!(matches!(
+            leer_argumentos(&args).unwrap(),
+            Comando::Traducir(_, Idioma::Espanol, Idioma::Ingles)
+        ));
+    }
+    
+    #[test]
+    fn test_comentarios_de_bloque_y_documentacion() {
+        let codigo = r#"
+            /// Calcula el área de un cuadrado.
+            ///
+            /// Devuelve `lado * lado`.
+            funcion area(lado: numero): numero {
+                retornar lado /* el lado /* anidado */ al cuadrado */ * lado
+            }
+            
+            //// Comentario normal, no documentación
+            /// Escena de entrada
+            @inicial
+            escena Principal {
+                /// Aceleración en m/s²
+                constante GRAVEDAD = 9.8
+                mostrar area(3)
+                mostrar GRAVEDAD
+            }
+        "#;
+        assert_eq!(salida(codigo, None).unwrap(), vec!["9", "9.8"]);
+        
+        let tokens = Lexer::new(codigo).tokenizar().unwrap();
+        let programa = Parser::new(tokens).parsear().unwrap();
+        let Declaracion::Funcion(funcion) = &programa.declaraciones[0] else {
+            panic!("se esperaba una función");
+        };
+        assert_eq!(
+            funcion.doc.as_deref(),
+            Some("Calcula el área de un cuadrado.\n\nDevuelve `lado * lado`.")
+        );
+        let Declaracion::Escena(escena) = &programa.declaraciones[1] else {
+            panic!("se esperaba una escena");
+        };
+        assert_eq!(escena.doc.as_deref(), Some("Escena de entrada"));
+        assert!(matches!(
+            &escena.cuerpo[0],
+            ast::Sentencia::Constante(nombre, _, Some(doc), _) if nombre == "GRAVEDAD" && doc == "Aceleración en m/s²"
+        ));
+        
+        // Un comentario sin cerrar se informa en cada apertura pendiente
+        let codigo = "escena Principal {\n    /* fuera\n    /* dentro */\n    /* otro\n}\n";
+        let errores = Lexer::new(codigo).tokenizar().unwrap_err();
+        assert_eq!(errores.len(), 1);
+        assert_eq!(errores[0].codigo, Codigo::ComentarioSinTerminar);
+        assert_eq!(errores[0].pos.as_ref().unwrap().linea, 2);
+        assert_eq!(errores[0].etiquetas[0].pos.linea, 4);
+    }
+    
+    #[test]
+    fn test_asignar_a_constante() {
+        let codigo = r#"
+            escena Principal {
+                constante VIDAS = 3
+                si verdadero {
+                    VIDAS = 2
+                }
+                si verdadero {
+                    var VIDAS = 5
+                    VIDAS = 4
+                }
+                constante VIDAS = 1
+            }
+        "#;
+        let tokens = Lexer::new(codigo).tokenizar().unwrap();
+        let errores = Parser::new(tokens).parsear().unwrap_err();
+        let resumen: Vec<(u32, &str)> = errores
+            .iter()
+            .map(|e| (e.pos.as_ref().unwrap().linea, e.codigo.texto()))
+            .collect();
+        assert_eq!(resumen, vec![(5, "E0107"), (11, "E0104")]);
+        assert_eq!(errores[0].etiquetas[0].pos.linea, 3);
+    }
+    
+    #[test]
+    fn test_interpolacion_de_textos() {
+        let codigo = r#"
+            escena Principal {
+                var puntos = 7
+                var nombre = "Ana"
+                mostrar "Hola {nombre}, tienes {puntos * 2} puntos"
+                mostrar "{puntos > 5 y verdadero}"
+                mostrar "Llaves: \{literal\}"
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec!["Hola Ana, tienes 14 puntos", "verdadero", "Llaves: {literal}"]
+        );
+        
+        // Las palabras reservadas entre llaves también se traducen
+        let traducido = traductor::traducir(codigo, Idioma::Espanol, Idioma::Ingles).unwrap();
+        assert!(traducido.contains(r#"show "{puntos > 5 and true}""#));
+        
+        // Interpolación vacía, sin cerrar y con tokens de más
+        let codigo = "escena Principal {\n    mostrar \"a {} b\"\n    mostrar \"c {x d\"\n}\n";
+        let errores = Lexer::new(codigo).tokenizar().unwrap_err();
+        let resumen: Vec<(u32, &str)> = errores
+            .iter()
+            .map(|e| (e.pos.as_ref().unwrap().linea, e.codigo.texto()))
+            .collect();
+        assert_eq!(resumen, vec![(2, "E0007"), (3, "E0007")]);
+        
+        let tokens = Lexer::new("escena Principal {\n    mostrar \"{1 2}\"\n}\n").tokenizar().unwrap();
+        let errores = Parser::new(tokens).parsear().unwrap_err();
+        assert!(errores[0].mensaje.contains("expresión interpolada"));
+    }
+    
+    #[test]
+    fn test_literales_numericos() {
+        let codigo = r#"
+            escena Principal {
+                mostrar 0xFF + 0b1010
+                mostrar 1_000_000
+                mostrar 1.5e-3
+                mostrar 2E2
+                mostrar "Ma\u{F1}ana \u{1F600}"
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec!["265", "1000000", "0.0015", "200", "Mañana 😀"]
+        );
+        
+        // Cada error señala el literal completo
+        let codigo = "escena Principal {\n    var a = 9223372036854775808\n    var b = 0x\n    var c = 1_\n    var d = \"\\u{110000}\"\n}\n";
+        let errores = Lexer::new(codigo).tokenizar().unwrap_err();
+        let resumen: Vec<(&str, u32, u32)> = errores
+            .iter()
+            .map(|e| {
+                let pos = e.pos.as_ref().unwrap();
+                (e.codigo.texto(), pos.columna, pos.columna_fin)
+            })
+            .collect();
+        assert_eq!(
+            resumen,
+            vec![("E0003", 13, 32), ("E0003", 13, 15), ("E0003", 13, 15), ("E0008", 14, 24)]
+        );
+        assert!(errores[0].mensaje.contains("no cabe en 64 bits"));
+    }
+    
+    #[test]
+    fn test_unidades_de_medida() {
+        let codigo = r#"
+            escena Principal {
+                var distancia = 1.5m + 20cm
+                mostrar distancia
+                mostrar distancia / 1cm
+                mostrar 3m / 2s
+                mostrar 90grados == 0.5 * 180grados
+                mostrar 20ms < 1s
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec!["1.7m", "170", "1.5m/s", "verdadero", "verdadero"]
+        );
+        
+        // Metros con segundos, medidas con números sin unidad e
+        // intervalos que no son tiempos
+        let codigo = r#"
+            escena Principal {
+                var distancia = 2m
+                var total = distancia + 500ms
+                si distancia > 1 { }
+                distancia = 3s
+                var velocidad = distancia / 2s
+                despues 2m { }
+            }
+        "#;
+        let errores = diagnosticar(codigo, None).unwrap_err();
+        let resumen: Vec<(u32, &str)> = errores
+            .iter()
+            .map(|e| (e.pos.as_ref().unwrap().linea, e.codigo.texto()))
+            .collect();
+        assert_eq!(resumen, vec![(4, "E0201"), (5, "E0201"), (6, "E0201"), (8, "E0201")]);
+        assert_eq!(errores[0].etiquetas[1].mensaje, "tiempo (s)");
+        assert_eq!(errores[0].codigo.fase(), diagnostico::Fase::Semantica);
+        
+        let errores = Lexer::new("escena Principal {\n    var masa = 5kg\n}\n").tokenizar().unwrap_err();
+        assert_eq!(errores[0].codigo, Codigo::UnidadDesconocida);
+        assert_eq!(errores[0].pos.as_ref().unwrap().columna_fin, 19);
+    }
+    
+    #[test]
+    fn test_cadena_sino_si() {
+        let codigo = r#"
+            funcion clasificar(t: numero): texto {
+                si t < 15 {
+                    retornar "frío"
+                } sino si t < 25 {
+                    retornar "agradable"
+                } sino si t < 35 {
+                    retornar "calor"
+                } sino {
+                    retornar "extremo"
+                }
+            }
+            
+            escena Principal {
+                mostrar clasificar(10)
+                mostrar clasificar(20)
+                mostrar clasificar(30)
+                mostrar clasificar(40)
+                si falso {
+                    mostrar "nunca"
+                } sino si falso {
+                    mostrar "tampoco"
+                }
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec!["frío", "agradable", "calor", "extremo"]
+        );
+        
+        // La cadena queda plana en el AST: una rama por condición
+        let tokens = Lexer::new(codigo).tokenizar().unwrap();
+        let programa = Parser::new(tokens).parsear().unwrap();
+        let Declaracion::Funcion(funcion) = &programa.declaraciones[0] else {
+            panic!("se esperaba una función");
+        };
+        let ast::Sentencia::Si(ramas, Some(_), _) = &funcion.cuerpo[0] else {
+            panic!("se esperaba un 'si' con 'sino'");
+        };
+        let lineas: Vec<u32> = ramas.iter().map(|rama| rama.pos.linea).collect();
+        assert_eq!(lineas, vec![3, 5, 7]);
+        
+        let tokens = Lexer::new("escena P {\n    si verdadero { } sino mostrar 1\n}\n").tokenizar().unwrap();
+        let errores = Parser::new(tokens).parsear().unwrap_err();
+        assert!(errores[0].mensaje.contains("Se esperaba '{' o 'si' después de 'sino'"));
+    }
+    
+    #[test]
+    fn test_romper_y_continuar() {
+        let codigo = r#"
+            funcion primer_multiplo(n: numero): numero {
+                para i = 1, 100 {
+                    si i % n == 0 {
+                        retornar i
+                    }
+                }
+                retornar 0
+            }
+            
+            escena Principal {
+                var i = 0
+                mientras verdadero {
+                    i = i + 1
+                    si i % 2 == 0 {
+                        continuar
+                    }
+                    si i > 5 {
+                        romper
+                    }
+                    mostrar i
+                }
+                
+                externo: para a = 0, 3 {
+                    para b = 0, 3 {
+                        si b == 1 {
+                            continuar externo
+                        }
+                        si a == 2 {
+                            romper externo
+                        }
+                        mostrar "{a}{b}"
+                    }
+                }
+                mostrar primer_multiplo(7)
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec!["1", "3", "5", "00", "10", "7"]
+        );
+        
+        // También dentro de una corrutina, aunque se suspenda en el bucle
+        let codigo = r#"
+            corrutina contar() {
+                var i = 0
+                mientras verdadero {
+                    i = i + 1
+                    esperar 0
+                    si i == 2 {
+                        continuar
+                    }
+                    si i == 4 {
+                        romper
+                    }
+                    mostrar i
+                }
+                mostrar "fin"
+            }
+            
+            escena Principal {
+                contar()
+            }
+        "#;
+        assert_eq!(salida(codigo, None).unwrap(), vec!["1", "3", "fin"]);
+        
+        let codigo = r#"
+            funcion f() {
+                romper
+            }
+            
+            escena Principal {
+                mientras verdadero {
+                    continuar otro
+                }
+                externo: mientras verdadero {
+                    externo: mientras verdadero { }
+                }
+            }
+        "#;
+        let tokens = Lexer::new(codigo).tokenizar().unwrap();
+        let errores = Parser::new(tokens).parsear().unwrap_err();
+        let resumen: Vec<(u32, &str)> = errores
+            .iter()
+            .map(|e| (e.pos.as_ref().unwrap().linea, e.codigo.texto()))
+            .collect();
+        assert_eq!(resumen, vec![(3, "E0105"), (8, "E0106"), (11, "E0104")]);
+    }
+    
+    #[test]
+    fn test_para_extendido() {
+        let codigo = r#"
+            escena Principal {
+                var i = 99
+                para i = 0, 10 paso 4 {
+                    mostrar i
+                }
+                para i = 3 hasta 1 paso -1 {
+                    mostrar i
+                }
+                para x = 0 hasta 1 paso 0.25 {
+                    mostrar x
+                }
+                para d = 0m hasta 1m paso 50cm {
+                    mostrar d
+                }
+                para letra en "ñu" {
+                    mostrar letra
+                }
+                mostrar i
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec!["0", "4", "8", "3", "2", "1", "0", "0.25", "0.5", "0.75", "1", "0m", "0.5m", "1m", "ñ", "u", "99"]
+        );
+        
+        // En una corrutina la variable también se restaura al salir
+        let codigo = r#"
+            corrutina contar() {
+                var i = "antes"
+                para i = 1 hasta 3 {
+                    esperar 0
+                    si i == 2 {
+                        romper
+                    }
+                    mostrar i
+                }
+                mostrar i
+            }
+            
+            escena Principal {
+                contar()
+            }
+        "#;
+        assert_eq!(salida(codigo, None).unwrap(), vec!["1", "antes"]);
+        
+        let errores = diagnosticar("escena A {\n    para i = 0, 1 paso 0 { }\n}", None).unwrap_err();
+        assert_eq!(errores[0].codigo, Codigo::IntervaloInvalido);
+        let errores = diagnosticar("escena A {\n    para d = 0m hasta 2s { }\n}", None).unwrap_err();
+        assert_eq!(errores[0].codigo, Codigo::UnidadesIncompatibles);
+        assert_eq!(errores[0].pos.as_ref().unwrap().columna, 23);
+        
+        // `en` y `paso` se traducen solo en la cabecera del bucle
+        let codigo = "escena A {\n    var en = 2\n    var paso = en\n    para i = 0, en paso paso {\n        mostrar i\n    }\n    para c en \"ab\" { }\n}\n";
+        let ingles = traductor::traducir(codigo, Idioma::Espanol, Idioma::Ingles).unwrap();
+        assert!(ingles.contains("var en = 2\n    var paso = en\n"));
+        assert!(ingles.contains("for i = 0, en step paso {"));
+        assert!(ingles.contains("for c in \"ab\" {"));
+    }
+    
+    #[test]
+    fn test_listas() {
+        let codigo = r#"
+            funcion duplicar(valores: lista<numero>) {
+                para i = 0, longitud(valores) {
+                    valores[i] = valores[i] * 2
+                }
+            }
+            
+            escena Principal {
+                var numeros = [1, 2, 3,]
+                duplicar(numeros)
+                agregar(numeros, 10)
+                insertar(numeros, 0, -1)
+                mostrar numeros
+                mostrar quitar(numeros, 1)
+                mostrar "{longitud(numeros)} {contiene(numeros, 10)} {contiene(numeros, 2)}"
+                mostrar numeros[1:3]
+                mostrar "{numeros[:1]} {numeros[longitud(numeros) - 1]}"
+                
+                var tabla = [["a", "b"], []]
+                agregar(tabla[1], "c")
+                tabla[0][1] = "x"
+                mostrar tabla
+                mostrar "hola"[1:]
+                para fila en tabla {
+                    mostrar longitud(fila)
+                }
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec!["[-1, 2, 4, 6, 10]", "2", "4 verdadero falso", "[4, 6]", "[-1] 10", "[[\"a\", \"x\"], [\"c\"]]", "ola", "2", "1"]
+        );
+        
+        let errores = diagnosticar("escena A {\n    var l = [1, 2]\n    l[2] = 0\n}", None).unwrap_err();
+        assert_eq!(errores[0].codigo, Codigo::IndiceFueraDeRango);
+        assert_eq!(errores[0].mensaje, "Índice 2 fuera de rango: hay 2 elementos");
+        let pos = errores[0].pos.as_ref().unwrap();
+        assert_eq!((pos.linea, pos.columna, pos.columna_fin), (3, 7, 8));
+        let errores = diagnosticar("escena A {\n    mostrar quitar([], 0)\n}", None).unwrap_err();
+        assert_eq!(errores[0].codigo, Codigo::IndiceFueraDeRango);
+        
+        // Un '[' al principio de una línea empieza otra expresión
+        let codigo = "funcion f(l: lista<lista<texto>>) { }\nescena A {\n    var l = [1]\n    [2]\n}";
+        let programa = Parser::new(Lexer::new(codigo).tokenizar().unwrap()).parsear().unwrap();
+        let Declaracion::Escena(escena) = &programa.declaraciones[1] else { panic!() };
+        assert_eq!(escena.cuerpo.len(), 2);
+        let ingles = traductor::traducir(codigo, Idioma::Espanol, Idioma::Ingles).unwrap();
+        assert!(ingles.contains("function f(l: list<list<text>>) { }"));
+    }
+    
+    #[test]
+    fn test_mapas() {
+        let codigo = r#"
+            funcion sumar_oro(inventario: mapa<texto, numero>, cantidad: numero) {
+                inventario["oro"] = inventario["oro"] + cantidad
+            }
+            
+            escena Principal {
+                var inventario = { "oro": 10, "pociones": 2, }
+                sumar_oro(inventario, 5)
+                inventario["llaves"] = 1
+                mostrar inventario
+                mostrar "{claves(inventario)} {valores(inventario)}"
+                mostrar [tiene(inventario, "oro"), tiene(inventario, "plata"), longitud(inventario)]
+                mostrar quitar(inventario, "pociones")
+                para clave en inventario {
+                    mostrar "{clave}: {inventario[clave]}"
+                }
+                mostrar { 1: "a", verdadero: [] } == { verdadero: [], 1: "a" }
+                mostrar {}
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec![
+                "{ \"oro\": 15, \"pociones\": 2, \"llaves\": 1 }",
+                "[\"oro\", \"pociones\", \"llaves\"] [15, 2, 1]",
+                "[verdadero, falso, 3]",
+                "2",
+                "oro: 15",
+                "llaves: 1",
+                "verdadero",
+                "{}",
+            ]
+        );
+        
+        let errores = diagnosticar("escena A {\n    var m = { \"a\": 1 }\n    mostrar m[\"b\"]\n}", None).unwrap_err();
+        assert_eq!(errores[0].codigo, Codigo::ClaveNoEncontrada);
+        assert_eq!(errores[0].mensaje, "Clave \"b\" no encontrada en el mapa");
+        assert_eq!(errores[0].pos.as_ref().unwrap().columna, 15);
+        
+        let codigo = "funcion f(m: mapa<decimal, texto>) { }\nescena A {\n    var m = { 1.5: 0, 2m: 1, [1]: 2, \"a\": 3 }\n}";
+        let errores = diagnosticar(codigo, None).unwrap_err();
+        let resumen: Vec<(&str, u32)> = errores
+            .iter()
+            .map(|e| (e.codigo.texto(), e.pos.as_ref().unwrap().linea))
+            .collect();
+        assert_eq!(resumen, vec![("E0202", 1), ("E0202", 3), ("E0202", 3), ("E0202", 3)]);
+        assert_eq!(errores[0].mensaje, "Tipo de clave de mapa inválido: decimal");
+        
+        let codigo = "funcion f(m: mapa<texto, lista<numero>>, texto: numero) { }\n";
+        let ingles = traductor::traducir(codigo, Idioma::Espanol, Idioma::Ingles).unwrap();
+        assert!(ingles.contains("function f(m: map<text, list<number>>, texto: number) { }"));
+    }
+    
+    #[test]
+    fn test_estructuras() {
+        let codigo = r#"
+            funcion mover(p: Punto, dx: numero): Punto {
+                p.x = p.x + dx
+                retornar p
+            }
+            
+            escena Principal {
+                var a = Punto(y: 2, x: 1)
+                var b = a
+                b.x = 9
+                mostrar a
+                mostrar b
+                mostrar mover(a, 5)
+                mostrar a.x
+                
+                var c = Cuerpo(nombre: "pelota", centro: a, etiquetas: [])
+                c.centro.y = 7
+                agregar(c.etiquetas, "roja")
+                mostrar c
+                mostrar a == Punto(x: 1, y: 2)
+                
+                var puntos = [a, b]
+                puntos[1].y = 0
+                mostrar "{puntos[1].x} {puntos[1].y} {b.y}"
+            }
+            
+            estructura Punto { x: numero, y: numero }
+            
+            /// Un objeto con posición
+            estructura Cuerpo {
+                nombre: texto
+                centro: Punto
+                etiquetas: lista<texto>,
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec![
+                "Punto { x: 1, y: 2 }",
+                "Punto { x: 9, y: 2 }",
+                "Punto { x: 6, y: 2 }",
+                "1",
+                "Cuerpo { nombre: \"pelota\", centro: Punto { x: 1, y: 7 }, etiquetas: [\"roja\"] }",
+                "verdadero",
+                "9 0 2",
+            ]
+        );
+        
+        let errores = diagnosticar("estructura P { x: numero }\nescena A {\n    var p = P(x: 1)\n    mostrar p.z\n}", None).unwrap_err();
+        assert_eq!(errores[0].codigo, Codigo::CampoDesconocido);
+        assert_eq!(errores[0].mensaje, "La estructura 'P' no tiene un campo 'z'");
+        
+        let codigo = "estructura P { x: numero, y: Q }\nescena A {\n    var p = P(x: 1, z: 2, x: 3)\n    constante c = P(x: 1, y: 2)\n    c.x = 2\n}";
+        let errores = Parser::new(Lexer::new(codigo).tokenizar().unwrap()).parsear().unwrap_err();
+        assert_eq!(errores[0].codigo, Codigo::AsignacionAConstante);
+        let programa = Parser::new(Lexer::new(codigo).tokenizar().unwrap()).parsear_parcial().0;
+        let errores = semantico::analizar(&programa).unwrap_err();
+        let resumen: Vec<(&str, u32)> = errores
+            .iter()
+            .map(|e| (e.codigo.texto(), e.pos.as_ref().unwrap().linea))
+            .collect();
+        assert_eq!(resumen, vec![("E0203", 1), ("E0204", 3), ("E0204", 3), ("E0204", 3)]);
+        assert_eq!(errores[3].mensaje, "Faltan campos al construir 'P': y");
+        
+        let codigo = "estructura Punto { x: decimal, y: texto }\nescena A {\n    mostrar Punto(x: 1, y: 2).y\n}\n";
+        let ingles = traductor::traducir(codigo, Idioma::Espanol, Idioma::Ingles).unwrap();
+        assert!(ingles.contains("struct Punto { x: decimal, y: text }"));
+        assert!(ingles.contains("show Punto(x: 1, y: 2).y"));
+    }
+    
+    #[test]
+    fn test_segun() {
+        let codigo = r#"
+            enumeracion Forma {
+                Circulo(decimal)
+                Rectangulo(decimal, decimal)
+                Vacia
+            }
+            
+            funcion area(forma: Forma): decimal {
+                retornar segun forma {
+                    caso Circulo(r) => 3 * r * r
+                    caso Rectangulo(ancho, alto) => ancho * alto
+                    caso Vacia => 0
+                }
+            }
+            
+            escena Principal {
+                var formas = [Circulo(2), Rectangulo(2, 3.5), Vacia]
+                mostrar formas
+                para forma en formas {
+                    mostrar area(forma)
+                }
+                mostrar [Circulo(1) == Circulo(1), Circulo(1) == Circulo(2)]
+                
+                para tecla en ["w", "q", "x"] {
+                    segun tecla {
+                        caso "w" => mostrar "avanzar"
+                        caso "q" => {
+                            mostrar "salir"
+                        }
+                        otro => mostrar "tecla {tecla}"
+                    }
+                }
+                
+                var r = 10
+                segun Rectangulo(1, 2) {
+                    caso Rectangulo(_, r) => mostrar r
+                    otro => mostrar "nada"
+                }
+                mostrar r
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec![
+                "[Circulo(2), Rectangulo(2, 3.5), Vacia]",
+                "12",
+                "7",
+                "0",
+                "[verdadero, falso]",
+                "avanzar",
+                "salir",
+                "tecla x",
+                "2",
+                "10",
+            ]
+        );
+        
+        let codigo = "enumeracion Luz { Roja, Ambar, Verde }\nescena A {\n    var luz = Roja\n    segun luz {\n        caso Roja => mostrar 1\n        caso Roja => mostrar 2\n        caso Verde(x) => mostrar x\n    }\n    segun 3 {\n        caso 1 => mostrar 1\n    }\n}";
+        let errores = diagnosticar(codigo, None).unwrap_err();
+        let resumen: Vec<(&str, u32)> = errores
+            .iter()
+            .map(|e| (e.codigo.texto(), e.pos.as_ref().unwrap().linea))
+            .collect();
+        assert_eq!(resumen, vec![("E0205", 6), ("E0204", 7), ("E0205", 4), ("E0205", 9)]);
+        assert_eq!(errores[2].mensaje, "'segun' no cubre las variantes de 'Luz': Ambar");
+        
+        let codigo = "enumeracion Luz { Roja }\nfuncion f(luz: Luz) {\n    segun luz {\n        caso Roja => mostrar 1\n    }\n}\nescena A {\n    f(3)\n}";
+        let errores = diagnosticar(codigo, None).unwrap_err();
+        assert_eq!(errores[0].codigo, Codigo::SinCaso);
+        assert_eq!(errores[0].mensaje, "Ningún caso de 'segun' coincide con 3");
+        
+        let codigo = "enumeracion Luz { Roja }\nescena A {\n    var caso = segun Roja {\n        caso Roja => 1\n        otro => 2\n    }\n}\n";
+        let ingles = traductor::traducir(codigo, Idioma::Espanol, Idioma::Ingles).unwrap();
+        assert!(ingles.contains("enum Luz { Roja }"));
+        assert!(ingles.contains("var caso = match Roja {\n        case Roja => 1\n        default => 2"));
+    }
+    
+    #[test]
+    fn test_funciones_como_valores() {
+        let codigo = r#"
+            funcion aplicar(f: funcion(numero): numero, x: numero): numero {
+                retornar f(x)
+            }
+            
+            funcion crear_contador(): funcion(): numero {
+                var cuenta = 0
+                retornar funcion(): numero {
+                    cuenta = cuenta + 1
+                    retornar cuenta
+                }
+            }
+            
+            funcion doble(x: numero): numero {
+                retornar x * 2
+            }
+            
+            escena Principal {
+                var factor = 3
+                var triple = funcion(x: numero) => x * factor
+                mostrar [aplicar(triple, 5), aplicar(doble, 5), aplicar(funcion(x: numero) => x + 1, 5)]
+                
+                var contador = crear_contador()
+                contador()
+                mostrar [contador(), crear_contador()()]
+                
+                var acciones = [doble, triple]
+                mostrar acciones[1](2)
+                mostrar acciones
+                mostrar [doble == doble, triple == doble]
+                
+                var factorial = funcion(n: numero): numero {
+                    si n <= 1 {
+                        retornar 1
+                    }
+                    retornar n * factorial(n - 1)
+                }
+                mostrar factorial(5)
+                
+                escuchar "golpe" funcion(fuerza: numero) {
+                    mostrar "golpe de {fuerza}"
+                }
+                emitir "golpe" (7)
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec![
+                "[15, 10, 6]",
+                "[2, 1]",
+                "6",
+                "[función doble, función triple]",
+                "[verdadero, falso]",
+                "120",
+                "golpe de 7",
+            ]
+        );
+        
+        let error = salida("escena A {\n    var f = funcion(x: numero) => x\n    f(1, 2)\n}", None).unwrap_err();
+        assert!(error.contains("Función 'f' espera 1 argumentos, se proporcionaron 2"));
+        let error = salida("escena A {\n    var x = 3\n    x(2)\n}", None).unwrap_err();
+        assert!(error.contains("'x' no es una función: vale 3"));
+        
+        let traducido = traductor::traducir(
+            "funcion aplicar(f: funcion(numero, texto): booleano) { }",
+            Idioma::Espanol,
+            Idioma::Ingles,
+        )
+        .unwrap();
+        assert_eq!(traducido, "@language en\nfunction aplicar(f: function(number, text): boolean) { }");
+    }
+    
+    #[test]
+    fn test_funciones_de_colecciones() {
+        let codigo = r#"
+            funcion par(n: numero): booleano {
+                retornar n % 2 == 0
+            }
+            
+            escena Principal {
+                var puntos = [5, 3, 8, 1, 4]
+                mostrar mapear(puntos, funcion(p: numero) => p * 2)
+                mostrar filtrar(puntos, par)
+                mostrar reducir(puntos, 0, funcion(total: numero, p: numero) => total + p)
+                mostrar [ordenar(puntos), ordenar(["pera", "kiwi", "higo"])]
+                mostrar ordenar(puntos, funcion(a: numero, b: numero) => a > b)
+                var jugadores = [["ana", 2], ["luis", 1], ["eva", 2]]
+                mostrar ordenar(jugadores, funcion(a: lista<texto>, b: lista<texto>) => a[1] < b[1])
+                mostrar [buscar(puntos, par), buscar(puntos, funcion(p: numero) => p > 10)]
+                mostrar [cualquiera(puntos, par), todos(puntos, par), todos([], par)]
+                mostrar enumerar(["a", "b"])
+                mostrar zip(puntos, ["a", "b"])
+                mostrar puntos
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec![
+                "[10, 6, 16, 2, 8]",
+                "[8, 4]",
+                "21",
+                "[[1, 3, 4, 5, 8], [\"higo\", \"kiwi\", \"pera\"]]",
+                "[8, 5, 4, 3, 1]",
+                "[[\"luis\", 1], [\"ana\", 2], [\"eva\", 2]]",
+                "[8, nulo]",
+                "[verdadero, falso, verdadero]",
+                "[[0, \"a\"], [1, \"b\"]]",
+                "[[5, \"a\"], [3, \"b\"]]",
+                "[5, 3, 8, 1, 4]",
+            ]
+        );
+        
+        let codigo = "funcion suma(a: numero, b: numero): numero {\n    retornar a + b\n}\nescena A {\n    mostrar mapear([1], suma)\n    mostrar filtrar(\"hola\", funcion(x: texto) => verdadero)\n    mostrar reducir([1], suma)\n}";
+        let errores = diagnosticar(codigo, None).unwrap_err();
+        assert_eq!(errores.len(), 3);
+        assert!(errores.iter().all(|e| e.codigo == Codigo::LlamadaIncorrecta));
+        assert!(errores[0].mensaje.contains("'mapear' llama a su función con 1 argumentos, pero recibe 2"));
+        
+        let error = salida("escena A {\n    mostrar ordenar([1, \"a\"])\n}", None).unwrap_err();
+        assert!(error.contains("Operador '<' no aplicable"));
+    }
+    
+    #[test]
+    fn test_intentar_y_lanzar() {
+        let codigo = r#"
+            funcion disparar(balas: numero): numero {
+                si balas == 0 {
+                    lanzar "sin munición"
+                }
+                retornar balas - 1
+            }
+            
+            escena Principal {
+                intentar {
+                    disparar(0)
+                    mostrar "no llega"
+                } capturar e {
+                    mostrar "capturado: {e}"
+                } finalmente {
+                    mostrar "finalmente"
+                }
+                
+                intentar {
+                    mostrar 10 / 0
+                } capturar e {
+                    mostrar [e.mensaje, e.codigo, e.linea]
+                }
+                
+                intentar {
+                    mostrar desconocida
+                } capturar {
+                    mostrar "sin nombre"
+                }
+                
+                para i = 0, 5 {
+                    intentar {
+                        si i == 1 {
+                            romper
+                        }
+                        mostrar i
+                    } finalmente {
+                        mostrar "fin {i}"
+                    }
+                }
+                
+                intentar {
+                    intentar {
+                        lanzar [1, 2]
+                    } capturar e {
+                        lanzar e
+                    }
+                } capturar e {
+                    mostrar e
+                }
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec![
+                "capturado: sin munición",
+                "finalmente",
+                "[\"División por cero\", \"E0304\", 20]",
+                "sin nombre",
+                "0",
+                "fin 0",
+                "fin 1",
+                "[1, 2]",
+            ]
+        );
+        
+        let error = diagnosticar("escena A {\n    intentar {\n        lanzar 42\n    } finalmente {\n        mostrar 1\n    }\n}", None).unwrap_err();
+        assert_eq!(error[0].codigo, Codigo::ErrorNoCapturado);
+        assert_eq!(error[0].mensaje, "Error no capturado: 42");
+        
+        let codigo = "corrutina c() {\n    intentar {\n        esperar 1\n    } capturar e { }\n}\nescena A {\n    intentar { }\n}";
+        let errores = diagnosticar(codigo, None).unwrap_err();
+        assert_eq!(errores.len(), 2);
+        assert!(errores[0].mensaje.contains("'esperar' no puede usarse dentro de 'intentar'"));
+        assert!(errores[1].mensaje.contains("Se esperaba 'capturar' o 'finalmente'"));
+    }
+    
+    #[test]
+    fn test_funciones_anonimas_comparten_variables() {
+        let codigo = r#"
+            escena Principal {
+                var puntos = 0
+                escuchar "golpe" funcion(fuerza: numero) {
+                    puntos = puntos + fuerza
+                }
+                var sumar = funcion(x: numero) {
+                    puntos = puntos + x
+                }
+                sumar(5)
+                mapear([1, 2], funcion(x: numero) {
+                    puntos = puntos + x
+                })
+                mostrar puntos
+                emitir "golpe" (10)
+                
+                al_salir {
+                    mostrar puntos
+                }
+            }
+        "#;
+        assert_eq!(salida(codigo, None).unwrap(), vec!["8", "18"]);
+    }
+    
+    #[test]
+    fn test_coleccion_dentro_de_si_misma() {
+        let codigo = r#"
+            estructura Caja { cosas: lista<numero> }
+            
+            escena Principal {
+                var l = [1]
+                var otra = [l]
+                agregar(l, [2])
+                mostrar [l, otra, l == l, otra == [l]]
+                
+                intentar {
+                    agregar(l, l)
+                } capturar e {
+                    mostrar e.codigo
+                }
+                intentar {
+                    insertar(l, 0, otra)
+                } capturar e {
+                    mostrar e.codigo
+                }
+                var m = {"caja": 1}
+                intentar {
+                    m["caja"] = Caja(cosas: [m])
+                } capturar e {
+                    mostrar e.mensaje
+                }
+                mostrar [l, m]
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec![
+                "[[1, [2]], [[1, [2]]], verdadero, verdadero]",
+                "E0315",
+                "E0315",
+                "Un mapa no puede contenerse a sí mismo",
+                "[[1, [2]], { \"caja\": 1 }]",
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_desbordamiento_de_enteros() {
+        let codigo = r#"
+            funcion probar(operacion: funcion(): numero) {
+                intentar {
+                    mostrar operacion()
+                } capturar e {
+                    mostrar "{e.codigo}: {e.mensaje}"
+                }
+            }
+            
+            escena Principal {
+                var maximo = 9223372036854775807
+                var minimo = -maximo - 1
+                probar(funcion() => maximo + 1)
+                probar(funcion() => minimo - 1)
+                probar(funcion() => maximo * 2)
+                probar(funcion() => -minimo)
+                probar(funcion() => minimo / -1)
+                probar(funcion() => minimo % -1)
+                probar(funcion() => maximo - 1)
+            }
+        "#;
+        let desbordamiento = "E0316: El resultado no cabe en un número entero";
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec![
+                desbordamiento,
+                desbordamiento,
+                desbordamiento,
+                desbordamiento,
+                desbordamiento,
+                desbordamiento,
+                "9223372036854775806",
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_cambiar_escena_tras_varios_frames() {
+        let codigo = r#"
+            escena Carga {
+                var frames = 0
+                cada frame {
+                    frames = frames + 1
+                    si frames == 8 {
+                        cambiar_escena("Nivel")
+                        mostrar "no se ejecuta"
+                    }
+                }
+                cada frame {
+                    si frames >= 7 {
+                        mostrar "carga en frame {frames}"
+                    }
+                }
+                al_salir {
+                    mostrar "carga termina tras {frames} frames"
+                }
+            }
+            
+            escena Nivel {
+                mostrar "nivel empieza en {tiempo()}"
+                var frames = 0
+                cada frame {
+                    frames = frames + 1
+                    si frames == 6 {
+                        mostrar "nivel lleva {frames} frames"
+                    }
+                }
+            }
+        "#;
+        
+        let tokens = Lexer::new(codigo).tokenizar().unwrap();
+        let programa = Parser::new(tokens).parsear().unwrap();
+        let mut ejecutor = Ejecutor::new();
+        ejecutor.fijar_frames_maximos(10);
+        ejecutor.ejecutar(&programa).unwrap();
+        
+        let lineas: Vec<&str> = ejecutor
+            .obtener_salida()
+            .iter()
+            .map(|linea| linea.trim_start_matches("📺 "))
+            .collect();
+        assert_eq!(
+            lineas,
+            vec![
+                "carga en frame 7",
+                "carga termina tras 8 frames",
+                "nivel empieza en 0",
+                "nivel lleva 6 frames",
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_tipos_de_funcion() {
+        let codigo = r#"
+            estructura Boton { al_pulsar: funcion(texto) }
+            
+            funcion aplicar(f: funcion(numero): numero, x: numero): numero {
+                retornar f(x)
+            }
+            
+            funcion longitud_de(t: texto): numero {
+                retornar longitud(t)
+            }
+            
+            funcion crear(): funcion(): texto {
+                retornar funcion(): numero => 1
+            }
+            
+            escena Principal {
+                mostrar aplicar(funcion(x: numero) => x * 2, 5)
+                aplicar(funcion(a: numero, b: numero) => a + b, 5)
+                aplicar(longitud_de, 5)
+                var boton = Boton(al_pulsar: funcion(quien: texto) { mostrar quien })
+                var roto = Boton(al_pulsar: aplicar)
+            }
+        "#;
+        let errores = diagnosticar(codigo, None).unwrap_err();
+        let mensajes: Vec<&str> = errores.iter().map(|e| e.mensaje.as_str()).collect();
+        assert!(errores.iter().all(|e| e.codigo == Codigo::FuncionIncompatible));
+        assert_eq!(
+            mensajes,
+            vec![
+                "El retorno de la función es funcion(): texto, pero se le da funcion(): numero",
+                "El parámetro 'f' de 'aplicar' es funcion(numero): numero, pero se le da funcion(numero, numero)",
+                "El parámetro 'f' de 'aplicar' es funcion(numero): numero, pero se le da funcion(texto): numero",
+                "El campo 'al_pulsar' de 'Boton' es funcion(texto), pero se le da funcion(funcion(numero): numero, numero): numero",
+            ]
+        );
+    }
+    
+    #[test]
+    fn test_segun_en_corrutina_restaura_variables() {
+        let codigo = r#"
+            enumeracion Forma {
+                Circulo(decimal)
+                Rectangulo(decimal, decimal)
+            }
+            
+            corrutina medir(forma: Forma) {
+                var r = 1
+                segun forma {
+                    caso Circulo(r) => {
+                        esperar 0.1
+                        mostrar "radio {r}"
+                    }
+                    caso Rectangulo(ancho, r) => mostrar "alto {r}"
+                }
+                mostrar "r sigue en {r}"
+            }
+            
+            escena Principal {
+                medir(Circulo(5))
+                medir(Rectangulo(2, 3))
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec!["alto 3", "r sigue en 1", "radio 5", "r sigue en 1"]
+        );
+    }
+    
+    #[test]
+    fn test_retornar_en_manejador_de_evento() {
+        let codigo = r#"
+            escena Principal {
+                escuchar "golpe" (fuerza) {
+                    mostrar "antes {fuerza}"
+                    si fuerza < 5 {
+                        retornar
+                    }
+                    mostrar "despues {fuerza}"
+                }
+                emitir "golpe" (3)
+                emitir "golpe" (8)
+            }
+        "#;
+        assert_eq!(salida(codigo, None).unwrap(), vec!["antes 3", "antes 8", "despues 8"]);
+    }
+    
+    #[test]
+    fn test_interpolacion_con_textos_y_llaves() {
+        let codigo = r#"
+            escena Principal {
+                var m = {"oro": 5, "plata": 2}
+                mostrar "Oro: {m["oro"]}, plata: {m["plata"]}"
+                mostrar "Doble: { {"a": 1, "b": 2}["b"] * 2 }"
+                mostrar "Anidado: {"x{1 + 1}y"} y \"{m["oro"]}\""
+            }
+        "#;
+        assert_eq!(
+            salida(codigo, None).unwrap(),
+            vec!["Oro: 5, plata: 2", "Doble: 4", "Anidado: x2y y \"5\""]
+        );
+        
+        // Una comilla sin texto anidado detrás sigue cerrando el de fuera
+        let errores = Lexer::new("escena A {\n    mostrar \"c {m\"\n}\n").tokenizar().unwrap_err();
+        assert_eq!(errores[0].codigo, Codigo::InterpolacionInvalida);
+    }
+    
+    #[test]
+    fn test_asignacion_a_destino_invalido() {
+        let codigo = r#"
+            funcion f(): numero {
+                retornar 1
+            }
+            escena Principal {
+                f() = 3
+                (1 + 2) = 4
+                mostrar "sigue"
+            }
+        "#;
+        let errores = diagnosticar(codigo, None).unwrap_err();
+        assert_eq!(errores.len(), 2);
+        assert!(errores.iter().all(|e| e.codigo == Codigo::TokenInesperado));
+        assert_eq!(errores[0].mensaje, "No se puede asignar a esta expresión");
+        let lugares: Vec<_> = errores.iter().map(|e| e.pos.as_ref().map(|p| (p.linea, p.columna))).collect();
+        assert_eq!(lugares, vec![Some((6, 17)), Some((7, 18))]);
+    }
+    
+    #[test]
+    fn test_escenas_que_se_cambian_en_bucle() {
+        let codigo = r#"
+            escena Menu {
+                cambiar_escena("Nivel")
+            }
+            
+            escena Nivel {
+                al_iniciar {
+                    cambiar_escena("Menu")
+                }
+            }
+        "#;
+        let errores = diagnosticar(codigo, None).unwrap_err();
+        assert_eq!(errores[0].codigo, Codigo::BucleDeEscenas);
+        assert!(errores[0].mensaje.starts_with("Más de 1000 cambios de escena sin avanzar ningún frame"));
+    }
 }
@@ -0,0 +1,58 @@
+// maquinas.rs - Máquinas de estados de V-Code
+// Cada frame, después de las corrutinas, el runtime evalúa las transiciones
+// del estado actual en orden de declaración y aplica la primera que se
+// cumple (como mucho una por frame); después ejecuta el `cada frame` del
+// estado resultante.
+
+use crate::ast::{Estado, Maquina};
+use std::fmt;
+
+/// Máquina en ejecución dentro de la escena en curso
+#[derive(Debug, Clone)]
+pub struct InstanciaMaquina {
+    pub definicion: Maquina,
+    pub actual: usize,
+}
+
+impl InstanciaMaquina {
+    /// Crea la instancia en el primer estado declarado
+    pub fn new(definicion: Maquina) -> Self {
+        InstanciaMaquina { definicion, actual: 0 }
+    }
+    
+    pub fn estado(&self) -> &Estado {
+        &self.definicion.estados[self.actual]
+    }
+    
+    /// Índice de un estado; el parser ya validó que los destinos existen
+    pub fn indice_de(&self, nombre: &str) -> Option<usize> {
+        self.definicion.estados.iter().position(|e| e.nombre == nombre)
+    }
+    
+    /// Si la máquina aún tiene trabajo: un estado sin transiciones ni
+    /// `cada frame` es final y no mantiene vivo el bucle de frames
+    pub fn activa(&self) -> bool {
+        let estado = self.estado();
+        !estado.transiciones.is_empty() || !estado.cada_frame.is_empty()
+    }
+}
+
+/// Transición aplicada, para depurar el comportamiento de las máquinas
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegistroTransicion {
+    pub maquina: String,
+    pub desde: String,
+    pub hacia: String,
+    pub frame: u64,
+    pub tiempo: f64,
+}
+
+impl fmt::Display for RegistroTransicion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[frame {} | {:.2} s] {}: {} → {}",
+            self.frame, self.tiempo, self.maquina, self.desde, self.hacia
+        )
+    }
+}
@@ -1,112 +1,436 @@
 // parser.rs - Analizador Sintáctico para V-Code
 // Convierte tokens en un AST
+//
+// Ante un error de sintaxis el parser lo registra y se resincroniza en el
+// siguiente punto seguro (inicio de sentencia, '}' o declaración), así que
+// informa de todos los errores en una pasada y conserva lo que sí pudo
+// analizar.
 
 use crate::ast::*;
-use crate::lexer::{Token, TipoToken};
+use crate::diagnostico::{Codigo, Diagnostico};
+use crate::idiomas::es_palabra_contextual;
+use crate::lexer::{ParteDeTexto, Token, TipoToken};
+use std::collections::HashMap;
 
 pub struct Parser {
     tokens: Vec<Token>,
     actual: usize,
+    en_corrutina: bool, // Si `esperar` está permitido en el bloque actual
+    en_intentar: bool,  // Dentro de un `intentar`, donde `esperar` no se permite
+    bucles: Vec<Option<String>>, // Etiquetas de los bucles que rodean el bloque actual
+    documentacion: HashMap<usize, String>, // Comentarios `///` por índice del token que documentan
+    ambitos: Vec<Vec<(String, Option<Posicion>)>>, // Nombres declarados; con posición si son constantes
+    diagnosticos: Vec<Diagnostico>,
 }
 
 impl Parser {
+    /// Crea el parser. Los tokens de error ya se informaron en el lexer y
+    /// se descartan; las líneas `///` se apartan y se asocian al token que
+    /// las sigue.
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, actual: 0 }
+        let mut filtrados = Vec::new();
+        let mut documentacion = HashMap::new();
+        let mut lineas: Vec<String> = Vec::new();
+        for token in tokens {
+            match token.tipo {
+                TipoToken::Error(_) => {}
+                TipoToken::Documentacion(linea) => lineas.push(linea),
+                _ => {
+                    if !lineas.is_empty() {
+                        documentacion.insert(filtrados.len(), lineas.join("\n"));
+                        lineas.clear();
+                    }
+                    filtrados.push(token);
+                }
+            }
+        }
+        Parser {
+            tokens: filtrados,
+            actual: 0,
+            en_corrutina: false,
+            en_intentar: false,
+            bucles: Vec::new(),
+            documentacion,
+            ambitos: Vec::new(),
+            diagnosticos: Vec::new(),
+        }
     }
     
-    /// Parsea el programa completo
-    pub fn parsear(&mut self) -> Result<Programa, String> {
+    /// Parsea el programa completo; si hay errores los devuelve todos
+    pub fn parsear(&mut self) -> Result<Programa, Vec<Diagnostico>> {
+        let (programa, diagnosticos) = self.parsear_parcial();
+        if diagnosticos.is_empty() {
+            Ok(programa)
+        } else {
+            Err(diagnosticos)
+        }
+    }
+    
+    /// Parsea el programa recuperándose de los errores. Devuelve las
+    /// declaraciones que se pudieron analizar junto con todos los
+    /// diagnósticos, para herramientas que trabajan con código incompleto.
+    pub fn parsear_parcial(&mut self) -> (Programa, Vec<Diagnostico>) {
+        let inicio = self.token_actual().pos.clone();
         let mut declaraciones = Vec::new();
         
         while !self.fin() {
-            declaraciones.push(self.declaracion()?);
+            match self.declaracion() {
+                Ok(declaracion) => declaraciones.push(declaracion),
+                Err(e) => {
+                    self.reportar(e);
+                    self.sincronizar_declaracion();
+                }
+            }
         }
         
-        Ok(Programa { declaraciones })
+        let pos = inicio.hasta(&self.token_actual().pos);
+        (Programa { declaraciones, pos }, std::mem::take(&mut self.diagnosticos))
     }
     
     /// Parsea una declaración de nivel superior
-    fn declaracion(&mut self) -> Result<Declaracion, String> {
+    fn declaracion(&mut self) -> Result<Declaracion, Diagnostico> {
         match &self.token_actual().tipo {
+            TipoToken::Arroba => {
+                let escena = self.escena_con_atributo()?;
+                Ok(Declaracion::Escena(escena))
+            }
             TipoToken::Escena => {
                 let escena = self.escena()?;
                 Ok(Declaracion::Escena(escena))
             }
-            TipoToken::Funcion => {
+            TipoToken::Funcion | TipoToken::Corrutina => {
                 let funcion = self.funcion()?;
                 Ok(Declaracion::Funcion(funcion))
             }
-            _ => Err(format!(
-                "Se esperaba 'escena' o 'funcion', se encontró {} en {}",
-                self.token_actual().tipo,
-                self.token_actual().pos
+            TipoToken::Estructura => {
+                let estructura = self.estructura()?;
+                Ok(Declaracion::Estructura(estructura))
+            }
+            TipoToken::Enumeracion => {
+                let enumeracion = self.enumeracion()?;
+                Ok(Declaracion::Enumeracion(enumeracion))
+            }
+            _ => Err(self.error_actual(
+                Codigo::DeclaracionInvalida,
+                "Se esperaba 'escena', 'funcion', 'corrutina', 'estructura' o 'enumeracion'",
             )),
         }
     }
     
+    /// Parsea una escena precedida de un atributo (`@inicial escena ...`)
+    fn escena_con_atributo(&mut self) -> Result<Escena, Diagnostico> {
+        let inicio = self.token_actual().pos.clone();
+        let doc = self.documentacion.remove(&self.actual);
+        self.consumir(TipoToken::Arroba, "Se esperaba '@'")?;
+        
+        let pos = self.token_actual().pos.clone();
+        match &self.token_actual().tipo {
+            TipoToken::Identificador(id) if es_palabra_contextual(id, "inicial") => self.avanzar(),
+            _ => {
+                let error = Diagnostico::new(
+                    Codigo::ReferenciaDesconocida,
+                    format!("Atributo desconocido {}, se esperaba '@inicial'", self.token_actual().tipo),
+                    &pos,
+                );
+                self.reportar(error);
+                self.avanzar();
+            }
+        }
+        
+        if !self.verificar(&TipoToken::Escena) {
+            return Err(self.error_actual(
+                Codigo::TokenInesperado,
+                "El atributo '@inicial' solo se aplica a escenas",
+            ));
+        }
+        
+        let mut escena = self.escena()?;
+        escena.inicial = true;
+        escena.doc = doc.or(escena.doc);
+        escena.pos = inicio.hasta(&escena.pos);
+        Ok(escena)
+    }
+    
     /// Parsea una escena
-    fn escena(&mut self) -> Result<Escena, String> {
+    fn escena(&mut self) -> Result<Escena, Diagnostico> {
         let pos = self.token_actual().pos.clone();
+        let doc = self.documentacion.remove(&self.actual);
         self.consumir(TipoToken::Escena, "Se esperaba 'escena'")?;
         
-        let nombre = match &self.token_actual().tipo {
-            TipoToken::Identificador(id) => {
-                let n = id.clone();
-                self.avanzar();
-                n
-            }
-            _ => return Err(format!(
-                "Se esperaba nombre de escena, se encontró {} en {}",
-                self.token_actual().tipo,
-                self.token_actual().pos
-            )),
-        };
+        let nombre = self.nombre("Se esperaba nombre de escena")?;
         
         self.consumir(TipoToken::LlaveAbre, "Se esperaba '{' después del nombre de escena")?;
         
         let mut cuerpo = Vec::new();
-        while !self.verificar(&TipoToken::LlaveCierra) && !self.fin() {
-            cuerpo.push(self.sentencia()?);
+        let mut al_iniciar = None;
+        let mut al_salir = None;
+        let mut maquinas: Vec<Maquina> = Vec::new();
+        self.ambitos.push(Vec::new());
+        while !self.verificar(&TipoToken::LlaveCierra) && !self.fin() && !self.inicio_de_declaracion() {
+            let inicio = self.actual;
+            let resultado = match &self.token_actual().tipo {
+                TipoToken::AlIniciar => self.hook(&mut al_iniciar, "al_iniciar", &nombre),
+                TipoToken::AlSalir => self.hook(&mut al_salir, "al_salir", &nombre),
+                TipoToken::Maquina => match self.maquina() {
+                    Ok(maquina) if maquinas.iter().any(|m| m.nombre == maquina.nombre) => {
+                        self.reportar(Diagnostico::new(
+                            Codigo::Duplicado,
+                            format!(
+                                "La escena '{}' ya tiene una máquina '{}', duplicada",
+                                nombre, maquina.nombre
+                            ),
+                            &maquina.pos,
+                        ));
+                        Ok(())
+                    }
+                    Ok(maquina) => {
+                        maquinas.push(maquina);
+                        Ok(())
+                    }
+                    Err(e) => Err(e),
+                },
+                _ => self.sentencia().map(|sentencia| cuerpo.push(sentencia)),
+            };
+            if let Err(e) = resultado {
+                self.reportar(e);
+                self.sincronizar(inicio, Self::inicio_de_sentencia);
+            }
         }
         
-        self.consumir(TipoToken::LlaveCierra, "Se esperaba '}' al final de escena")?;
+        self.cerrar_bloque("Se esperaba '}' al final de escena");
+        self.ambitos.pop();
         
-        Ok(Escena { nombre, cuerpo, pos })
+        Ok(Escena {
+            nombre,
+            inicial: false,
+            cuerpo,
+            al_iniciar: al_iniciar.unwrap_or_default(),
+            al_salir: al_salir.unwrap_or_default(),
+            maquinas,
+            doc,
+            pos: self.tramo(&pos),
+        })
     }
     
-    /// Parsea una función
-    fn funcion(&mut self) -> Result<Funcion, String> {
+    /// Parsea un bloque de hook (`al_iniciar`, `al_salir`...) que solo puede
+    /// aparecer una vez en `duenio`
+    fn hook(
+        &mut self,
+        destino: &mut Option<Vec<Sentencia>>,
+        contexto: &str,
+        duenio: &str,
+    ) -> Result<(), Diagnostico> {
         let pos = self.token_actual().pos.clone();
-        self.consumir(TipoToken::Funcion, "Se esperaba 'funcion'")?;
+        self.avanzar();
         
-        let nombre = match &self.token_actual().tipo {
-            TipoToken::Identificador(id) => {
-                let n = id.clone();
-                self.avanzar();
-                n
+        let bloque = self.bloque(contexto)?;
+        if destino.is_some() {
+            // Se analiza igualmente para seguir informando de sus errores
+            self.reportar(Diagnostico::new(
+                Codigo::Duplicado,
+                format!("'{}' ya tiene un bloque '{}', duplicado", duenio, contexto),
+                &pos,
+            ));
+        } else {
+            *destino = Some(bloque);
+        }
+        Ok(())
+    }
+    
+    /// Parsea una máquina de estados
+    fn maquina(&mut self) -> Result<Maquina, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        self.consumir(TipoToken::Maquina, "Se esperaba 'maquina'")?;
+        
+        let nombre = self.nombre("Se esperaba nombre de máquina")?;
+        
+        self.consumir(TipoToken::LlaveAbre, "Se esperaba '{' después del nombre de máquina")?;
+        
+        let mut estados: Vec<Estado> = Vec::new();
+        while !self.verificar(&TipoToken::LlaveCierra) && !self.fin() && !self.inicio_de_declaracion() {
+            let inicio = self.actual;
+            match self.estado() {
+                Ok(estado) if estados.iter().any(|e| e.nombre == estado.nombre) => {
+                    self.reportar(Diagnostico::new(
+                        Codigo::Duplicado,
+                        format!(
+                            "La máquina '{}' ya tiene un estado '{}', duplicado",
+                            nombre, estado.nombre
+                        ),
+                        &estado.pos,
+                    ));
+                }
+                Ok(estado) => estados.push(estado),
+                Err(e) => {
+                    self.reportar(e);
+                    self.sincronizar(inicio, Self::inicio_de_estado);
+                }
+            }
+        }
+        
+        self.cerrar_bloque("Se esperaba '}' al final de máquina");
+        
+        if estados.is_empty() {
+            return Err(Diagnostico::new(
+                Codigo::TokenInesperado,
+                format!("La máquina '{}' no tiene estados", nombre),
+                &pos,
+            ));
+        }
+        
+        for transicion in estados.iter().flat_map(|e| &e.transiciones) {
+            if !estados.iter().any(|e| e.nombre == transicion.destino) {
+                self.reportar(Diagnostico::new(
+                    Codigo::ReferenciaDesconocida,
+                    format!("La máquina '{}' no tiene un estado '{}'", nombre, transicion.destino),
+                    &transicion.pos,
+                ));
+            }
+        }
+        
+        Ok(Maquina { nombre, estados, pos: self.tramo(&pos) })
+    }
+    
+    /// Parsea un estado de máquina. `estado` es una palabra contextual, así
+    /// que fuera de una máquina sigue pudiendo usarse como nombre.
+    fn estado(&mut self) -> Result<Estado, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        match &self.token_actual().tipo {
+            TipoToken::Identificador(id) if es_palabra_contextual(id, "estado") => self.avanzar(),
+            _ => return Err(self.error_actual(Codigo::TokenInesperado, "Se esperaba 'estado'")),
+        }
+        
+        let nombre = self.nombre("Se esperaba nombre de estado")?;
+        
+        self.consumir(TipoToken::LlaveAbre, "Se esperaba '{' después del nombre de estado")?;
+        
+        let mut al_entrar = None;
+        let mut cada_frame = None;
+        let mut al_salir = None;
+        let mut transiciones = Vec::new();
+        while !self.verificar(&TipoToken::LlaveCierra) && !self.fin() && !self.inicio_de_declaracion() {
+            let inicio = self.actual;
+            let resultado = match &self.token_actual().tipo {
+                TipoToken::AlEntrar => self.hook(&mut al_entrar, "al_entrar", &nombre),
+                TipoToken::AlSalir => self.hook(&mut al_salir, "al_salir", &nombre),
+                TipoToken::Cada if matches!(self.token_en(self.actual + 1).tipo, TipoToken::Frame) => {
+                    self.avanzar();
+                    self.hook(&mut cada_frame, "cada frame", &nombre)
+                }
+                TipoToken::Cada => Err(Diagnostico::new(
+                    Codigo::FueraDeContexto,
+                    "En un estado solo se admite 'cada frame'",
+                    &self.token_actual().pos,
+                )),
+                TipoToken::Transicion => self.transicion().map(|t| transiciones.push(t)),
+                _ => Err(self.error_actual(
+                    Codigo::TokenInesperado,
+                    "Se esperaba 'al_entrar', 'cada frame', 'al_salir' o 'transicion'",
+                )),
+            };
+            if let Err(e) = resultado {
+                self.reportar(e);
+                self.sincronizar(inicio, Self::inicio_de_miembro_de_estado);
             }
-            _ => return Err(format!(
-                "Se esperaba nombre de función, se encontró {} en {}",
-                self.token_actual().tipo,
-                self.token_actual().pos
+        }
+        
+        self.cerrar_bloque("Se esperaba '}' al final de estado");
+        
+        Ok(Estado {
+            nombre,
+            al_entrar: al_entrar.unwrap_or_default(),
+            cada_frame: cada_frame.unwrap_or_default(),
+            al_salir: al_salir.unwrap_or_default(),
+            transiciones,
+            pos: self.tramo(&pos),
+        })
+    }
+    
+    /// Parsea `transicion a Destino cuando condición`
+    fn transicion(&mut self) -> Result<Transicion, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        self.consumir(TipoToken::Transicion, "Se esperaba 'transicion'")?;
+        
+        match &self.token_actual().tipo {
+            TipoToken::Identificador(id) if es_palabra_contextual(id, "a") => self.avanzar(),
+            _ => return Err(self.error_actual(
+                Codigo::TokenInesperado,
+                "Se esperaba 'a' después de 'transicion'",
             )),
-        };
+        }
+        
+        let destino = self.nombre("Se esperaba el estado destino")?;
+        
+        self.consumir(TipoToken::Cuando, "Se esperaba 'cuando' después del estado destino")?;
+        let condicion = self.expresion()?;
+        
+        Ok(Transicion { destino, condicion, pos: self.tramo(&pos) })
+    }
+    
+    /// Parsea una función o una corrutina
+    fn funcion(&mut self) -> Result<Funcion, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        let doc = self.documentacion.remove(&self.actual);
+        let es_corrutina = self.coincidir(&TipoToken::Corrutina);
+        if !es_corrutina {
+            self.consumir(TipoToken::Funcion, "Se esperaba 'funcion'")?;
+        }
+        
+        let nombre = self.nombre("Se esperaba nombre de función")?;
         
         self.consumir(TipoToken::ParentesisAbre, "Se esperaba '(' después del nombre de función")?;
+        let parametros = self.parametros()?;
+        
+        let tipo_retorno = if self.coincidir(&TipoToken::DosPuntos) {
+            if es_corrutina {
+                let error = Diagnostico::new(
+                    Codigo::FueraDeContexto,
+                    format!("La corrutina '{}' no puede declarar tipo de retorno", nombre),
+                    &self.token_anterior().pos,
+                )
+                .con_nota("una corrutina devuelve su identificador para poder cancelarla");
+                self.reportar(error);
+            }
+            Some(self.tipo()?)
+        } else {
+            None
+        };
         
+        self.consumir(TipoToken::LlaveAbre, "Se esperaba '{' antes del cuerpo de función")?;
+        
+        let en_corrutina = std::mem::replace(&mut self.en_corrutina, es_corrutina);
+        let bucles = std::mem::take(&mut self.bucles);
+        self.ambitos.push(parametros.iter().map(|p| (p.nombre.clone(), None)).collect());
+        let cuerpo = self.cuerpo_de_bloque("Se esperaba '}' al final de función");
+        self.ambitos.pop();
+        self.bucles = bucles;
+        self.en_corrutina = en_corrutina;
+        
+        Ok(Funcion {
+            nombre,
+            parametros,
+            tipo_retorno,
+            cuerpo,
+            es_corrutina,
+            doc,
+            pos: self.tramo(&pos),
+        })
+    }
+    
+    /// Parsea los parámetros de una función, ya pasado el '(', hasta el ')'
+    fn parametros(&mut self) -> Result<Vec<Parametro>, Diagnostico> {
         let mut parametros = Vec::new();
         if !self.verificar(&TipoToken::ParentesisCierra) {
             loop {
-                let nombre_param = match &self.token_actual().tipo {
-                    TipoToken::Identificador(id) => {
-                        let n = id.clone();
+                let pos_param = self.token_actual().pos.clone();
+                let nombre_param = match self.nombre_actual() {
+                    Some(n) => {
                         self.avanzar();
                         n
                     }
-                    _ => return Err(format!(
-                        "Se esperaba nombre de parámetro, se encontró {} en {}",
-                        self.token_actual().tipo,
-                        self.token_actual().pos
+                    _ => return Err(self.error_actual(
+                        Codigo::NombreEsperado,
+                        "Se esperaba nombre de parámetro",
                     )),
                 };
                 
@@ -117,6 +441,7 @@ impl Parser {
                 parametros.push(Parametro {
                     nombre: nombre_param,
                     tipo_dato,
+                    pos: self.tramo(&pos_param),
                 });
                 
                 if !self.coincidir(&TipoToken::Coma) {
@@ -126,33 +451,146 @@ impl Parser {
         }
         
         self.consumir(TipoToken::ParentesisCierra, "Se esperaba ')' después de parámetros")?;
-        
+        Ok(parametros)
+    }
+    
+    /// Parsea una función anónima: `funcion(x: numero): numero { ... }` o
+    /// su forma corta `funcion(x: numero) => x * 2`, que retorna la expresión
+    fn funcion_anonima(&mut self) -> Result<Expresion, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        self.consumir(TipoToken::Funcion, "Se esperaba 'funcion'")?;
+        self.consumir(TipoToken::ParentesisAbre, "Se esperaba '(' después de 'funcion'")?;
+        let parametros = self.parametros()?;
         let tipo_retorno = if self.coincidir(&TipoToken::DosPuntos) {
-            Some(self.tipo()?)
+            Some(Box::new(self.tipo()?))
         } else {
             None
         };
         
-        self.consumir(TipoToken::LlaveAbre, "Se esperaba '{' antes del cuerpo de función")?;
+        // La función se ejecuta cuando se la llama, fuera de la corrutina
+        // y de los bucles en los que se escribió
+        let en_corrutina = std::mem::replace(&mut self.en_corrutina, false);
+        let bucles = std::mem::take(&mut self.bucles);
+        self.ambitos.push(parametros.iter().map(|p| (p.nombre.clone(), None)).collect());
+        let cuerpo = if self.coincidir(&TipoToken::Flecha) {
+            self.expresion().map(|expr| {
+                let pos = expr.posicion().clone();
+                vec![Sentencia::Retornar(Some(expr), pos)]
+            })
+        } else {
+            self.bloque("funcion(...)")
+        };
+        self.ambitos.pop();
+        self.bucles = bucles;
+        self.en_corrutina = en_corrutina;
         
-        let mut cuerpo = Vec::new();
+        Ok(Expresion::FuncionAnonima(parametros, tipo_retorno, cuerpo?, self.tramo(&pos)))
+    }
+    
+    /// Parsea una estructura: `estructura Punto { x: decimal, y: decimal }`.
+    /// Los campos se separan con comas o saltos de línea.
+    fn estructura(&mut self) -> Result<Estructura, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        let doc = self.documentacion.remove(&self.actual);
+        self.consumir(TipoToken::Estructura, "Se esperaba 'estructura'")?;
+        
+        let nombre = self.nombre("Se esperaba nombre de estructura")?;
+        
+        self.consumir(TipoToken::LlaveAbre, "Se esperaba '{' después del nombre de estructura")?;
+        
+        let mut campos: Vec<Campo> = Vec::new();
         while !self.verificar(&TipoToken::LlaveCierra) && !self.fin() {
-            cuerpo.push(self.sentencia()?);
+            let pos_campo = self.token_actual().pos.clone();
+            let nombre_campo = self.nombre_de_campo("Se esperaba nombre de campo")?;
+            self.consumir(TipoToken::DosPuntos, "Se esperaba ':' después del nombre de campo")?;
+            let tipo_dato = self.tipo()?;
+            let campo = Campo {
+                nombre: nombre_campo,
+                tipo_dato,
+                pos: self.tramo(&pos_campo),
+            };
+            
+            if let Some(anterior) = campos.iter().find(|c| c.nombre == campo.nombre) {
+                let error = Diagnostico::new(
+                    Codigo::Duplicado,
+                    format!("La estructura '{}' ya tiene un campo '{}'", nombre, campo.nombre),
+                    &campo.pos,
+                )
+                .con_etiqueta(&anterior.pos, "declarado antes aquí");
+                self.reportar(error);
+            } else {
+                campos.push(campo);
+            }
+            self.coincidir(&TipoToken::Coma);
         }
         
-        self.consumir(TipoToken::LlaveCierra, "Se esperaba '}' al final de función")?;
+        self.consumir(TipoToken::LlaveCierra, "Se esperaba '}' al final de estructura")?;
         
-        Ok(Funcion {
+        Ok(Estructura {
             nombre,
-            parametros,
-            tipo_retorno,
-            cuerpo,
-            pos,
+            campos,
+            doc,
+            pos: self.tramo(&pos),
+        })
+    }
+    
+    /// Parsea una enumeración: `enumeracion Forma { Circulo(decimal), Vacia }`.
+    /// Las variantes se separan con comas o saltos de línea.
+    fn enumeracion(&mut self) -> Result<Enumeracion, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        let doc = self.documentacion.remove(&self.actual);
+        self.consumir(TipoToken::Enumeracion, "Se esperaba 'enumeracion'")?;
+        
+        let nombre = self.nombre("Se esperaba nombre de enumeración")?;
+        
+        self.consumir(TipoToken::LlaveAbre, "Se esperaba '{' después del nombre de enumeración")?;
+        
+        let mut variantes: Vec<Variante> = Vec::new();
+        while !self.verificar(&TipoToken::LlaveCierra) && !self.fin() {
+            let pos_variante = self.token_actual().pos.clone();
+            let nombre_variante = self.nombre("Se esperaba nombre de variante")?;
+            let mut campos = Vec::new();
+            if self.coincidir(&TipoToken::ParentesisAbre) {
+                while !self.verificar(&TipoToken::ParentesisCierra) {
+                    campos.push(self.tipo()?);
+                    if !self.coincidir(&TipoToken::Coma) {
+                        break;
+                    }
+                }
+                self.consumir(TipoToken::ParentesisCierra, "Se esperaba ')' después de los tipos de la variante")?;
+            }
+            let variante = Variante {
+                nombre: nombre_variante,
+                campos,
+                pos: self.tramo(&pos_variante),
+            };
+            
+            if let Some(anterior) = variantes.iter().find(|v| v.nombre == variante.nombre) {
+                let error = Diagnostico::new(
+                    Codigo::Duplicado,
+                    format!("La enumeración '{}' ya tiene una variante '{}'", nombre, variante.nombre),
+                    &variante.pos,
+                )
+                .con_etiqueta(&anterior.pos, "declarada antes aquí");
+                self.reportar(error);
+            } else {
+                variantes.push(variante);
+            }
+            self.coincidir(&TipoToken::Coma);
+        }
+        
+        self.consumir(TipoToken::LlaveCierra, "Se esperaba '}' al final de enumeración")?;
+        
+        Ok(Enumeracion {
+            nombre,
+            variantes,
+            doc,
+            pos: self.tramo(&pos),
         })
     }
     
     /// Parsea un tipo de dato
-    fn tipo(&mut self) -> Result<Tipo, String> {
+    fn tipo(&mut self) -> Result<Tipo, Diagnostico> {
         let tipo = match &self.token_actual().tipo {
             TipoToken::TipoNumero => Tipo::Numero,
             TipoToken::TipoDecimal => Tipo::Decimal,
@@ -162,10 +600,44 @@ impl Parser {
             TipoToken::TipoPose => Tipo::Pose,
             TipoToken::TipoMano => Tipo::Mano,
             TipoToken::TipoControlador => Tipo::Controlador,
-            _ => return Err(format!(
-                "Se esperaba un tipo de dato, se encontró {} en {}",
-                self.token_actual().tipo,
-                self.token_actual().pos
+            TipoToken::TipoLista => {
+                self.avanzar();
+                self.consumir(TipoToken::Menor, "Se esperaba '<' después de 'lista', como en lista<numero>")?;
+                let elemento = self.tipo()?;
+                self.consumir(TipoToken::Mayor, "Se esperaba '>' para cerrar el tipo de la lista")?;
+                return Ok(Tipo::Lista(Box::new(elemento)));
+            }
+            TipoToken::TipoMapa => {
+                self.avanzar();
+                self.consumir(TipoToken::Menor, "Se esperaba '<' después de 'mapa', como en mapa<texto, numero>")?;
+                let clave = self.tipo()?;
+                self.consumir(TipoToken::Coma, "Se esperaba ',' entre el tipo de las claves y el de los valores")?;
+                let valor = self.tipo()?;
+                self.consumir(TipoToken::Mayor, "Se esperaba '>' para cerrar el tipo del mapa")?;
+                return Ok(Tipo::Mapa(Box::new(clave), Box::new(valor)));
+            }
+            TipoToken::Funcion => {
+                self.avanzar();
+                self.consumir(TipoToken::ParentesisAbre, "Se esperaba '(' después de 'funcion', como en funcion(numero): numero")?;
+                let mut parametros = Vec::new();
+                while !self.verificar(&TipoToken::ParentesisCierra) {
+                    parametros.push(self.tipo()?);
+                    if !self.coincidir(&TipoToken::Coma) {
+                        break;
+                    }
+                }
+                self.consumir(TipoToken::ParentesisCierra, "Se esperaba ')' después de los tipos de los parámetros")?;
+                let retorno = if self.coincidir(&TipoToken::DosPuntos) {
+                    Some(Box::new(self.tipo()?))
+                } else {
+                    None
+                };
+                return Ok(Tipo::Funcion(parametros, retorno));
+            }
+            TipoToken::Identificador(nombre) => Tipo::Nombrado(nombre.clone()),
+            _ => return Err(self.error_actual(
+                Codigo::TokenInesperado,
+                "Se esperaba un tipo de dato",
             )),
         };
         
@@ -174,64 +646,121 @@ impl Parser {
     }
     
     /// Parsea una sentencia
-    fn sentencia(&mut self) -> Result<Sentencia, String> {
+    fn sentencia(&mut self) -> Result<Sentencia, Diagnostico> {
         match &self.token_actual().tipo {
             TipoToken::Mostrar => self.sentencia_mostrar(),
             TipoToken::Var => self.sentencia_var(),
+            TipoToken::Constante => self.sentencia_constante(),
             TipoToken::Si => self.sentencia_si(),
-            TipoToken::Mientras => self.sentencia_mientras(),
-            TipoToken::Para => self.sentencia_para(),
+            TipoToken::Mientras => self.sentencia_mientras(None),
+            TipoToken::Para => self.sentencia_para(None),
             TipoToken::Retornar => self.sentencia_retornar(),
-            TipoToken::Identificador(_) => {
+            TipoToken::Romper | TipoToken::Continuar => self.sentencia_salto(),
+            _ if self.es_etiqueta_de_bucle() => self.bucle_etiquetado(),
+            TipoToken::Esperar => self.sentencia_esperar(),
+            TipoToken::Emitir => self.sentencia_emitir(),
+            TipoToken::Escuchar => self.sentencia_escuchar(),
+            TipoToken::Segun => self.sentencia_segun(),
+            TipoToken::Intentar => self.sentencia_intentar(),
+            TipoToken::Lanzar => self.sentencia_lanzar(),
+            tipo if matches!(tipo, TipoToken::Identificador(_)) || Self::es_contextual(tipo) => {
                 // Puede ser asignación o expresión
                 let pos_guardada = self.actual;
-                let nombre = match &self.token_actual().tipo {
-                    TipoToken::Identificador(id) => id.clone(),
-                    _ => unreachable!(),
-                };
+                let nombre = self.nombre_actual().unwrap();
                 self.avanzar();
                 
                 if self.verificar(&TipoToken::Asignacion) {
                     self.avanzar();
                     let valor = self.expresion()?;
-                    Ok(Sentencia::Asignacion(nombre, valor, self.token_en(pos_guardada).pos.clone()))
+                    let pos = self.tramo(&self.token_en(pos_guardada).pos);
+                    self.comprobar_no_constante(&nombre, &pos);
+                    Ok(Sentencia::Asignacion(nombre, valor, pos))
                 } else {
-                    // Es una expresión (probablemente llamada a función)
+                    // Es una expresión (probablemente llamada a función) o
+                    // una asignación a un elemento o a un campo
                     self.actual = pos_guardada;
                     let expr = self.expresion()?;
-                    Ok(Sentencia::Expresion(expr))
+                    if !self.verificar(&TipoToken::Asignacion) {
+                        let pos = expr.posicion().clone();
+                        return Ok(Sentencia::Expresion(expr, pos));
+                    }
+                    
+                    match expr {
+                        Expresion::Indice(lista, indice, _) => {
+                            self.avanzar();
+                            let valor = self.expresion()?;
+                            let pos = self.tramo(lista.posicion());
+                            Ok(Sentencia::AsignacionIndice(*lista, *indice, valor, pos))
+                        }
+                        Expresion::Campo(registro, campo, pos_campo) => {
+                            self.avanzar();
+                            let valor = self.expresion()?;
+                            let pos = self.tramo(registro.posicion());
+                            // Las estructuras son valores: cambiar un campo
+                            // cambia la variable que la guarda
+                            match Self::raiz_de_campo(&registro) {
+                                Some(Some(variable)) => self.comprobar_no_constante(&variable, &pos),
+                                Some(None) => {}
+                                None => self.reportar(
+                                    Diagnostico::new(
+                                        Codigo::TokenInesperado,
+                                        format!("No se puede asignar al campo '{}' de un valor temporal", campo),
+                                        &pos_campo,
+                                    )
+                                    .con_nota("guarda la estructura en una variable y cambia el campo de la variable"),
+                                ),
+                            }
+                            Ok(Sentencia::AsignacionCampo(*registro, campo, valor, pos))
+                        }
+                        expr => self.asignacion_invalida(expr),
+                    }
                 }
             }
             _ => {
                 let expr = self.expresion()?;
-                Ok(Sentencia::Expresion(expr))
+                if self.verificar(&TipoToken::Asignacion) {
+                    return self.asignacion_invalida(expr);
+                }
+                let pos = expr.posicion().clone();
+                Ok(Sentencia::Expresion(expr, pos))
             }
         }
     }
     
+    /// Informa de un `=` detrás de algo a lo que no se puede asignar, como
+    /// `f() = 3`, y se salta el valor para seguir con la siguiente sentencia
+    fn asignacion_invalida(&mut self, destino: Expresion) -> Result<Sentencia, Diagnostico> {
+        self.consumir(TipoToken::Asignacion, "Se esperaba '='")?;
+        self.expresion()?;
+        self.reportar(
+            Diagnostico::new(Codigo::TokenInesperado, "No se puede asignar a esta expresión", destino.posicion())
+                .con_nota("solo se asigna a una variable, a un elemento (`lista[0] = x`) o a un campo (`p.x = 1`)"),
+        );
+        let pos = destino.posicion().clone();
+        Ok(Sentencia::Expresion(destino, pos))
+    }
+    
     /// Parsea sentencia mostrar
-    fn sentencia_mostrar(&mut self) -> Result<Sentencia, String> {
+    fn sentencia_mostrar(&mut self) -> Result<Sentencia, Diagnostico> {
         let pos = self.token_actual().pos.clone();
         self.consumir(TipoToken::Mostrar, "Se esperaba 'mostrar'")?;
         let expr = self.expresion()?;
-        Ok(Sentencia::Mostrar(expr, pos))
+        Ok(Sentencia::Mostrar(expr, self.tramo(&pos)))
     }
     
     /// Parsea sentencia var
-    fn sentencia_var(&mut self) -> Result<Sentencia, String> {
+    fn sentencia_var(&mut self) -> Result<Sentencia, Diagnostico> {
         let pos = self.token_actual().pos.clone();
         self.consumir(TipoToken::Var, "Se esperaba 'var'")?;
         
-        let nombre = match &self.token_actual().tipo {
-            TipoToken::Identificador(id) => {
-                let n = id.clone();
+        let nombre = match self.nombre_actual() {
+            Some(n) => {
                 self.avanzar();
                 n
             }
-            _ => return Err(format!(
-                "Se esperaba nombre de variable, se encontró {} en {}",
-                self.token_actual().tipo,
-                self.token_actual().pos
+            _ => return Err(self.error_actual(
+                Codigo::NombreEsperado,
+                "Se esperaba nombre de variable",
             )),
         };
         
@@ -239,102 +768,378 @@ impl Parser {
         
         let valor = self.expresion()?;
         
+        let pos = self.tramo(&pos);
+        self.declarar(&nombre, None, &pos);
         Ok(Sentencia::Var(nombre, valor, pos))
     }
     
+    /// Parsea sentencia constante, con la documentación que la precede
+    fn sentencia_constante(&mut self) -> Result<Sentencia, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        let doc = self.documentacion.remove(&self.actual);
+        self.consumir(TipoToken::Constante, "Se esperaba 'constante'")?;
+        
+        let nombre = self.nombre("Se esperaba nombre de constante")?;
+        
+        self.consumir(TipoToken::Asignacion, "Se esperaba '=' después del nombre de constante")?;
+        
+        let valor = self.expresion()?;
+        
+        let pos = self.tramo(&pos);
+        self.declarar(&nombre, Some(pos.clone()), &pos);
+        Ok(Sentencia::Constante(nombre, valor, doc, pos))
+    }
+    
     /// Parsea sentencia si
-    fn sentencia_si(&mut self) -> Result<Sentencia, String> {
+    fn sentencia_si(&mut self) -> Result<Sentencia, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        let mut ramas = vec![self.rama_si("si")?];
+        
+        // Cadena de `sino si`, y un `sino` opcional al final
+        let mut sino = None;
+        while self.coincidir(&TipoToken::Sino) {
+            if self.verificar(&TipoToken::Si) {
+                ramas.push(self.rama_si("sino si")?);
+                continue;
+            }
+            self.consumir(TipoToken::LlaveAbre, "Se esperaba '{' o 'si' después de 'sino'")?;
+            sino = Some(self.cuerpo_de_bloque("Se esperaba '}' al final del bloque 'sino'"));
+            break;
+        }
+        
+        Ok(Sentencia::Si(ramas, sino, self.tramo(&pos)))
+    }
+    
+    /// Parsea `si condición { }`; `nombre` es la rama en los mensajes
+    fn rama_si(&mut self, nombre: &str) -> Result<Rama, Diagnostico> {
         let pos = self.token_actual().pos.clone();
         self.consumir(TipoToken::Si, "Se esperaba 'si'")?;
         
         let condicion = self.expresion()?;
         
         self.consumir(TipoToken::LlaveAbre, "Se esperaba '{' después de condición")?;
+        let cuerpo = self.cuerpo_de_bloque(&format!("Se esperaba '}}' al final del bloque '{}'", nombre));
         
-        let mut entonces = Vec::new();
-        while !self.verificar(&TipoToken::LlaveCierra) && !self.fin() {
-            entonces.push(self.sentencia()?);
+        Ok(Rama {
+            condicion,
+            cuerpo,
+            pos: self.tramo(&pos),
+        })
+    }
+    
+    /// Parsea `intentar { } capturar e { } finalmente { }`; puede faltar
+    /// `capturar` o `finalmente`, pero no los dos
+    fn sentencia_intentar(&mut self) -> Result<Sentencia, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        self.consumir(TipoToken::Intentar, "Se esperaba 'intentar'")?;
+        
+        let en_intentar = std::mem::replace(&mut self.en_intentar, true);
+        let partes = self.bloque("intentar").and_then(|cuerpo| {
+            let captura = self.captura()?;
+            let finalmente = if self.coincidir(&TipoToken::Finalmente) {
+                Some(self.bloque("finalmente")?)
+            } else {
+                None
+            };
+            Ok((cuerpo, captura, finalmente))
+        });
+        self.en_intentar = en_intentar;
+        let (cuerpo, captura, finalmente) = partes?;
+        
+        if captura.is_none() && finalmente.is_none() {
+            return Err(self.error_actual(
+                Codigo::TokenInesperado,
+                "Se esperaba 'capturar' o 'finalmente' después del bloque de 'intentar'",
+            ));
         }
         
-        self.consumir(TipoToken::LlaveCierra, "Se esperaba '}' al final del bloque 'si'")?;
+        Ok(Sentencia::Intentar(cuerpo, captura, finalmente, self.tramo(&pos)))
+    }
+    
+    /// Parsea el `capturar e { ... }` de un `intentar`, si lo tiene
+    fn captura(&mut self) -> Result<Option<Captura>, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        if !self.coincidir(&TipoToken::Capturar) {
+            return Ok(None);
+        }
+        let variable = if self.verificar(&TipoToken::LlaveAbre) {
+            None
+        } else {
+            Some(self.nombre("Se esperaba el nombre del error o '{' después de 'capturar'")?)
+        };
+        self.ambitos.push(variable.iter().map(|v| (v.clone(), None)).collect());
+        let cuerpo = self.bloque("capturar");
+        self.ambitos.pop();
+        Ok(Some(Captura { variable, cuerpo: cuerpo?, pos: self.tramo(&pos) }))
+    }
+    
+    /// Parsea `lanzar valor`
+    fn sentencia_lanzar(&mut self) -> Result<Sentencia, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        self.consumir(TipoToken::Lanzar, "Se esperaba 'lanzar'")?;
+        let valor = self.expresion()?;
+        Ok(Sentencia::Lanzar(valor, self.tramo(&pos)))
+    }
+    
+    /// Parsea `segun` como sentencia: el cuerpo de cada caso es un bloque o
+    /// una sola sentencia
+    fn sentencia_segun(&mut self) -> Result<Sentencia, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        let (valor, casos) = self.segun(|parser| {
+            if parser.coincidir(&TipoToken::LlaveAbre) {
+                Ok(parser.cuerpo_de_bloque("Se esperaba '}' al final del caso"))
+            } else {
+                Ok(vec![parser.sentencia()?])
+            }
+        })?;
+        Ok(Sentencia::Segun(valor, casos, self.tramo(&pos)))
+    }
+    
+    /// Parsea `segun valor { caso Patron => cuerpo ... }`. Los casos se
+    /// separan con comas o saltos de línea, y los nombres de su patrón solo
+    /// se ven en su cuerpo.
+    fn segun<T>(
+        &mut self,
+        mut cuerpo: impl FnMut(&mut Self) -> Result<T, Diagnostico>,
+    ) -> Result<(Expresion, Vec<Caso<T>>), Diagnostico> {
+        self.consumir(TipoToken::Segun, "Se esperaba 'segun'")?;
+        let valor = self.expresion()?;
+        self.consumir(TipoToken::LlaveAbre, "Se esperaba '{' después del valor de 'segun'")?;
         
-        let sino = if self.coincidir(&TipoToken::Sino) {
-            self.consumir(TipoToken::LlaveAbre, "Se esperaba '{' después de 'sino'")?;
+        let mut casos = Vec::new();
+        while !self.verificar(&TipoToken::LlaveCierra) && !self.fin() {
+            let pos = self.token_actual().pos.clone();
+            let patron = self.patron()?;
+            self.consumir(TipoToken::Flecha, "Se esperaba '=>' después del patrón")?;
             
-            let mut bloque_sino = Vec::new();
-            while !self.verificar(&TipoToken::LlaveCierra) && !self.fin() {
-                bloque_sino.push(self.sentencia()?);
+            self.ambitos.push(Vec::new());
+            if let Patron::Variante(_, Some(nombres), pos_patron) = &patron {
+                for nombre in nombres {
+                    self.declarar(nombre, None, pos_patron);
+                }
             }
+            let cuerpo = cuerpo(self);
+            self.ambitos.pop();
             
-            self.consumir(TipoToken::LlaveCierra, "Se esperaba '}' al final del bloque 'sino'")?;
-            
-            Some(bloque_sino)
-        } else {
-            None
-        };
+            casos.push(Caso {
+                patron,
+                cuerpo: cuerpo?,
+                pos: self.tramo(&pos),
+            });
+            self.coincidir(&TipoToken::Coma);
+        }
         
-        Ok(Sentencia::Si(condicion, entonces, sino, pos))
+        self.consumir(TipoToken::LlaveCierra, "Se esperaba '}' al final de 'segun'")?;
+        Ok((valor, casos))
+    }
+    
+    /// Parsea el patrón de un caso: `caso Variante`, `caso Variante(a, _)`,
+    /// `caso <literal>` u `otro`
+    fn patron(&mut self) -> Result<Patron, Diagnostico> {
+        let inicio = self.token_actual().pos.clone();
+        if self.coincidir_contextual("otro") {
+            return Ok(Patron::Otro(inicio));
+        }
+        if !self.coincidir_contextual("caso") {
+            return Err(self.error_actual(Codigo::TokenInesperado, "Se esperaba 'caso' u 'otro'"));
+        }
+        
+        let pos = self.token_actual().pos.clone();
+        if let TipoToken::Identificador(nombre) = &self.token_actual().tipo {
+            let nombre = nombre.clone();
+            self.avanzar();
+            let mut nombres = None;
+            if self.coincidir(&TipoToken::ParentesisAbre) {
+                let mut lista = Vec::new();
+                while !self.verificar(&TipoToken::ParentesisCierra) {
+                    lista.push(self.nombre_de_campo("Se esperaba un nombre para el valor de la variante")?);
+                    if !self.coincidir(&TipoToken::Coma) {
+                        break;
+                    }
+                }
+                self.consumir(TipoToken::ParentesisCierra, "Se esperaba ')' después de los valores de la variante")?;
+                nombres = Some(lista);
+            }
+            return Ok(Patron::Variante(nombre, nombres, self.tramo(&pos)));
+        }
+        
+        let literal = self.unario()?;
+        let es_literal = |expr: &Expresion| {
+            matches!(
+                expr,
+                Expresion::Numero(..)
+                    | Expresion::Decimal(..)
+                    | Expresion::Medida(..)
+                    | Expresion::Texto(..)
+                    | Expresion::Booleano(..)
+            )
+        };
+        match &literal {
+            Expresion::Unaria(OperadorUnario::Negacion, numero, _) if es_literal(numero) => Ok(Patron::Literal(literal)),
+            expr if es_literal(expr) => Ok(Patron::Literal(literal)),
+            expr => Err(Diagnostico::new(
+                Codigo::TokenInesperado,
+                "Patrón inválido: un caso nombra una variante o un valor literal",
+                expr.posicion(),
+            )
+            .con_sugerencia("compara con otros valores usando 'si'")),
+        }
     }
     
     /// Parsea sentencia mientras
-    fn sentencia_mientras(&mut self) -> Result<Sentencia, String> {
+    fn sentencia_mientras(&mut self, etiqueta: Option<String>) -> Result<Sentencia, Diagnostico> {
         let pos = self.token_actual().pos.clone();
         self.consumir(TipoToken::Mientras, "Se esperaba 'mientras'")?;
         
         let condicion = self.expresion()?;
         
         self.consumir(TipoToken::LlaveAbre, "Se esperaba '{' después de condición")?;
+        self.bucles.push(etiqueta.clone());
+        let cuerpo = self.cuerpo_de_bloque("Se esperaba '}' al final del bloque 'mientras'");
+        self.bucles.pop();
         
-        let mut cuerpo = Vec::new();
-        while !self.verificar(&TipoToken::LlaveCierra) && !self.fin() {
-            cuerpo.push(self.sentencia()?);
+        Ok(Sentencia::Mientras(condicion, cuerpo, etiqueta, self.tramo(&pos)))
+    }
+    
+    /// Si el token actual empieza un bucle con etiqueta: `externo: para`
+    fn es_etiqueta_de_bucle(&self) -> bool {
+        self.nombre_actual().is_some()
+            && matches!(self.tokens.get(self.actual + 1).map(|t| &t.tipo), Some(TipoToken::DosPuntos))
+            && matches!(
+                self.tokens.get(self.actual + 2).map(|t| &t.tipo),
+                Some(TipoToken::Mientras | TipoToken::Para)
+            )
+    }
+    
+    /// Parsea un bucle con etiqueta, para salir de él o continuarlo desde
+    /// un bucle interior: `externo: para i = 0, 10 { ... romper externo }`
+    fn bucle_etiquetado(&mut self) -> Result<Sentencia, Diagnostico> {
+        let inicio = self.token_actual().pos.clone();
+        let etiqueta = self.nombre_actual().unwrap();
+        self.avanzar();
+        self.consumir(TipoToken::DosPuntos, "Se esperaba ':' después de la etiqueta")?;
+        
+        if self.bucles.contains(&Some(etiqueta.clone())) {
+            self.reportar(Diagnostico::new(
+                Codigo::Duplicado,
+                format!("La etiqueta '{}' ya la usa un bucle exterior", etiqueta),
+                &inicio,
+            ));
         }
         
-        self.consumir(TipoToken::LlaveCierra, "Se esperaba '}' al final del bloque 'mientras'")?;
+        let mut bucle = if self.verificar(&TipoToken::Mientras) {
+            self.sentencia_mientras(Some(etiqueta))?
+        } else {
+            self.sentencia_para(Some(etiqueta))?
+        };
         
-        Ok(Sentencia::Mientras(condicion, cuerpo, pos))
+        // El tramo del bucle empieza en su etiqueta
+        if let Sentencia::Mientras(.., pos) | Sentencia::Para(.., pos) = &mut bucle {
+            *pos = inicio.hasta(pos);
+        }
+        Ok(bucle)
     }
     
-    /// Parsea sentencia para
-    fn sentencia_para(&mut self) -> Result<Sentencia, String> {
+    /// Parsea `romper` o `continuar`, con la etiqueta opcional del bucle
+    /// al que afectan; sin etiqueta afectan al bucle más interior
+    fn sentencia_salto(&mut self) -> Result<Sentencia, Diagnostico> {
         let pos = self.token_actual().pos.clone();
-        self.consumir(TipoToken::Para, "Se esperaba 'para'")?;
+        let romper = self.verificar(&TipoToken::Romper);
+        let palabra = if romper { "romper" } else { "continuar" };
+        self.avanzar();
         
-        let variable = match &self.token_actual().tipo {
-            TipoToken::Identificador(id) => {
-                let n = id.clone();
+        // La etiqueta va en la misma línea
+        let etiqueta = match self.nombre_actual() {
+            Some(nombre) if !self.empieza_linea() => {
                 self.avanzar();
-                n
+                Some(nombre)
             }
-            _ => return Err(format!(
-                "Se esperaba nombre de variable, se encontró {} en {}",
-                self.token_actual().tipo,
-                self.token_actual().pos
-            )),
+            _ => None,
         };
+        let pos = self.tramo(&pos);
         
-        self.consumir(TipoToken::Asignacion, "Se esperaba '=' después del nombre de variable")?;
+        if self.bucles.is_empty() {
+            self.reportar(Diagnostico::new(
+                Codigo::FueraDeContexto,
+                format!("'{}' solo puede usarse dentro de un bucle 'mientras' o 'para'", palabra),
+                &pos,
+            )
+            .con_nota("las funciones, oyentes y temporizadores no ven los bucles que los rodean"));
+        } else if let Some(etiqueta) = &etiqueta {
+            if !self.bucles.contains(&Some(etiqueta.clone())) {
+                self.reportar(Diagnostico::new(
+                    Codigo::ReferenciaDesconocida,
+                    format!("Ningún bucle que rodea a '{}' tiene la etiqueta '{}'", palabra, etiqueta),
+                    &pos,
+                )
+                .con_sugerencia(format!("etiqueta el bucle así: {}: mientras ... {{ }}", etiqueta)));
+            }
+        }
         
-        let inicio = self.expresion()?;
+        Ok(if romper {
+            Sentencia::Romper(etiqueta, pos)
+        } else {
+            Sentencia::Continuar(etiqueta, pos)
+        })
+    }
+    
+    /// Parsea sentencia para: `para i = 0, 10 { }`, `para i = 10 hasta 0
+    /// paso -2 { }` o `para letra en "hola" { }`
+    fn sentencia_para(&mut self, etiqueta: Option<String>) -> Result<Sentencia, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        self.consumir(TipoToken::Para, "Se esperaba 'para'")?;
         
-        self.consumir(TipoToken::Coma, "Se esperaba ',' entre inicio y fin")?;
+        let variable = match self.nombre_actual() {
+            Some(n) => {
+                self.avanzar();
+                n
+            }
+            _ => return Err(self.error_actual(
+                Codigo::NombreEsperado,
+                "Se esperaba nombre de variable",
+            )),
+        };
         
-        let fin = self.expresion()?;
+        let iteracion = if self.coincidir_contextual("en") {
+            Iteracion::Coleccion(self.expresion()?)
+        } else {
+            self.consumir(TipoToken::Asignacion, "Se esperaba '=' o 'en' después del nombre de variable")?;
+            
+            let inicio = self.expresion()?;
+            
+            let inclusivo = self.coincidir(&TipoToken::Hasta);
+            if !inclusivo {
+                self.consumir(TipoToken::Coma, "Se esperaba ',' o 'hasta' entre inicio y fin")?;
+            }
+            
+            let fin = self.expresion()?;
+            
+            let paso = if self.coincidir_contextual("paso") {
+                Some(self.expresion()?)
+            } else {
+                None
+            };
+            
+            Iteracion::Rango {
+                inicio,
+                fin,
+                inclusivo,
+                paso,
+            }
+        };
         
         self.consumir(TipoToken::LlaveAbre, "Se esperaba '{' después del rango")?;
+        self.ambitos.push(vec![(variable.clone(), None)]);
+        self.bucles.push(etiqueta.clone());
+        let cuerpo = self.cuerpo_de_bloque("Se esperaba '}' al final del bloque 'para'");
+        self.bucles.pop();
+        self.ambitos.pop();
         
-        let mut cuerpo = Vec::new();
-        while !self.verificar(&TipoToken::LlaveCierra) && !self.fin() {
-            cuerpo.push(self.sentencia()?);
-        }
-        
-        self.consumir(TipoToken::LlaveCierra, "Se esperaba '}' al final del bloque 'para'")?;
-        
-        Ok(Sentencia::Para(variable, inicio, fin, cuerpo, pos))
+        Ok(Sentencia::Para(variable, iteracion, cuerpo, etiqueta, self.tramo(&pos)))
     }
     
     /// Parsea sentencia retornar
-    fn sentencia_retornar(&mut self) -> Result<Sentencia, String> {
+    fn sentencia_retornar(&mut self) -> Result<Sentencia, Diagnostico> {
         let pos = self.token_actual().pos.clone();
         self.consumir(TipoToken::Retornar, "Se esperaba 'retornar'")?;
         
@@ -344,21 +1149,138 @@ impl Parser {
             Some(self.expresion()?)
         };
         
-        Ok(Sentencia::Retornar(valor, pos))
+        Ok(Sentencia::Retornar(valor, self.tramo(&pos)))
+    }
+    
+    /// Parsea sentencia esperar (`esperar 2` o `esperar hasta condición`)
+    fn sentencia_esperar(&mut self) -> Result<Sentencia, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        self.consumir(TipoToken::Esperar, "Se esperaba 'esperar'")?;
+        
+        if !self.en_corrutina {
+            self.reportar(Diagnostico::new(
+                Codigo::FueraDeContexto,
+                "'esperar' solo puede usarse dentro de una corrutina",
+                &pos,
+            )
+            .con_sugerencia("declara la función con 'corrutina' en lugar de 'funcion'"));
+        } else if self.en_intentar {
+            self.reportar(Diagnostico::new(
+                Codigo::FueraDeContexto,
+                "'esperar' no puede usarse dentro de 'intentar', 'capturar' o 'finalmente'",
+                &pos,
+            )
+            .con_nota("un 'intentar' se ejecuta entero en el mismo frame")
+            .con_sugerencia("espera antes o después del 'intentar' y deja dentro solo lo que puede fallar"));
+        }
+        
+        let espera = if self.coincidir(&TipoToken::Hasta) {
+            Espera::Hasta(self.expresion()?)
+        } else {
+            Espera::Segundos(self.expresion()?)
+        };
+        
+        Ok(Sentencia::Esperar(espera, self.tramo(&pos)))
+    }
+    
+    /// Parsea sentencia emitir (`emitir "evento"(datos, ...)`)
+    fn sentencia_emitir(&mut self) -> Result<Sentencia, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        self.consumir(TipoToken::Emitir, "Se esperaba 'emitir'")?;
+        
+        let evento = self.nombre_evento("emitir")?;
+        
+        let mut datos = Vec::new();
+        if self.coincidir(&TipoToken::ParentesisAbre) {
+            if !self.verificar(&TipoToken::ParentesisCierra) {
+                loop {
+                    datos.push(self.expresion()?);
+                    if !self.coincidir(&TipoToken::Coma) {
+                        break;
+                    }
+                }
+            }
+            self.consumir(TipoToken::ParentesisCierra, "Se esperaba ')' después de los datos del evento")?;
+        }
+        
+        Ok(Sentencia::Emitir(evento, datos, self.tramo(&pos)))
+    }
+    
+    /// Parsea sentencia escuchar (`escuchar "evento" (datos) { ... }`). En
+    /// vez del bloque puede ir una función: `escuchar "evento" al_chocar`.
+    fn sentencia_escuchar(&mut self) -> Result<Sentencia, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        self.consumir(TipoToken::Escuchar, "Se esperaba 'escuchar'")?;
+        
+        let evento = self.nombre_evento("escuchar")?;
+        
+        if !self.verificar(&TipoToken::ParentesisAbre) && !self.verificar(&TipoToken::LlaveAbre) {
+            let manejador = self.expresion()?;
+            return Ok(Sentencia::EscucharFuncion(evento, manejador, self.tramo(&pos)));
+        }
+        
+        let mut parametros = Vec::new();
+        if self.coincidir(&TipoToken::ParentesisAbre) {
+            if !self.verificar(&TipoToken::ParentesisCierra) {
+                loop {
+                    match self.nombre_actual() {
+                        Some(nombre) => {
+                            self.avanzar();
+                            parametros.push(nombre);
+                        }
+                        None => return Err(self.error_actual(
+                            Codigo::NombreEsperado,
+                            "Se esperaba nombre de parámetro",
+                        )),
+                    }
+                    if !self.coincidir(&TipoToken::Coma) {
+                        break;
+                    }
+                }
+            }
+            self.consumir(TipoToken::ParentesisCierra, "Se esperaba ')' después de los parámetros del evento")?;
+        }
+        
+        // El manejador lo ejecuta el bus de eventos, fuera de cualquier
+        // corrutina y de cualquier bucle
+        let en_corrutina = std::mem::replace(&mut self.en_corrutina, false);
+        let bucles = std::mem::take(&mut self.bucles);
+        self.ambitos.push(parametros.iter().map(|p| (p.clone(), None)).collect());
+        let cuerpo = self.bloque("escuchar");
+        self.ambitos.pop();
+        self.bucles = bucles;
+        self.en_corrutina = en_corrutina;
+        
+        Ok(Sentencia::Escuchar(evento, parametros, cuerpo?, self.tramo(&pos)))
+    }
+    
+    /// Parsea el nombre de un evento, que se escribe como texto
+    fn nombre_evento(&mut self, contexto: &str) -> Result<String, Diagnostico> {
+        match &self.token_actual().tipo {
+            TipoToken::Texto(nombre) => {
+                let nombre = nombre.clone();
+                self.avanzar();
+                Ok(nombre)
+            }
+            _ => Err(self.error_actual(
+                Codigo::NombreEsperado,
+                &format!("Se esperaba el nombre del evento entre comillas después de '{}'", contexto),
+            )),
+        }
     }
     
     /// Parsea una expresión
-    fn expresion(&mut self) -> Result<Expresion, String> {
+    fn expresion(&mut self) -> Result<Expresion, Diagnostico> {
         self.o_logico()
     }
     
     /// Parsea OR lógico
-    fn o_logico(&mut self) -> Result<Expresion, String> {
+    fn o_logico(&mut self) -> Result<Expresion, Diagnostico> {
         let mut expr = self.y_logico()?;
         
         while self.coincidir(&TipoToken::O) {
-            let pos = self.token_anterior().pos.clone();
             let derecha = self.y_logico()?;
+            let pos = expr.posicion().hasta(derecha.posicion());
             expr = Expresion::Binaria(Box::new(expr), OperadorBinario::O, Box::new(derecha), pos);
         }
         
@@ -366,12 +1288,12 @@ impl Parser {
     }
     
     /// Parsea AND lógico
-    fn y_logico(&mut self) -> Result<Expresion, String> {
+    fn y_logico(&mut self) -> Result<Expresion, Diagnostico> {
         let mut expr = self.igualdad()?;
         
         while self.coincidir(&TipoToken::Y) {
-            let pos = self.token_anterior().pos.clone();
             let derecha = self.igualdad()?;
+            let pos = expr.posicion().hasta(derecha.posicion());
             expr = Expresion::Binaria(Box::new(expr), OperadorBinario::Y, Box::new(derecha), pos);
         }
         
@@ -379,17 +1301,17 @@ impl Parser {
     }
     
     /// Parsea igualdad y diferencia
-    fn igualdad(&mut self) -> Result<Expresion, String> {
+    fn igualdad(&mut self) -> Result<Expresion, Diagnostico> {
         let mut expr = self.comparacion()?;
         
         while let Some(op) = self.coincidir_varios(&[TipoToken::Igual, TipoToken::Diferente]) {
-            let pos = self.token_anterior().pos.clone();
             let operador = match op {
                 TipoToken::Igual => OperadorBinario::Igual,
                 TipoToken::Diferente => OperadorBinario::Diferente,
                 _ => unreachable!(),
             };
             let derecha = self.comparacion()?;
+            let pos = expr.posicion().hasta(derecha.posicion());
             expr = Expresion::Binaria(Box::new(expr), operador, Box::new(derecha), pos);
         }
         
@@ -397,7 +1319,7 @@ impl Parser {
     }
     
     /// Parsea comparaciones
-    fn comparacion(&mut self) -> Result<Expresion, String> {
+    fn comparacion(&mut self) -> Result<Expresion, Diagnostico> {
         let mut expr = self.suma_resta()?;
         
         while let Some(op) = self.coincidir_varios(&[
@@ -406,7 +1328,6 @@ impl Parser {
             TipoToken::Mayor,
             TipoToken::MayorIgual,
         ]) {
-            let pos = self.token_anterior().pos.clone();
             let operador = match op {
                 TipoToken::Menor => OperadorBinario::Menor,
                 TipoToken::MenorIgual => OperadorBinario::MenorIgual,
@@ -415,6 +1336,7 @@ impl Parser {
                 _ => unreachable!(),
             };
             let derecha = self.suma_resta()?;
+            let pos = expr.posicion().hasta(derecha.posicion());
             expr = Expresion::Binaria(Box::new(expr), operador, Box::new(derecha), pos);
         }
         
@@ -422,17 +1344,17 @@ impl Parser {
     }
     
     /// Parsea suma y resta
-    fn suma_resta(&mut self) -> Result<Expresion, String> {
+    fn suma_resta(&mut self) -> Result<Expresion, Diagnostico> {
         let mut expr = self.multiplicacion_division()?;
         
         while let Some(op) = self.coincidir_varios(&[TipoToken::Suma, TipoToken::Resta]) {
-            let pos = self.token_anterior().pos.clone();
             let operador = match op {
                 TipoToken::Suma => OperadorBinario::Suma,
                 TipoToken::Resta => OperadorBinario::Resta,
                 _ => unreachable!(),
             };
             let derecha = self.multiplicacion_division()?;
+            let pos = expr.posicion().hasta(derecha.posicion());
             expr = Expresion::Binaria(Box::new(expr), operador, Box::new(derecha), pos);
         }
         
@@ -440,7 +1362,7 @@ impl Parser {
     }
     
     /// Parsea multiplicación, división y módulo
-    fn multiplicacion_division(&mut self) -> Result<Expresion, String> {
+    fn multiplicacion_division(&mut self) -> Result<Expresion, Diagnostico> {
         let mut expr = self.unario()?;
         
         while let Some(op) = self.coincidir_varios(&[
@@ -448,7 +1370,6 @@ impl Parser {
             TipoToken::Division,
             TipoToken::Modulo,
         ]) {
-            let pos = self.token_anterior().pos.clone();
             let operador = match op {
                 TipoToken::Multiplicacion => OperadorBinario::Multiplicacion,
                 TipoToken::Division => OperadorBinario::Division,
@@ -456,6 +1377,7 @@ impl Parser {
                 _ => unreachable!(),
             };
             let derecha = self.unario()?;
+            let pos = expr.posicion().hasta(derecha.posicion());
             expr = Expresion::Binaria(Box::new(expr), operador, Box::new(derecha), pos);
         }
         
@@ -463,7 +1385,7 @@ impl Parser {
     }
     
     /// Parsea operadores unarios
-    fn unario(&mut self) -> Result<Expresion, String> {
+    fn unario(&mut self) -> Result<Expresion, Diagnostico> {
         if let Some(op) = self.coincidir_varios(&[TipoToken::No, TipoToken::Resta]) {
             let pos = self.token_anterior().pos.clone();
             let operador = match op {
@@ -472,33 +1394,93 @@ impl Parser {
                 _ => unreachable!(),
             };
             let derecha = self.unario()?;
+            let pos = pos.hasta(derecha.posicion());
             Ok(Expresion::Unaria(operador, Box::new(derecha), pos))
         } else {
             self.llamada()
         }
     }
     
-    /// Parsea llamadas a función
-    fn llamada(&mut self) -> Result<Expresion, String> {
+    /// Parsea llamadas a función, campos (`punto.x`), índices (`lista[0]`),
+    /// rebanadas (`lista[1:3]`) y llamadas a valores (`acciones[0]()`). Un
+    /// '[' o '(' al principio de una línea empieza otra expresión en vez de
+    /// indexar o llamar a la anterior.
+    fn llamada(&mut self) -> Result<Expresion, Diagnostico> {
+        let mut expr = self.invocacion()?;
+        
+        loop {
+            if self.verificar(&TipoToken::ParentesisAbre) && !self.empieza_linea() {
+                self.avanzar();
+                let argumentos = self.argumentos()?;
+                let pos = self.tramo(expr.posicion());
+                expr = Expresion::LlamadaValor(Box::new(expr), argumentos, pos);
+                continue;
+            }
+            if self.coincidir(&TipoToken::Punto) {
+                let campo = self.nombre_de_campo("Se esperaba nombre de campo después de '.'")?;
+                let pos = self.tramo(expr.posicion());
+                expr = Expresion::Campo(Box::new(expr), campo, pos);
+                continue;
+            }
+            if !self.verificar(&TipoToken::CorcheteAbre) || self.empieza_linea() {
+                break;
+            }
+            self.avanzar();
+            let desde = if self.verificar(&TipoToken::DosPuntos) {
+                None
+            } else {
+                Some(Box::new(self.expresion()?))
+            };
+            let es_rebanada = self.coincidir(&TipoToken::DosPuntos);
+            
+            expr = match (desde, es_rebanada) {
+                (Some(indice), false) => {
+                    self.consumir(TipoToken::CorcheteCierra, "Se esperaba ']' después del índice")?;
+                    let pos = self.tramo(expr.posicion());
+                    Expresion::Indice(Box::new(expr), indice, pos)
+                }
+                (desde, _) => {
+                    let hasta = if self.verificar(&TipoToken::CorcheteCierra) {
+                        None
+                    } else {
+                        Some(Box::new(self.expresion()?))
+                    };
+                    self.consumir(TipoToken::CorcheteCierra, "Se esperaba ']' después de la rebanada")?;
+                    let pos = self.tramo(expr.posicion());
+                    Expresion::Rebanada(Box::new(expr), desde, hasta, pos)
+                }
+            };
+        }
+        
+        Ok(expr)
+    }
+    
+    /// Parsea llamadas a función y construcciones de estructuras, que
+    /// nombran cada campo: `Punto(x: 1, y: 2)`
+    fn invocacion(&mut self) -> Result<Expresion, Diagnostico> {
         let expr = self.primario()?;
         
         if let Expresion::Variable(nombre, pos) = expr {
             if self.verificar(&TipoToken::ParentesisAbre) {
                 self.avanzar();
                 
-                let mut argumentos = Vec::new();
-                if !self.verificar(&TipoToken::ParentesisCierra) {
+                let con_nombre = self.nombre_actual().is_some() && self.token_en(self.actual + 1).tipo == TipoToken::DosPuntos;
+                if con_nombre {
+                    let mut campos = Vec::new();
                     loop {
-                        argumentos.push(self.expresion()?);
-                        if !self.coincidir(&TipoToken::Coma) {
+                        let campo = self.nombre_de_campo("Se esperaba nombre de campo")?;
+                        self.consumir(TipoToken::DosPuntos, "Se esperaba ':' después del nombre de campo")?;
+                        campos.push((campo, self.expresion()?));
+                        if !self.coincidir(&TipoToken::Coma) || self.verificar(&TipoToken::ParentesisCierra) {
                             break;
                         }
                     }
+                    self.consumir(TipoToken::ParentesisCierra, "Se esperaba ')' después de los campos")?;
+                    return Ok(Expresion::Construccion(nombre, campos, self.tramo(&pos)));
                 }
                 
-                self.consumir(TipoToken::ParentesisCierra, "Se esperaba ')' después de argumentos")?;
-                
-                Ok(Expresion::Llamada(nombre, argumentos, pos))
+                let argumentos = self.argumentos()?;
+                Ok(Expresion::Llamada(nombre, argumentos, self.tramo(&pos)))
             } else {
                 Ok(Expresion::Variable(nombre, pos))
             }
@@ -507,8 +1489,24 @@ impl Parser {
         }
     }
     
+    /// Parsea los argumentos de una llamada, ya pasado el '(', hasta el ')'
+    fn argumentos(&mut self) -> Result<Vec<Expresion>, Diagnostico> {
+        let mut argumentos = Vec::new();
+        if !self.verificar(&TipoToken::ParentesisCierra) {
+            loop {
+                argumentos.push(self.expresion()?);
+                if !self.coincidir(&TipoToken::Coma) {
+                    break;
+                }
+            }
+        }
+        
+        self.consumir(TipoToken::ParentesisCierra, "Se esperaba ')' después de argumentos")?;
+        Ok(argumentos)
+    }
+    
     /// Parsea expresiones primarias
-    fn primario(&mut self) -> Result<Expresion, String> {
+    fn primario(&mut self) -> Result<Expresion, Diagnostico> {
         let token = self.token_actual().clone();
         
         match &token.tipo {
@@ -520,10 +1518,22 @@ impl Parser {
                 self.avanzar();
                 Ok(Expresion::Decimal(*d, token.pos))
             }
+            TipoToken::Medida(valor, unidad) => {
+                self.avanzar();
+                Ok(Expresion::Medida(valor * unidad.factor, unidad.dimension, token.pos))
+            }
             TipoToken::Texto(s) => {
                 self.avanzar();
                 Ok(Expresion::Texto(s.clone(), token.pos))
             }
+            TipoToken::TextoInterpolado(partes) => {
+                self.avanzar();
+                let fragmentos = partes
+                    .iter()
+                    .map(|parte| self.fragmento(parte))
+                    .collect::<Result<_, _>>()?;
+                Ok(Expresion::Interpolacion(fragmentos, token.pos))
+            }
             TipoToken::Verdadero => {
                 self.avanzar();
                 Ok(Expresion::Booleano(true, token.pos))
@@ -536,21 +1546,347 @@ impl Parser {
                 self.avanzar();
                 Ok(Expresion::Variable(id.clone(), token.pos))
             }
+            tipo if Self::es_contextual(tipo) => {
+                // En posición de operando son nombres (ver `es_contextual`)
+                self.avanzar();
+                Ok(Expresion::Variable(token.lexema.clone(), token.pos))
+            }
             TipoToken::ParentesisAbre => {
                 self.avanzar();
                 let expr = self.expresion()?;
                 self.consumir(TipoToken::ParentesisCierra, "Se esperaba ')' después de expresión")?;
                 Ok(expr)
             }
-            _ => Err(format!(
-                "Expresión inesperada: {} en {}",
-                token.tipo, token.pos
+            TipoToken::CorcheteAbre => {
+                self.avanzar();
+                let mut elementos = Vec::new();
+                // Admite una coma final: [1, 2, 3,]
+                while !self.verificar(&TipoToken::CorcheteCierra) {
+                    elementos.push(self.expresion()?);
+                    if !self.coincidir(&TipoToken::Coma) {
+                        break;
+                    }
+                }
+                self.consumir(TipoToken::CorcheteCierra, "Se esperaba ']' para cerrar la lista")?;
+                Ok(Expresion::Lista(elementos, self.tramo(&token.pos)))
+            }
+            TipoToken::LlaveAbre => {
+                // En posición de operando una llave abre un mapa, no un bloque
+                self.avanzar();
+                let mut entradas = Vec::new();
+                while !self.verificar(&TipoToken::LlaveCierra) {
+                    let clave = self.expresion()?;
+                    self.consumir(TipoToken::DosPuntos, "Se esperaba ':' entre la clave y su valor")?;
+                    entradas.push((clave, self.expresion()?));
+                    if !self.coincidir(&TipoToken::Coma) {
+                        break;
+                    }
+                }
+                self.consumir(TipoToken::LlaveCierra, "Se esperaba '}' para cerrar el mapa")?;
+                Ok(Expresion::Mapa(entradas, self.tramo(&token.pos)))
+            }
+            TipoToken::Despues | TipoToken::Cada => self.temporizador(),
+            TipoToken::Funcion => self.funcion_anonima(),
+            TipoToken::Segun => {
+                let (valor, casos) = self.segun(Self::expresion)?;
+                Ok(Expresion::Segun(Box::new(valor), casos, self.tramo(&token.pos)))
+            }
+            _ => Err(Diagnostico::new(
+                Codigo::TokenInesperado,
+                format!("Expresión inesperada: {}", token.tipo),
+                &token.pos,
             )),
         }
     }
     
+    /// Parsea un trozo de texto interpolado. La expresión de un `{...}` se
+    /// analiza con su propio parser, cuyos errores pasan a éste.
+    fn fragmento(&mut self, parte: &ParteDeTexto) -> Result<Fragmento, Diagnostico> {
+        let tokens = match parte {
+            ParteDeTexto::Literal(texto) => return Ok(Fragmento::Texto(texto.clone())),
+            ParteDeTexto::Codigo(tokens) => tokens.clone(),
+        };
+        
+        let mut parser = Parser::new(tokens);
+        let resultado = parser.expresion().and_then(|expr| {
+            if parser.fin() {
+                Ok(expr)
+            } else {
+                Err(parser.error_actual(
+                    Codigo::TokenInesperado,
+                    "Se esperaba '}' después de la expresión interpolada",
+                ))
+            }
+        });
+        self.diagnosticos.append(&mut parser.diagnosticos);
+        resultado.map(Fragmento::Expresion)
+    }
+    
+    /// Parsea `despues <segundos> { }`, `cada <segundos> { }` o `cada frame { }`
+    fn temporizador(&mut self) -> Result<Expresion, Diagnostico> {
+        let pos = self.token_actual().pos.clone();
+        
+        let (programacion, contexto) = if self.coincidir(&TipoToken::Despues) {
+            (Programacion::Despues(Box::new(self.expresion()?)), "despues")
+        } else {
+            self.consumir(TipoToken::Cada, "Se esperaba 'despues' o 'cada'")?;
+            if self.coincidir(&TipoToken::Frame) {
+                (Programacion::CadaFrame, "cada frame")
+            } else {
+                (Programacion::Cada(Box::new(self.expresion()?)), "cada")
+            }
+        };
+        
+        // El bloque se ejecuta desde el planificador, fuera de cualquier
+        // corrutina y de cualquier bucle
+        let en_corrutina = std::mem::replace(&mut self.en_corrutina, false);
+        let bucles = std::mem::take(&mut self.bucles);
+        let cuerpo = self.bloque(contexto);
+        self.bucles = bucles;
+        self.en_corrutina = en_corrutina;
+        
+        Ok(Expresion::Temporizador(programacion, cuerpo?, self.tramo(&pos)))
+    }
+    
+    /// Parsea un bloque `{ ... }` de sentencias
+    fn bloque(&mut self, contexto: &str) -> Result<Vec<Sentencia>, Diagnostico> {
+        self.consumir(
+            TipoToken::LlaveAbre,
+            &format!("Se esperaba '{{' después de '{}'", contexto),
+        )?;
+        
+        Ok(self.cuerpo_de_bloque(&format!("Se esperaba '}}' al final del bloque '{}'", contexto)))
+    }
+    
+    /// Parsea sentencias hasta la '}' que cierra el bloque. Los errores se
+    /// registran y el análisis continúa en la siguiente sentencia.
+    fn cuerpo_de_bloque(&mut self, mensaje_cierre: &str) -> Vec<Sentencia> {
+        let mut sentencias = Vec::new();
+        self.ambitos.push(Vec::new());
+        while !self.verificar(&TipoToken::LlaveCierra) && !self.fin() && !self.inicio_de_declaracion() {
+            let inicio = self.actual;
+            match self.sentencia() {
+                Ok(sentencia) => sentencias.push(sentencia),
+                Err(e) => {
+                    self.reportar(e);
+                    self.sincronizar(inicio, Self::inicio_de_sentencia);
+                }
+            }
+        }
+        
+        self.cerrar_bloque(mensaje_cierre);
+        self.ambitos.pop();
+        sentencias
+    }
+    
+    /// Consume la '}' de cierre. Si falta se informa sin abortar: el bloque
+    /// termina donde empieza la siguiente declaración o el archivo.
+    fn cerrar_bloque(&mut self, mensaje: &str) {
+        if !self.coincidir(&TipoToken::LlaveCierra) {
+            let error = self.error_actual(Codigo::TokenInesperado, mensaje);
+            self.reportar(error);
+        }
+    }
+    
+    // Ámbitos de nombres, para detectar asignaciones a constantes
+    
+    /// Declara `nombre` en el ámbito actual; `constante` es la posición de
+    /// su declaración si lo es. Redeclarar una constante en su mismo ámbito
+    /// se informa como duplicado.
+    fn declarar(&mut self, nombre: &str, constante: Option<Posicion>, pos: &Posicion) {
+        let Some(ambito) = self.ambitos.last_mut() else {
+            return;
+        };
+        let anterior = ambito.iter().rev().find(|(otro, _)| otro == nombre);
+        if let Some((_, Some(declaracion))) = anterior {
+            let error = Diagnostico::new(
+                Codigo::Duplicado,
+                format!("'{}' ya está declarada como constante en este ámbito", nombre),
+                pos,
+            )
+            .con_etiqueta(declaracion, "declarada antes aquí");
+            self.diagnosticos.push(error);
+            return;
+        }
+        ambito.push((nombre.to_string(), constante));
+    }
+    
+    /// Posición de la constante a la que se refiere `nombre`, si el nombre
+    /// visible más cercano es una constante
+    fn constante_visible(&self, nombre: &str) -> Option<Posicion> {
+        self.ambitos
+            .iter()
+            .rev()
+            .flat_map(|ambito| ambito.iter().rev())
+            .find(|(otro, _)| otro == nombre)
+            .and_then(|(_, constante)| constante.clone())
+    }
+    
+    /// Informa si `nombre` es una constante visible, que no se puede
+    /// reasignar ni cambiar
+    fn comprobar_no_constante(&mut self, nombre: &str, pos: &Posicion) {
+        if let Some(declaracion) = self.constante_visible(nombre) {
+            let error = Diagnostico::new(
+                Codigo::AsignacionAConstante,
+                format!("No se puede asignar a '{}', es una constante", nombre),
+                pos,
+            )
+            .con_etiqueta(&declaracion, "declarada como constante aquí")
+            .con_sugerencia(format!("declárala con 'var {} = ...' si debe cambiar", nombre));
+            self.reportar(error);
+        }
+    }
+    
+    /// Variable que guarda la estructura de un campo (`a` en `a.b.c`). Tras
+    /// un índice el campo es de un elemento de una lista o un mapa, que se
+    /// cambia en su sitio y no tiene variable (`Some(None)`); cualquier
+    /// otra expresión es un valor temporal (`None`).
+    fn raiz_de_campo(registro: &Expresion) -> Option<Option<String>> {
+        match registro {
+            Expresion::Variable(nombre, _) => Some(Some(nombre.clone())),
+            Expresion::Campo(registro, _, _) => Self::raiz_de_campo(registro),
+            Expresion::Indice(..) => Some(None),
+            _ => None,
+        }
+    }
+    
+    // Recuperación de errores
+    
+    fn reportar(&mut self, diagnostico: Diagnostico) {
+        self.diagnosticos.push(diagnostico);
+    }
+    
+    /// Salta tokens tras un error hasta un punto seguro: uno que cumpla
+    /// `es_punto_seguro`, la '}' del bloque actual o el inicio de una
+    /// declaración. Los bloques `{ }` que se saltan se descartan enteros.
+    /// Siempre avanza desde `inicio` para no repetir el mismo error.
+    fn sincronizar(&mut self, inicio: usize, es_punto_seguro: fn(&Parser) -> bool) {
+        if self.actual == inicio {
+            self.avanzar();
+        }
+        
+        let mut profundidad = 0;
+        while !self.fin() && !self.inicio_de_declaracion() {
+            match self.token_actual().tipo {
+                TipoToken::LlaveAbre => profundidad += 1,
+                TipoToken::LlaveCierra if profundidad == 0 => return,
+                TipoToken::LlaveCierra => profundidad -= 1,
+                _ if profundidad == 0 && es_punto_seguro(self) => return,
+                _ => {}
+            }
+            self.avanzar();
+        }
+    }
+    
+    /// Salta hasta la siguiente declaración de nivel superior
+    fn sincronizar_declaracion(&mut self) {
+        self.avanzar();
+        while !self.fin() && !self.inicio_de_declaracion() {
+            self.avanzar();
+        }
+    }
+    
+    fn inicio_de_declaracion(&self) -> bool {
+        matches!(
+            self.token_actual().tipo,
+            TipoToken::Escena
+                | TipoToken::Funcion
+                | TipoToken::Corrutina
+                | TipoToken::Estructura
+                | TipoToken::Enumeracion
+                | TipoToken::Arroba
+        )
+    }
+    
+    /// Palabra que empieza una sentencia o un miembro de escena, o un
+    /// nombre al principio de una línea (asignación o llamada)
+    fn inicio_de_sentencia(&self) -> bool {
+        match self.token_actual().tipo {
+            TipoToken::Mostrar
+            | TipoToken::Var
+            | TipoToken::Constante
+            | TipoToken::Si
+            | TipoToken::Mientras
+            | TipoToken::Para
+            | TipoToken::Retornar
+            | TipoToken::Romper
+            | TipoToken::Continuar
+            | TipoToken::Esperar
+            | TipoToken::Emitir
+            | TipoToken::Escuchar
+            | TipoToken::Segun
+            | TipoToken::Intentar
+            | TipoToken::Lanzar
+            | TipoToken::Despues
+            | TipoToken::Cada
+            | TipoToken::AlIniciar
+            | TipoToken::AlSalir
+            | TipoToken::Maquina => true,
+            _ => self.nombre_actual().is_some() && self.empieza_linea(),
+        }
+    }
+    
+    fn inicio_de_miembro_de_estado(&self) -> bool {
+        matches!(
+            self.token_actual().tipo,
+            TipoToken::AlEntrar | TipoToken::AlSalir | TipoToken::Cada | TipoToken::Transicion
+        )
+    }
+    
+    fn inicio_de_estado(&self) -> bool {
+        matches!(&self.token_actual().tipo, TipoToken::Identificador(id) if es_palabra_contextual(id, "estado"))
+    }
+    
+    /// Consume el token actual si es la palabra contextual `espanol`
+    fn coincidir_contextual(&mut self, espanol: &str) -> bool {
+        let es_palabra = matches!(
+            &self.token_actual().tipo,
+            TipoToken::Identificador(id) if es_palabra_contextual(id, espanol)
+        );
+        if es_palabra {
+            self.avanzar();
+        }
+        es_palabra
+    }
+    
+    /// Si el token actual es el primero de su línea
+    fn empieza_linea(&self) -> bool {
+        self.actual == 0 || self.token_anterior().pos.linea < self.token_actual().pos.linea
+    }
+    
     // Utilidades
     
+    /// Nombre del token actual si puede usarse como identificador
+    fn nombre_actual(&self) -> Option<String> {
+        match &self.token_actual().tipo {
+            TipoToken::Identificador(id) => Some(id.clone()),
+            tipo if Self::es_contextual(tipo) => Some(self.token_actual().lexema.clone()),
+            _ => None,
+        }
+    }
+    
+    /// Palabras reservadas que también sirven como nombres: `y` y `o` son
+    /// nombres naturales para coordenadas, `frame` solo es reservada tras
+    /// `cada` y los nombres de tipo solo tras ':'.
+    pub(crate) fn es_contextual(tipo: &TipoToken) -> bool {
+        matches!(
+            tipo,
+            TipoToken::Y
+                | TipoToken::O
+                | TipoToken::Frame
+                | TipoToken::TipoNumero
+                | TipoToken::TipoDecimal
+                | TipoToken::TipoTexto
+                | TipoToken::TipoBooleano
+                | TipoToken::TipoVector3
+                | TipoToken::TipoPose
+                | TipoToken::TipoMano
+                | TipoToken::TipoControlador
+                | TipoToken::TipoLista
+                | TipoToken::TipoMapa
+        )
+    }
+    
     fn token_actual(&self) -> &Token {
         &self.tokens[self.actual]
     }
@@ -559,6 +1895,11 @@ impl Parser {
         &self.tokens[self.actual - 1]
     }
     
+    /// Tramo desde `inicio` hasta el final del último token consumido
+    fn tramo(&self, inicio: &Posicion) -> Posicion {
+        inicio.hasta(&self.token_anterior().pos)
+    }
+    
     fn token_en(&self, pos: usize) -> &Token {
         &self.tokens[pos]
     }
@@ -601,17 +1942,41 @@ impl Parser {
         None
     }
     
-    fn consumir(&mut self, tipo: TipoToken, mensaje: &str) -> Result<(), String> {
+    fn consumir(&mut self, tipo: TipoToken, mensaje: &str) -> Result<(), Diagnostico> {
         if self.verificar(&tipo) {
             self.avanzar();
             Ok(())
         } else {
-            Err(format!(
-                "{}, se encontró {} en {}",
-                mensaje,
-                self.token_actual().tipo,
-                self.token_actual().pos
-            ))
+            Err(self.error_actual(Codigo::TokenInesperado, mensaje))
+        }
+    }
+    
+    /// Error en el token actual: "<mensaje>, se encontró <token>"
+    fn error_actual(&self, codigo: Codigo, mensaje: &str) -> Diagnostico {
+        let token = self.token_actual();
+        Diagnostico::new(codigo, format!("{}, se encontró {}", mensaje, token.tipo), &token.pos)
+    }
+    
+    /// Consume un identificador y devuelve su nombre
+    /// Como `nombre`, pero admite las palabras contextuales: `punto.y`
+    fn nombre_de_campo(&mut self, mensaje: &str) -> Result<String, Diagnostico> {
+        match self.nombre_actual() {
+            Some(nombre) => {
+                self.avanzar();
+                Ok(nombre)
+            }
+            None => Err(self.error_actual(Codigo::NombreEsperado, mensaje)),
+        }
+    }
+    
+    fn nombre(&mut self, mensaje: &str) -> Result<String, Diagnostico> {
+        match &self.token_actual().tipo {
+            TipoToken::Identificador(id) => {
+                let nombre = id.clone();
+                self.avanzar();
+                Ok(nombre)
+            }
+            _ => Err(self.error_actual(Codigo::NombreEsperado, mensaje)),
         }
     }
 }
@@ -0,0 +1,235 @@
+// planificador.rs - Reloj de frames y temporizadores para V-Code
+// El runtime avanza en pasos fijos, así que todo lo programado aquí es
+// determinista aunque se ejecute sin visor.
+
+use crate::ast::{Expresion, Sentencia};
+use crate::ejecutor::{Recorrido, Valor};
+use std::collections::{BTreeMap, HashMap};
+
+/// Frames por segundo del reloj simulado
+pub const FPS: u64 = 90;
+
+/// Límite de frames por escena al ejecutar sin visor (10 segundos)
+pub const FRAMES_MAXIMOS: u64 = FPS * 10;
+
+/// Tolerancia al comparar instantes acumulados en coma flotante
+const EPSILON: f64 = 1e-9;
+
+/// Reloj del bucle de frames
+#[derive(Debug, Clone)]
+pub struct Reloj {
+    pub frame: u64,
+    pub delta: f64,
+}
+
+impl Reloj {
+    pub fn new() -> Self {
+        Reloj {
+            frame: 0,
+            delta: 1.0 / FPS as f64,
+        }
+    }
+    
+    /// Segundos transcurridos desde el inicio de la escena
+    pub fn tiempo(&self) -> f64 {
+        self.frame as f64 * self.delta
+    }
+}
+
+/// Frecuencia de un temporizador
+#[derive(Debug, Clone, PartialEq)]
+pub enum Periodo {
+    Unico,           // `despues`
+    Segundos(f64),   // `cada <intervalo>`
+    Frame,           // `cada frame`
+}
+
+/// Bloque programado para ejecutarse en un frame futuro
+#[derive(Debug, Clone)]
+pub struct Temporizador {
+    pub id: u64,
+    pub vencimiento: f64,
+    pub periodo: Periodo,
+    pub cuerpo: Vec<Sentencia>,
+}
+
+/// Punto de ejecución guardado de una corrutina. La pila de marcos
+/// sustituye a la pila de Rust para poder suspender en `esperar`.
+#[derive(Debug, Clone)]
+pub enum Marco {
+    Bloque(Vec<Sentencia>, usize),                       // Sentencias y siguiente índice
+    Mientras(Expresion, Vec<Sentencia>, Option<String>), // Reevalúa la condición al volver
+    // Variable, valores pendientes, cuerpo, etiqueta y el valor que tenía
+    // la variable antes del bucle, que se restaura al salir
+    Para(String, Recorrido, Vec<Sentencia>, Option<String>, Option<Valor>),
+    // Bajo el cuerpo de un caso de `segun`: las variables de su patrón y
+    // el valor que tenían antes, que se restaura al salir del caso
+    Caso(Vec<(String, Option<Valor>)>),
+}
+
+/// Cuándo despierta una corrutina suspendida
+#[derive(Debug, Clone)]
+pub enum Despertar {
+    Tiempo(f64),          // esperar <segundos>
+    Condicion(Expresion), // esperar hasta <condición>
+}
+
+/// Corrutina en curso: su ámbito local y su pila de marcos
+#[derive(Debug)]
+pub struct Corrutina {
+    pub id: u64,
+    pub nombre: String,
+    pub locales: HashMap<String, Valor>,
+    pub pila: Vec<Marco>,
+    pub despertar: Option<Despertar>,
+    pub frame_suspension: u64,
+}
+
+/// Temporizadores y corrutinas activos de la escena en curso
+#[derive(Debug)]
+pub struct Planificador {
+    temporizadores: Vec<Temporizador>,
+    corrutinas: BTreeMap<u64, Corrutina>, // Ordenadas por creación
+    en_curso: Vec<(u64, bool)>,           // Corrutinas sacadas para ejecutarse y si se cancelaron
+    siguiente_id: u64,
+}
+
+impl Planificador {
+    pub fn new() -> Self {
+        Planificador {
+            temporizadores: Vec::new(),
+            corrutinas: BTreeMap::new(),
+            en_curso: Vec::new(),
+            siguiente_id: 1,
+        }
+    }
+    
+    fn nuevo_id(&mut self) -> u64 {
+        let id = self.siguiente_id;
+        self.siguiente_id += 1;
+        id
+    }
+    
+    /// Programa un bloque y devuelve el identificador de su temporizador
+    pub fn programar(&mut self, vencimiento: f64, periodo: Periodo, cuerpo: Vec<Sentencia>) -> u64 {
+        let id = self.nuevo_id();
+        self.temporizadores.push(Temporizador {
+            id,
+            vencimiento,
+            periodo,
+            cuerpo,
+        });
+        id
+    }
+    
+    /// Cancela un temporizador; devuelve si seguía activo
+    pub fn cancelar(&mut self, id: u64) -> bool {
+        let antes = self.temporizadores.len();
+        self.temporizadores.retain(|t| t.id != id);
+        self.temporizadores.len() != antes
+    }
+    
+    /// Crea una corrutina lista para ejecutar `cuerpo` con sus parámetros
+    pub fn nueva_corrutina(
+        &mut self,
+        nombre: &str,
+        locales: HashMap<String, Valor>,
+        cuerpo: Vec<Sentencia>,
+    ) -> Corrutina {
+        Corrutina {
+            id: self.nuevo_id(),
+            nombre: nombre.to_string(),
+            locales,
+            pila: vec![Marco::Bloque(cuerpo, 0)],
+            despertar: None,
+            frame_suspension: 0,
+        }
+    }
+    
+    /// Identificadores de las corrutinas suspendidas, en orden de creación
+    pub fn corrutinas(&self) -> Vec<u64> {
+        self.corrutinas.keys().copied().collect()
+    }
+    
+    /// Saca una corrutina para ejecutarla; debe devolverse con `devolver`
+    pub fn tomar(&mut self, id: u64) -> Option<Corrutina> {
+        let corrutina = self.corrutinas.remove(&id)?;
+        self.marcar_en_curso(id);
+        Some(corrutina)
+    }
+    
+    /// Marca una corrutina recién creada como en ejecución. Una corrutina
+    /// puede iniciar otra, así que las que están en curso forman una pila.
+    pub fn marcar_en_curso(&mut self, id: u64) {
+        self.en_curso.push((id, false));
+    }
+    
+    /// Devuelve la corrutina en curso, salvo que haya terminado o se haya
+    /// cancelado mientras se ejecutaba
+    pub fn devolver(&mut self, corrutina: Corrutina, terminada: bool) {
+        let cancelada = self.en_curso.pop().is_some_and(|(_, cancelada)| cancelada);
+        if !terminada && !cancelada {
+            self.corrutinas.insert(corrutina.id, corrutina);
+        }
+    }
+    
+    /// Cancela una corrutina; devuelve si seguía activa
+    pub fn cancelar_corrutina(&mut self, id: u64) -> bool {
+        if let Some((_, cancelada)) = self.en_curso.iter_mut().find(|(en_curso, _)| *en_curso == id) {
+            let activa = !*cancelada;
+            *cancelada = true;
+            return activa;
+        }
+        self.corrutinas.remove(&id).is_some()
+    }
+    
+    /// Identificadores de los temporizadores vencidos en `tiempo`, en orden
+    /// de vencimiento y, a igual vencimiento, de creación
+    pub fn vencidos(&self, tiempo: f64) -> Vec<u64> {
+        let mut vencidos: Vec<&Temporizador> = self
+            .temporizadores
+            .iter()
+            .filter(|t| vencido(t.vencimiento, tiempo))
+            .collect();
+        vencidos.sort_by(|a, b| a.vencimiento.total_cmp(&b.vencimiento).then(a.id.cmp(&b.id)));
+        vencidos.iter().map(|t| t.id).collect()
+    }
+    
+    /// Dispara un temporizador vencido: lo reprograma si es periódico o lo
+    /// elimina si es único, y devuelve su cuerpo. Un temporizador periódico
+    /// se dispara como mucho una vez por frame; los periodos perdidos se
+    /// descartan en lugar de acumularse.
+    pub fn disparar(&mut self, id: u64, reloj: &Reloj) -> Option<Vec<Sentencia>> {
+        let indice = self.temporizadores.iter().position(|t| t.id == id)?;
+        let tiempo = reloj.tiempo();
+        
+        let intervalo = match self.temporizadores[indice].periodo {
+            Periodo::Unico => {
+                return Some(self.temporizadores.remove(indice).cuerpo);
+            }
+            Periodo::Segundos(segundos) => segundos,
+            Periodo::Frame => reloj.delta,
+        };
+        
+        let temporizador = &mut self.temporizadores[indice];
+        while vencido(temporizador.vencimiento, tiempo) {
+            temporizador.vencimiento += intervalo;
+        }
+        Some(temporizador.cuerpo.clone())
+    }
+    
+    pub fn hay_pendientes(&self) -> bool {
+        !self.temporizadores.is_empty() || !self.corrutinas.is_empty()
+    }
+    
+    /// Descarta todo lo programado (al salir de una escena)
+    pub fn limpiar(&mut self) {
+        self.temporizadores.clear();
+        self.corrutinas.clear();
+    }
+}
+
+/// Si un instante programado ya llegó en `tiempo`
+pub fn vencido(instante: f64, tiempo: f64) -> bool {
+    instante <= tiempo + EPSILON
+}
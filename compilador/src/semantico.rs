@@ -0,0 +1,946 @@
+// semantico.rs - Análisis semántico de V-Code
+// Recorre el AST antes de ejecutarlo y comprueba:
+// - que las medidas combinan unidades compatibles (no metros más segundos)
+// - que las claves de los mapas son de un tipo que admite clave
+// - que los tipos con nombre son estructuras o enumeraciones declaradas
+// - que cada construcción da valor a todos los campos de su estructura
+// - que cada `segun` cubre todos los valores posibles
+// - que las funciones de colecciones reciben los argumentos que esperan
+// - que una función pasada, retornada o guardada en un campo encaja con el
+//   tipo `funcion(P, ...): R` declarado
+// Solo informa de lo que sabe seguro; lo que depende de la ejecución se deja
+// para el ejecutor.
+
+use crate::ast::*;
+use crate::colecciones;
+use crate::diagnostico::{Codigo, Diagnostico};
+use crate::unidades::Dimension;
+use std::collections::HashMap;
+
+/// Lo que se sabe antes de ejecutar del valor de una expresión
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Clase {
+    Medida(Dimension),
+    Escalar, // Número o decimal sin unidad
+    Texto,
+    Otra,        // Booleanos, temporizadores
+    Desconocida, // Depende de la ejecución
+}
+
+impl Clase {
+    /// Dimensión de una medida o de un número sin unidad
+    fn dimension(&self) -> Option<Dimension> {
+        match self {
+            Clase::Medida(dimension) => Some(*dimension),
+            Clase::Escalar => Some(Dimension::default()),
+            _ => None,
+        }
+    }
+    
+    fn de_dimension(dimension: Dimension) -> Clase {
+        if dimension.es_adimensional() {
+            Clase::Escalar
+        } else {
+            Clase::Medida(dimension)
+        }
+    }
+}
+
+/// Analiza el programa y devuelve todos los errores encontrados
+pub fn analizar(programa: &Programa) -> Result<(), Vec<Diagnostico>> {
+    let mut analizador = Analizador {
+        ambitos: Vec::new(),
+        funciones: HashMap::new(),
+        retornos: Vec::new(),
+        estructuras: HashMap::new(),
+        enumeraciones: HashMap::new(),
+        variantes: HashMap::new(),
+        diagnosticos: Vec::new(),
+    };
+    // Las funciones, estructuras y enumeraciones se pueden usar antes de su
+    // declaración
+    for declaracion in &programa.declaraciones {
+        match declaracion {
+            Declaracion::Funcion(funcion) => {
+                let firma = (funcion.parametros.clone(), funcion.tipo_retorno.clone());
+                analizador.funciones.insert(funcion.nombre.clone(), firma);
+            }
+            Declaracion::Estructura(estructura) => {
+                let campos = estructura.campos.iter().map(|c| (c.nombre.clone(), c.tipo_dato.clone())).collect();
+                analizador.estructuras.entry(estructura.nombre.clone()).or_insert(campos);
+            }
+            Declaracion::Enumeracion(enumeracion) => analizador.registrar_enumeracion(enumeracion),
+            _ => {}
+        }
+    }
+    for declaracion in &programa.declaraciones {
+        analizador.declaracion(declaracion);
+    }
+    
+    if analizador.diagnosticos.is_empty() {
+        Ok(())
+    } else {
+        Err(analizador.diagnosticos)
+    }
+}
+
+struct Analizador {
+    ambitos: Vec<HashMap<String, Clase>>,
+    funciones: HashMap<String, (Vec<Parametro>, Option<Tipo>)>, // Parámetros y retorno
+    retornos: Vec<Option<Tipo>>, // Retorno declarado de las funciones que se analizan
+    estructuras: HashMap<String, Vec<(String, Tipo)>>, // Campos y sus tipos
+    enumeraciones: HashMap<String, Vec<String>>, // Nombres de las variantes
+    // Enumeración de cada variante, cuántos valores lleva y dónde se declaró
+    variantes: HashMap<String, (String, usize, Posicion)>,
+    diagnosticos: Vec<Diagnostico>,
+}
+
+impl Analizador {
+    fn declaracion(&mut self, declaracion: &Declaracion) {
+        match declaracion {
+            Declaracion::Funcion(funcion) => {
+                self.funcion(&funcion.parametros, funcion.tipo_retorno.as_ref(), &funcion.cuerpo, &funcion.pos);
+            }
+            Declaracion::Escena(escena) => {
+                // Las variables de la escena se ven en sus hooks y máquinas
+                self.ambitos.push(HashMap::new());
+                self.sentencias(&escena.cuerpo);
+                self.bloque(&escena.al_iniciar);
+                self.bloque(&escena.al_salir);
+                for estado in escena.maquinas.iter().flat_map(|m| &m.estados) {
+                    self.bloque(&estado.al_entrar);
+                    self.bloque(&estado.cada_frame);
+                    self.bloque(&estado.al_salir);
+                    for transicion in &estado.transiciones {
+                        self.expresion(&transicion.condicion);
+                    }
+                }
+                self.ambitos.pop();
+            }
+            Declaracion::Estructura(estructura) => {
+                for campo in &estructura.campos {
+                    self.tipo(&campo.tipo_dato, &campo.pos);
+                }
+            }
+            Declaracion::Enumeracion(enumeracion) => {
+                for variante in &enumeracion.variantes {
+                    for tipo in &variante.campos {
+                        self.tipo(tipo, &variante.pos);
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Analiza una función, con nombre o anónima, en un ámbito con sus
+    /// parámetros
+    fn funcion(&mut self, parametros: &[Parametro], retorno: Option<&Tipo>, cuerpo: &[Sentencia], pos: &Posicion) {
+        for parametro in parametros {
+            self.tipo(&parametro.tipo_dato, &parametro.pos);
+        }
+        if let Some(tipo) = retorno {
+            self.tipo(tipo, pos);
+        }
+        // Los argumentos pueden ser de cualquier clase
+        let parametros = parametros
+            .iter()
+            .map(|p| (p.nombre.clone(), Clase::Desconocida))
+            .collect();
+        self.ambitos.push(parametros);
+        self.retornos.push(retorno.cloned());
+        self.sentencias(cuerpo);
+        self.retornos.pop();
+        self.ambitos.pop();
+    }
+    
+    /// Registra las variantes de una enumeración. Se nombran sin su
+    /// enumeración, así que dos enumeraciones no pueden compartir variante.
+    fn registrar_enumeracion(&mut self, enumeracion: &Enumeracion) {
+        if self.enumeraciones.contains_key(&enumeracion.nombre) {
+            return; // El ejecutor informa de la enumeración repetida
+        }
+        for variante in &enumeracion.variantes {
+            if let Some((otra, _, pos)) = self.variantes.get(&variante.nombre) {
+                self.diagnosticos.push(
+                    Diagnostico::new(
+                        Codigo::Duplicado,
+                        format!("La variante '{}' ya pertenece a la enumeración '{}'", variante.nombre, otra),
+                        &variante.pos,
+                    )
+                    .con_etiqueta(pos, "declarada antes aquí"),
+                );
+                continue;
+            }
+            self.variantes.insert(
+                variante.nombre.clone(),
+                (enumeracion.nombre.clone(), variante.campos.len(), variante.pos.clone()),
+            );
+        }
+        let nombres = enumeracion.variantes.iter().map(|v| v.nombre.clone()).collect();
+        self.enumeraciones.insert(enumeracion.nombre.clone(), nombres);
+    }
+    
+    /// Analiza un bloque en su propio ámbito
+    fn bloque(&mut self, sentencias: &[Sentencia]) {
+        self.ambitos.push(HashMap::new());
+        self.sentencias(sentencias);
+        self.ambitos.pop();
+    }
+    
+    fn sentencias(&mut self, sentencias: &[Sentencia]) {
+        for sentencia in sentencias {
+            self.sentencia(sentencia);
+        }
+    }
+    
+    fn sentencia(&mut self, sentencia: &Sentencia) {
+        match sentencia {
+            Sentencia::Var(nombre, expr, _) | Sentencia::Constante(nombre, expr, _, _) => {
+                let clase = self.expresion(expr);
+                self.declarar(nombre, clase);
+            }
+            Sentencia::Asignacion(nombre, expr, pos) => {
+                let nueva = self.expresion(expr);
+                let anterior = self.buscar(nombre);
+                if let (Some(antes), Some(despues)) = (anterior.dimension(), nueva.dimension()) {
+                    if antes != despues {
+                        self.diagnosticos.push(
+                            Diagnostico::new(
+                                Codigo::UnidadesIncompatibles,
+                                format!(
+                                    "Unidades incompatibles al asignar a '{}': guarda {} y se le asigna {}",
+                                    nombre,
+                                    antes.describir(),
+                                    despues.describir()
+                                ),
+                                pos,
+                            )
+                            .con_etiqueta(expr.posicion(), despues.describir()),
+                        );
+                    }
+                }
+            }
+            Sentencia::AsignacionIndice(lista, indice, valor, _) => {
+                self.expresion(lista);
+                self.expresion(indice);
+                self.expresion(valor);
+            }
+            Sentencia::AsignacionCampo(registro, _, valor, _) => {
+                self.expresion(registro);
+                self.expresion(valor);
+            }
+            Sentencia::Mostrar(expr, _) | Sentencia::Expresion(expr, _) => {
+                self.expresion(expr);
+            }
+            Sentencia::Si(ramas, sino, _) => {
+                for rama in ramas {
+                    self.expresion(&rama.condicion);
+                    self.bloque(&rama.cuerpo);
+                }
+                if let Some(sino) = sino {
+                    self.bloque(sino);
+                }
+            }
+            Sentencia::Mientras(condicion, cuerpo, _, _) => {
+                self.expresion(condicion);
+                self.bloque(cuerpo);
+            }
+            Sentencia::Para(variable, iteracion, cuerpo, _, _) => {
+                let clase = self.iteracion(iteracion);
+                self.ambitos.push(HashMap::from([(variable.clone(), clase)]));
+                self.bloque(cuerpo);
+                self.ambitos.pop();
+            }
+            Sentencia::Romper(..) | Sentencia::Continuar(..) => {}
+            Sentencia::Retornar(valor, _) => {
+                if let Some(expr) = valor {
+                    self.expresion(expr);
+                    if let Some(Some(retorno)) = self.retornos.last().cloned() {
+                        self.funcion_declarada(&retorno, expr, "El retorno de la función");
+                    }
+                }
+            }
+            Sentencia::Esperar(Espera::Segundos(expr), _) => self.intervalo(expr, "esperar"),
+            Sentencia::Esperar(Espera::Hasta(expr), _) => {
+                self.expresion(expr);
+            }
+            Sentencia::Emitir(_, argumentos, _) => {
+                for arg in argumentos {
+                    self.expresion(arg);
+                }
+            }
+            Sentencia::Segun(valor, casos, pos) => {
+                self.expresion(valor);
+                self.casos(casos, valor, pos);
+                for caso in casos {
+                    self.ambitos.push(ligaduras(&caso.patron));
+                    self.bloque(&caso.cuerpo);
+                    self.ambitos.pop();
+                }
+            }
+            Sentencia::Escuchar(_, parametros, cuerpo, _) => {
+                let parametros = parametros.iter().map(|p| (p.clone(), Clase::Desconocida)).collect();
+                self.ambitos.push(parametros);
+                self.bloque(cuerpo);
+                self.ambitos.pop();
+            }
+            Sentencia::EscucharFuncion(_, manejador, _) => {
+                self.expresion(manejador);
+            }
+            Sentencia::Intentar(cuerpo, captura, finalmente, _) => {
+                self.bloque(cuerpo);
+                if let Some(captura) = captura {
+                    let variables = captura.variable.iter().map(|v| (v.clone(), Clase::Desconocida)).collect();
+                    self.ambitos.push(variables);
+                    self.bloque(&captura.cuerpo);
+                    self.ambitos.pop();
+                }
+                if let Some(finalmente) = finalmente {
+                    self.bloque(finalmente);
+                }
+            }
+            Sentencia::Lanzar(valor, _) => {
+                self.expresion(valor);
+            }
+        }
+    }
+    
+    /// Clase del valor de una expresión; informa de las unidades que no
+    /// encajan en sus operaciones
+    fn expresion(&mut self, expr: &Expresion) -> Clase {
+        match expr {
+            Expresion::Numero(..) | Expresion::Decimal(..) => Clase::Escalar,
+            Expresion::Medida(_, dimension, _) => Clase::de_dimension(*dimension),
+            Expresion::Texto(..) => Clase::Texto,
+            Expresion::Booleano(..) => Clase::Otra,
+            Expresion::Variable(nombre, _) => self.buscar(nombre),
+            Expresion::Interpolacion(fragmentos, _) => {
+                for fragmento in fragmentos {
+                    if let Fragmento::Expresion(expr) = fragmento {
+                        self.expresion(expr);
+                    }
+                }
+                Clase::Texto
+            }
+            Expresion::Lista(elementos, _) => {
+                for elemento in elementos {
+                    self.expresion(elemento);
+                }
+                Clase::Otra
+            }
+            Expresion::Mapa(entradas, _) => {
+                for (clave, valor) in entradas {
+                    self.clave(clave);
+                    self.expresion(valor);
+                }
+                Clase::Otra
+            }
+            Expresion::Indice(lista, indice, _) => {
+                self.expresion(lista);
+                self.expresion(indice);
+                Clase::Desconocida
+            }
+            Expresion::Construccion(nombre, campos, pos) => {
+                for (_, valor) in campos {
+                    self.expresion(valor);
+                }
+                self.construccion(nombre, campos, pos);
+                Clase::Otra
+            }
+            Expresion::Campo(registro, _, _) => {
+                self.expresion(registro);
+                Clase::Desconocida
+            }
+            Expresion::Rebanada(base, desde, hasta, _) => {
+                for extremo in desde.iter().chain(hasta) {
+                    self.expresion(extremo);
+                }
+                // Una rebanada es del mismo tipo que lo rebanado
+                self.expresion(base)
+            }
+            Expresion::Unaria(OperadorUnario::Negacion, operando, _) => self.expresion(operando),
+            Expresion::Unaria(OperadorUnario::No, operando, _) => {
+                self.expresion(operando);
+                Clase::Otra
+            }
+            Expresion::Binaria(izq, op, der, pos) => {
+                let clase_izq = self.expresion(izq);
+                let clase_der = self.expresion(der);
+                self.binaria(izq, op, der, pos, clase_izq, clase_der)
+            }
+            Expresion::Llamada(nombre, argumentos, pos) if self.estructuras.contains_key(nombre) => {
+                for arg in argumentos {
+                    self.expresion(arg);
+                }
+                if argumentos.is_empty() {
+                    self.construccion(nombre, &[], pos);
+                } else {
+                    self.diagnosticos.push(
+                        Diagnostico::new(
+                            Codigo::CamposIncorrectos,
+                            format!("Los campos de '{}' se nombran al construirla", nombre),
+                            pos,
+                        )
+                        .con_sugerencia(format!("escribe {}(campo: valor, ...)", nombre)),
+                    );
+                }
+                Clase::Otra
+            }
+            Expresion::Llamada(nombre, argumentos, pos) if self.variantes.contains_key(nombre) => {
+                for arg in argumentos {
+                    self.expresion(arg);
+                }
+                let (_, campos, _) = &self.variantes[nombre];
+                if argumentos.len() != *campos {
+                    let error = Diagnostico::new(
+                        Codigo::CamposIncorrectos,
+                        format!(
+                            "La variante '{}' lleva {} valores, se dieron {}",
+                            nombre,
+                            campos,
+                            argumentos.len()
+                        ),
+                        pos,
+                    );
+                    self.diagnosticos.push(error);
+                }
+                Clase::Otra
+            }
+            Expresion::Llamada(nombre, argumentos, pos) => {
+                let clases: Vec<Clase> = argumentos.iter().map(|arg| self.expresion(arg)).collect();
+                // Una función o variable del programa tapa a la nativa
+                if self.es_visible(nombre) {
+                    return Clase::Desconocida;
+                }
+                match self.funciones.get(nombre).cloned() {
+                    Some((parametros, _)) => {
+                        for (parametro, arg) in parametros.iter().zip(argumentos) {
+                            let destino = format!("El parámetro '{}' de '{}'", parametro.nombre, nombre);
+                            self.funcion_declarada(&parametro.tipo_dato, arg, &destino);
+                        }
+                    }
+                    None => {
+                        if let Some(firma) = colecciones::firma(nombre) {
+                            self.coleccion(firma, argumentos, &clases, pos);
+                        }
+                    }
+                }
+                Clase::Desconocida
+            }
+            Expresion::LlamadaValor(funcion, argumentos, _) => {
+                self.expresion(funcion);
+                for arg in argumentos {
+                    self.expresion(arg);
+                }
+                Clase::Desconocida
+            }
+            Expresion::FuncionAnonima(parametros, retorno, cuerpo, pos) => {
+                self.funcion(parametros, retorno.as_deref(), cuerpo, pos);
+                Clase::Otra
+            }
+            Expresion::Segun(valor, casos, pos) => {
+                self.expresion(valor);
+                self.casos(casos, valor, pos);
+                for caso in casos {
+                    self.ambitos.push(ligaduras(&caso.patron));
+                    self.expresion(&caso.cuerpo);
+                    self.ambitos.pop();
+                }
+                Clase::Desconocida
+            }
+            Expresion::Temporizador(programacion, cuerpo, _) => {
+                match programacion {
+                    Programacion::Despues(expr) => self.intervalo(expr, "despues"),
+                    Programacion::Cada(expr) => self.intervalo(expr, "cada"),
+                    Programacion::CadaFrame => {}
+                }
+                self.bloque(cuerpo);
+                Clase::Otra
+            }
+        }
+    }
+    
+    /// Clase del resultado de una operación binaria
+    fn binaria(
+        &mut self,
+        izq: &Expresion,
+        op: &OperadorBinario,
+        der: &Expresion,
+        pos: &Posicion,
+        clase_izq: Clase,
+        clase_der: Clase,
+    ) -> Clase {
+        if matches!(op, OperadorBinario::Y | OperadorBinario::O) {
+            return Clase::Otra;
+        }
+        if *op == OperadorBinario::Suma && (clase_izq == Clase::Texto || clase_der == Clase::Texto) {
+            return Clase::Texto;
+        }
+        let (Some(dim_izq), Some(dim_der)) = (clase_izq.dimension(), clase_der.dimension()) else {
+            return Clase::Desconocida;
+        };
+        
+        match op {
+            OperadorBinario::Multiplicacion => return Clase::de_dimension(dim_izq.por(dim_der)),
+            OperadorBinario::Division => return Clase::de_dimension(dim_izq.entre(dim_der)),
+            _ => {}
+        }
+        
+        if dim_izq != dim_der {
+            let mut error = Diagnostico::new(
+                Codigo::UnidadesIncompatibles,
+                format!(
+                    "Unidades incompatibles en '{}': {} y {}",
+                    op,
+                    dim_izq.describir(),
+                    dim_der.describir()
+                ),
+                pos,
+            )
+            .con_etiqueta(izq.posicion(), dim_izq.describir())
+            .con_etiqueta(der.posicion(), dim_der.describir());
+            if dim_izq.es_adimensional() || dim_der.es_adimensional() {
+                error = error.con_sugerencia("escribe la unidad del número, como en 0m o 1s");
+            }
+            self.diagnosticos.push(error);
+            return Clase::Desconocida;
+        }
+        
+        match op {
+            OperadorBinario::Suma | OperadorBinario::Resta | OperadorBinario::Modulo => clase_izq,
+            _ => Clase::Otra,
+        }
+    }
+    
+    /// Clase de la variable de un bucle `para`; los límites y el paso de un
+    /// rango deben tener la misma unidad
+    fn iteracion(&mut self, iteracion: &Iteracion) -> Clase {
+        let (inicio, fin, paso) = match iteracion {
+            Iteracion::Coleccion(expr) => {
+                return match self.expresion(expr) {
+                    Clase::Texto => Clase::Texto,
+                    _ => Clase::Desconocida,
+                };
+            }
+            Iteracion::Rango { inicio, fin, paso, .. } => (inicio, fin, paso),
+        };
+        
+        let clase = self.expresion(inicio);
+        let mut otros = vec![fin];
+        otros.extend(paso);
+        for expr in otros {
+            let clase_otra = self.expresion(expr);
+            let (Some(dimension), Some(dimension_otra)) = (clase.dimension(), clase_otra.dimension()) else {
+                continue;
+            };
+            if dimension != dimension_otra {
+                self.diagnosticos.push(
+                    Diagnostico::new(
+                        Codigo::UnidadesIncompatibles,
+                        format!(
+                            "Unidades incompatibles en 'para': empieza en {} y recibe {}",
+                            dimension.describir(),
+                            dimension_otra.describir()
+                        ),
+                        expr.posicion(),
+                    )
+                    .con_etiqueta(inicio.posicion(), dimension.describir()),
+                );
+            }
+        }
+        clase
+    }
+    
+    /// Comprueba los patrones de un `segun` y que cubra todos los valores:
+    /// todas las variantes de su enumeración, `verdadero` y `falso`, o los
+    /// demás con `otro`
+    fn casos<T>(&mut self, casos: &[Caso<T>], valor: &Expresion, pos: &Posicion) {
+        let mut enumeracion: Option<String> = None;
+        let mut hay_literales = false;
+        let mut vistos: Vec<String> = Vec::new(); // Variantes y literales cubiertos
+        let mut otro: Option<&Posicion> = None;
+        let mut errores = Vec::new();
+        
+        for caso in casos {
+            if let Some(pos_otro) = otro {
+                errores.push(
+                    Diagnostico::new(Codigo::CasosIncorrectos, "Caso inalcanzable: va después de 'otro'", &caso.pos)
+                        .con_etiqueta(pos_otro, "'otro' acepta cualquier valor"),
+                );
+                continue;
+            }
+            
+            let (cubierto, pos_patron) = match &caso.patron {
+                Patron::Otro(pos) => {
+                    otro = Some(pos);
+                    continue;
+                }
+                Patron::Literal(literal) => {
+                    hay_literales = true;
+                    (describir_literal(literal), literal.posicion())
+                }
+                Patron::Variante(nombre, nombres, pos) => {
+                    let Some((de, campos, _)) = self.variantes.get(nombre) else {
+                        errores.push(Diagnostico::new(
+                            Codigo::CasosIncorrectos,
+                            format!("Variante desconocida: '{}'", nombre),
+                            pos,
+                        ));
+                        continue;
+                    };
+                    if let Some(nombres) = nombres.as_ref().filter(|nombres| nombres.len() != *campos) {
+                        errores.push(
+                            Diagnostico::new(
+                                Codigo::CamposIncorrectos,
+                                format!(
+                                    "La variante '{}' lleva {} valores, el caso nombra {}",
+                                    nombre,
+                                    campos,
+                                    nombres.len()
+                                ),
+                                pos,
+                            )
+                            .con_sugerencia("usa '_' para los valores que no necesites"),
+                        );
+                    }
+                    match &enumeracion {
+                        Some(anterior) if anterior != de => {
+                            errores.push(Diagnostico::new(
+                                Codigo::CasosIncorrectos,
+                                format!(
+                                    "El caso '{}' es de la enumeración '{}' y los anteriores de '{}'",
+                                    nombre, de, anterior
+                                ),
+                                pos,
+                            ));
+                            continue;
+                        }
+                        _ => enumeracion = Some(de.clone()),
+                    }
+                    (nombre.clone(), pos)
+                }
+            };
+            
+            if hay_literales && enumeracion.is_some() {
+                errores.push(
+                    Diagnostico::new(
+                        Codigo::CasosIncorrectos,
+                        "Un 'segun' no puede mezclar variantes y valores literales",
+                        pos_patron,
+                    ),
+                );
+            } else if vistos.contains(&cubierto) {
+                errores.push(Diagnostico::new(
+                    Codigo::CasosIncorrectos,
+                    format!("Caso repetido: {}", cubierto),
+                    pos_patron,
+                ));
+            } else {
+                vistos.push(cubierto);
+            }
+        }
+        
+        let cabecera = pos.hasta(valor.posicion());
+        if otro.is_none() {
+            match &enumeracion {
+                Some(nombre) => {
+                    let faltan: Vec<&str> = self.enumeraciones[nombre]
+                        .iter()
+                        .filter(|variante| !vistos.contains(variante))
+                        .map(String::as_str)
+                        .collect();
+                    if !faltan.is_empty() {
+                        errores.push(
+                            Diagnostico::new(
+                                Codigo::CasosIncorrectos,
+                                format!("'segun' no cubre las variantes de '{}': {}", nombre, faltan.join(", ")),
+                                &cabecera,
+                            )
+                            .con_sugerencia("añade un caso para cada una o un caso 'otro =>'"),
+                        );
+                    }
+                }
+                None if ["verdadero", "falso"].iter().all(|b| vistos.iter().any(|v| v == b)) => {}
+                None => errores.push(
+                    Diagnostico::new(
+                        Codigo::CasosIncorrectos,
+                        "'segun' no cubre todos los valores posibles",
+                        &cabecera,
+                    )
+                    .con_sugerencia("añade un caso 'otro =>' para los demás valores"),
+                ),
+            }
+        }
+        self.diagnosticos.extend(errores);
+    }
+    
+    /// Comprueba que una construcción nombre una estructura declarada y dé
+    /// valor a cada uno de sus campos una sola vez
+    fn construccion(&mut self, nombre: &str, campos: &[(String, Expresion)], pos: &Posicion) {
+        let Some(declarados) = self.estructuras.get(nombre).cloned() else {
+            self.diagnosticos.push(Diagnostico::new(
+                Codigo::TipoDesconocido,
+                format!("Estructura desconocida: '{}'", nombre),
+                pos,
+            ));
+            return;
+        };
+        
+        let mut errores = Vec::new();
+        for (i, (campo, valor)) in campos.iter().enumerate() {
+            let Some((_, tipo)) = declarados.iter().find(|(declarado, _)| declarado == campo) else {
+                let nombres: Vec<&str> = declarados.iter().map(|(declarado, _)| declarado.as_str()).collect();
+                errores.push(
+                    Diagnostico::new(
+                        Codigo::CamposIncorrectos,
+                        format!("La estructura '{}' no tiene un campo '{}'", nombre, campo),
+                        valor.posicion(),
+                    )
+                    .con_nota(format!("sus campos son: {}", nombres.join(", "))),
+                );
+                continue;
+            };
+            if campos[..i].iter().any(|(otro, _)| otro == campo) {
+                errores.push(Diagnostico::new(
+                    Codigo::CamposIncorrectos,
+                    format!("Campo '{}' repetido al construir '{}'", campo, nombre),
+                    valor.posicion(),
+                ));
+            } else {
+                self.funcion_declarada(tipo, valor, &format!("El campo '{}' de '{}'", campo, nombre));
+            }
+        }
+        let faltan: Vec<&str> = declarados
+            .iter()
+            .map(|(campo, _)| campo.as_str())
+            .filter(|campo| !campos.iter().any(|(dado, _)| dado == campo))
+            .collect();
+        if !faltan.is_empty() {
+            errores.push(Diagnostico::new(
+                Codigo::CamposIncorrectos,
+                format!("Faltan campos al construir '{}': {}", nombre, faltan.join(", ")),
+                pos,
+            ));
+        }
+        self.diagnosticos.extend(errores);
+    }
+    
+    /// Comprueba una llamada a una función de colecciones: cuántos
+    /// argumentos lleva, que las listas no sean textos o números y que la
+    /// función reciba los parámetros con que se la va a llamar
+    fn coleccion(&mut self, firma: &colecciones::Firma, argumentos: &[Expresion], clases: &[Clase], pos: &Posicion) {
+        if argumentos.len() < firma.minimo || argumentos.len() > firma.maximo {
+            self.diagnosticos.push(
+                Diagnostico::new(
+                    Codigo::LlamadaIncorrecta,
+                    format!("'{}' espera {}; se dieron {} argumentos", firma.nombre, firma.uso, argumentos.len()),
+                    pos,
+                ),
+            );
+            return;
+        }
+        
+        for (arg, clase) in argumentos.iter().zip(clases).take(firma.listas) {
+            if matches!(clase, Clase::Escalar | Clase::Medida(_) | Clase::Texto) {
+                let que = if *clase == Clase::Texto { "un texto" } else { "un número" };
+                self.diagnosticos.push(
+                    Diagnostico::new(
+                        Codigo::LlamadaIncorrecta,
+                        format!("'{}' espera {}", firma.nombre, firma.uso),
+                        pos,
+                    )
+                    .con_etiqueta(arg.posicion(), format!("esto es {}, no una lista", que)),
+                );
+            }
+        }
+        
+        let Some((i, esperados)) = firma.funcion else { return };
+        let Some(funcion) = argumentos.get(i) else { return };
+        let recibidos = match self.tipo_funcion(funcion) {
+            Some(Tipo::Funcion(parametros, _)) => parametros.len(),
+            _ if matches!(clases[i], Clase::Escalar | Clase::Medida(_) | Clase::Texto) => {
+                self.diagnosticos.push(
+                    Diagnostico::new(
+                        Codigo::LlamadaIncorrecta,
+                        format!("'{}' espera {}", firma.nombre, firma.uso),
+                        pos,
+                    )
+                    .con_etiqueta(funcion.posicion(), "esto no es una función"),
+                );
+                return;
+            }
+            _ => return,
+        };
+        if recibidos != esperados {
+            self.diagnosticos.push(
+                Diagnostico::new(
+                    Codigo::LlamadaIncorrecta,
+                    format!(
+                        "'{}' llama a su función con {} argumentos, pero recibe {}",
+                        firma.nombre, esperados, recibidos
+                    ),
+                    funcion.posicion(),
+                )
+                .con_nota(format!("'{}' espera {}", firma.nombre, firma.uso)),
+            );
+        }
+    }
+    
+    /// Tipo de la función que da `expr`, si se sabe cuál es: una anónima o
+    /// una declarada que ninguna variable tapa
+    fn tipo_funcion(&self, expr: &Expresion) -> Option<Tipo> {
+        let (parametros, retorno) = match expr {
+            Expresion::FuncionAnonima(parametros, retorno, ..) => (parametros, retorno.as_deref().cloned()),
+            Expresion::Variable(nombre, _) if !self.es_visible(nombre) => {
+                let (parametros, retorno) = self.funciones.get(nombre)?;
+                (parametros, retorno.clone())
+            }
+            _ => return None,
+        };
+        let parametros = parametros.iter().map(|p| p.tipo_dato.clone()).collect();
+        Some(Tipo::Funcion(parametros, retorno.map(Box::new)))
+    }
+    
+    /// Comprueba que la función que da `valor` encaje con `esperado` si es
+    /// un tipo `funcion(P, ...): R`: los mismos parámetros y, si las dos lo
+    /// declaran, el mismo retorno. `destino` dice a qué se le da.
+    fn funcion_declarada(&mut self, esperado: &Tipo, valor: &Expresion, destino: &str) {
+        let Tipo::Funcion(parametros, retorno) = esperado else { return };
+        let Some(dada) = self.tipo_funcion(valor) else { return };
+        let Tipo::Funcion(recibe, devuelve) = &dada else { return };
+        let mismo_retorno = match (retorno, devuelve) {
+            (Some(retorno), Some(devuelve)) => retorno == devuelve,
+            _ => true,
+        };
+        if recibe != parametros || !mismo_retorno {
+            self.diagnosticos.push(Diagnostico::new(
+                Codigo::FuncionIncompatible,
+                format!("{} es {}, pero se le da {}", destino, esperado, dada),
+                valor.posicion(),
+            ));
+        }
+    }
+    
+    /// Comprueba que las claves de los `mapa<K, V>` de un tipo sean textos,
+    /// números o booleanos, y que los tipos con nombre estén declarados
+    fn tipo(&mut self, tipo: &Tipo, pos: &Posicion) {
+        match tipo {
+            Tipo::Nombrado(nombre)
+                if !self.estructuras.contains_key(nombre) && !self.enumeraciones.contains_key(nombre) =>
+            {
+                self.diagnosticos.push(
+                    Diagnostico::new(Codigo::TipoDesconocido, format!("Tipo desconocido: '{}'", nombre), pos)
+                        .con_sugerencia(format!("declara '{}' con 'estructura' o 'enumeracion'", nombre)),
+                );
+            }
+            Tipo::Lista(elemento) => self.tipo(elemento, pos),
+            Tipo::Funcion(parametros, retorno) => {
+                for parametro in parametros.iter().chain(retorno.as_deref()) {
+                    self.tipo(parametro, pos);
+                }
+            }
+            Tipo::Mapa(clave, valor) => {
+                if !matches!(**clave, Tipo::Texto | Tipo::Numero | Tipo::Booleano) {
+                    self.diagnosticos.push(
+                        Diagnostico::new(
+                            Codigo::ClaveInvalida,
+                            format!("Tipo de clave de mapa inválido: {}", clave),
+                            pos,
+                        )
+                        .con_nota("las claves son textos, números enteros o booleanos"),
+                    );
+                }
+                self.tipo(clave, pos);
+                self.tipo(valor, pos);
+            }
+            _ => {}
+        }
+    }
+    
+    /// Analiza la clave de una entrada de un literal de mapa; informa de
+    /// las que seguro no pueden ser clave
+    fn clave(&mut self, clave: &Expresion) {
+        let clase = self.expresion(clave);
+        let descripcion = match (clave, clase) {
+            (_, Clase::Medida(dimension)) => dimension.describir(),
+            (Expresion::Decimal(..), _) => "un decimal".to_string(),
+            (Expresion::Lista(..), _) => "una lista".to_string(),
+            (Expresion::Mapa(..), _) => "un mapa".to_string(),
+            _ => return,
+        };
+        self.diagnosticos.push(
+            Diagnostico::new(
+                Codigo::ClaveInvalida,
+                format!("Clave de mapa inválida: {} no puede ser clave", descripcion),
+                clave.posicion(),
+            )
+            .con_nota("las claves son textos, números enteros o booleanos"),
+        );
+    }
+    
+    /// Comprueba que el intervalo de un temporizador o de `esperar`, si
+    /// lleva unidad, sea un tiempo
+    fn intervalo(&mut self, expr: &Expresion, contexto: &str) {
+        if let Clase::Medida(dimension) = self.expresion(expr) {
+            if dimension != Dimension::TIEMPO {
+                self.diagnosticos.push(
+                    Diagnostico::new(
+                        Codigo::UnidadesIncompatibles,
+                        format!(
+                            "Intervalo de '{}' debe ser un tiempo, no {}",
+                            contexto,
+                            dimension.describir()
+                        ),
+                        expr.posicion(),
+                    )
+                    .con_sugerencia("escribe el intervalo en s, ms, min o h"),
+                );
+            }
+        }
+    }
+    
+    fn declarar(&mut self, nombre: &str, clase: Clase) {
+        if let Some(ambito) = self.ambitos.last_mut() {
+            ambito.insert(nombre.to_string(), clase);
+        }
+    }
+    
+    /// Si hay una variable `nombre` declarada en un ámbito visible
+    fn es_visible(&self, nombre: &str) -> bool {
+        self.ambitos.iter().any(|ambito| ambito.contains_key(nombre))
+    }
+    
+    /// Clase de una variable visible; las demás pueden venir de quien llama
+    fn buscar(&self, nombre: &str) -> Clase {
+        self.ambitos
+            .iter()
+            .rev()
+            .find_map(|ambito| ambito.get(nombre).copied())
+            .unwrap_or(Clase::Desconocida)
+    }
+}
+
+/// Variables que declara un patrón en el cuerpo de su caso; `_` no declara
+fn ligaduras(patron: &Patron) -> HashMap<String, Clase> {
+    match patron {
+        Patron::Variante(_, Some(nombres), _) => nombres
+            .iter()
+            .filter(|nombre| *nombre != "_")
+            .map(|nombre| (nombre.clone(), Clase::Desconocida))
+            .collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// Un literal de un patrón como se escribe, para detectar casos repetidos
+fn describir_literal(literal: &Expresion) -> String {
+    match literal {
+        Expresion::Numero(n, _) => n.to_string(),
+        Expresion::Decimal(d, _) => format!("{:?}", d),
+        Expresion::Medida(valor, dimension, _) => format!("{}{}", valor, dimension),
+        Expresion::Texto(texto, _) => format!("\"{}\"", texto),
+        Expresion::Booleano(b, _) => if *b { "verdadero" } else { "falso" }.to_string(),
+        Expresion::Unaria(_, literal, _) => format!("-{}", describir_literal(literal)),
+        _ => String::new(),
+    }
+}
@@ -0,0 +1,232 @@
+// traductor.rs - Traducción de archivos entre conjuntos de palabras reservadas
+// Reescribe solo las palabras reservadas y contextuales: nombres, textos,
+// comentarios y espacios se copian tal cual del original.
+
+use crate::diagnostico::{Codigo, Diagnostico};
+use crate::idiomas::{es_palabra_contextual, Idioma};
+use crate::lexer::{Lexer, ParteDeTexto, TipoToken, Token};
+use crate::parser::Parser;
+
+/// Traduce `codigo`, escrito en `origen` salvo que su pragma diga otro
+/// idioma, a las palabras reservadas de `destino`. El resultado declara
+/// su idioma con un pragma cuando no es el español.
+pub fn traducir(codigo: &str, origen: Idioma, destino: Idioma) -> Result<String, Vec<Diagnostico>> {
+    let mut lexer = Lexer::new(codigo);
+    lexer.fijar_idioma(origen);
+    let tokens = aplanar(lexer.tokenizar()?);
+    
+    let mut salida = String::new();
+    if destino != Idioma::Espanol {
+        salida.push_str(&format!("@language {}\n", destino.codigo()));
+    }
+    
+    // El pragma original se quita junto con su salto de línea
+    let mut copiado = 0;
+    if let Some(pragma) = lexer.pragma() {
+        salida.push_str(&codigo[..pragma.byte as usize]);
+        copiado = pragma.byte_fin as usize;
+        if codigo[copiado..].starts_with('\n') {
+            copiado += 1;
+        }
+    }
+    
+    let mut errores = Vec::new();
+    for (i, token) in tokens.iter().enumerate() {
+        match traducir_token(&tokens, i, destino) {
+            Ok(Some(traduccion)) => {
+                salida.push_str(&codigo[copiado..token.pos.byte as usize]);
+                salida.push_str(traduccion);
+                copiado = token.pos.byte_fin as usize;
+            }
+            Ok(None) => {}
+            Err(error) => errores.push(error),
+        }
+    }
+    salida.push_str(&codigo[copiado..]);
+    
+    if errores.is_empty() {
+        Ok(salida)
+    } else {
+        Err(errores)
+    }
+}
+
+/// Sustituye cada texto con interpolación por los tokens de sus
+/// expresiones, para traducir también las palabras reservadas entre llaves
+fn aplanar(tokens: Vec<Token>) -> Vec<Token> {
+    let mut planos = Vec::new();
+    for token in tokens {
+        match token.tipo {
+            TipoToken::TextoInterpolado(partes) => {
+                for parte in partes {
+                    if let ParteDeTexto::Codigo(codigo) = parte {
+                        planos.extend(codigo.into_iter().filter(|t| t.tipo != TipoToken::Eof));
+                    }
+                }
+            }
+            _ => planos.push(token),
+        }
+    }
+    planos
+}
+
+/// Grafía en `destino` del token `i`, o `None` si se copia tal cual
+fn traducir_token(tokens: &[Token], i: usize, destino: Idioma) -> Result<Option<&'static str>, Diagnostico> {
+    let token = &tokens[i];
+    let anterior = i.checked_sub(1).map(|i| &tokens[i]);
+    let siguiente = tokens.get(i + 1).map(|t| &t.tipo);
+    
+    let es_nombre = match &token.tipo {
+        TipoToken::Identificador(nombre) => {
+            // Salvo las que el parser también acepta como nombre
+            let reservada = destino.palabra_reservada(nombre);
+            if reservada.is_some_and(|tipo| !Parser::es_contextual(&tipo)) {
+                return Err(Diagnostico::new(
+                    Codigo::NombreReservado,
+                    format!("'{}' es una palabra reservada en el idioma '{}'", nombre, destino.codigo()),
+                    &token.pos,
+                )
+                .con_sugerencia("renombra este nombre antes de traducir"));
+            }
+            return Ok(if es_posicion_contextual(nombre, tokens, i) {
+                destino.contextual(nombre)
+            } else {
+                None
+            });
+        }
+        // Las palabras reservadas que también sirven de nombre solo se
+        // traducen donde el parser las lee como palabra reservada
+        TipoToken::Frame => !matches!(anterior.map(|t| &t.tipo), Some(TipoToken::Cada)),
+        TipoToken::Y | TipoToken::O => match anterior {
+            Some(anterior) => {
+                !termina_operando(&anterior.tipo)
+                    || anterior.pos.linea_fin < token.pos.linea
+                    || siguiente == Some(&TipoToken::Asignacion)
+            }
+            None => true,
+        },
+        TipoToken::TipoNumero
+        | TipoToken::TipoDecimal
+        | TipoToken::TipoTexto
+        | TipoToken::TipoBooleano
+        | TipoToken::TipoVector3
+        | TipoToken::TipoPose
+        | TipoToken::TipoMano
+        | TipoToken::TipoControlador
+        | TipoToken::TipoLista
+        | TipoToken::TipoMapa => !es_posicion_de_tipo(tokens, i),
+        _ => false,
+    };
+    
+    Ok(if es_nombre { None } else { destino.escribir(&token.tipo) })
+}
+
+/// Si el token `i` está donde el parser espera un tipo: tras ':' o dentro
+/// de `lista<...>`, `mapa<..., ...>` o `funcion(...)`
+fn es_posicion_de_tipo(tokens: &[Token], i: usize) -> bool {
+    let es_generico = |j: usize| {
+        matches!(tokens[j].tipo, TipoToken::TipoLista | TipoToken::TipoMapa) && es_posicion_de_tipo(tokens, j)
+    };
+    let es_tipo_funcion = |j: usize| tokens[j].tipo == TipoToken::Funcion && es_posicion_de_tipo(tokens, j);
+    match i.checked_sub(1).map(|i| &tokens[i].tipo) {
+        Some(TipoToken::DosPuntos) => true,
+        Some(TipoToken::Menor) => i >= 2 && es_generico(i - 2),
+        Some(TipoToken::ParentesisAbre) => i >= 2 && es_tipo_funcion(i - 2),
+        Some(TipoToken::Coma) => {
+            // El valor de un mapa o un parámetro de un tipo de función: se
+            // busca el '<' o '(' sin cerrar de su tipo
+            let mut profundidad = 0;
+            for j in (0..i - 1).rev() {
+                match tokens[j].tipo {
+                    TipoToken::Mayor | TipoToken::ParentesisCierra => profundidad += 1,
+                    TipoToken::Menor | TipoToken::ParentesisAbre if profundidad > 0 => profundidad -= 1,
+                    TipoToken::Menor => return j >= 1 && tokens[j - 1].tipo == TipoToken::TipoMapa && es_posicion_de_tipo(tokens, j - 1),
+                    TipoToken::ParentesisAbre => return j >= 1 && es_tipo_funcion(j - 1),
+                    TipoToken::Coma | TipoToken::DosPuntos | TipoToken::Funcion => {}
+                    ref tipo if Parser::es_contextual(tipo) => {}
+                    _ => return false,
+                }
+            }
+            false
+        }
+        _ => false,
+    }
+}
+
+/// Si un token de tipo `tipo` puede cerrar un operando, de modo que una
+/// `y`/`o` tras él es el operador
+fn termina_operando(tipo: &TipoToken) -> bool {
+    matches!(
+        tipo,
+        TipoToken::Identificador(_)
+            | TipoToken::Numero(_)
+            | TipoToken::Decimal(_)
+            | TipoToken::Medida(..)
+            | TipoToken::Texto(_)
+            | TipoToken::Verdadero
+            | TipoToken::Falso
+            | TipoToken::ParentesisCierra
+            | TipoToken::CorcheteCierra
+    )
+}
+
+/// Si el nombre `nombre` del token `i` está donde su palabra contextual
+/// tiene sentido: `@inicial`, `transicion a`, `estado Nombre {`,
+/// `para x en`, el `paso` tras el fin de un rango o el principio de un
+/// caso de `segun` (`caso Patron =>`, `otro =>`)
+fn es_posicion_contextual(nombre: &str, tokens: &[Token], i: usize) -> bool {
+    let anterior = |atras: usize| i.checked_sub(atras).map(|i| &tokens[i].tipo);
+    let siguiente = |adelante: usize| tokens.get(i + adelante).map(|t| &t.tipo);
+    if es_palabra_contextual(nombre, "otro") {
+        return siguiente(1) == Some(&TipoToken::Flecha);
+    }
+    if es_palabra_contextual(nombre, "caso") {
+        // Entre `caso` y '=>' solo va el patrón
+        let patron = tokens[i + 1..].iter().take_while(|t| {
+            matches!(
+                t.tipo,
+                TipoToken::Identificador(_)
+                    | TipoToken::Numero(_)
+                    | TipoToken::Decimal(_)
+                    | TipoToken::Medida(..)
+                    | TipoToken::Texto(_)
+                    | TipoToken::Verdadero
+                    | TipoToken::Falso
+                    | TipoToken::Resta
+                    | TipoToken::ParentesisAbre
+                    | TipoToken::ParentesisCierra
+                    | TipoToken::Coma
+            ) || Parser::es_contextual(&t.tipo)
+        });
+        let longitud = patron.count();
+        return longitud > 0 && siguiente(longitud + 1) == Some(&TipoToken::Flecha);
+    }
+    match anterior(1) {
+        Some(TipoToken::Arroba) | Some(TipoToken::Transicion) => return true,
+        Some(TipoToken::Identificador(_)) if es_palabra_contextual(nombre, "en") => {
+            return anterior(2) == Some(&TipoToken::Para);
+        }
+        Some(tipo) if es_palabra_contextual(nombre, "paso") && termina_operando(tipo) => {
+            // En `paso paso`, el segundo es el nombre que da el paso
+            if let TipoToken::Identificador(otro) = tipo {
+                if es_palabra_contextual(otro, "paso") && es_posicion_contextual(otro, tokens, i - 1) {
+                    return false;
+                }
+            }
+            // Dentro de la cabecera de un `para`, antes de abrir su cuerpo
+            let cabecera = tokens[..i]
+                .iter()
+                .rev()
+                .map(|t| &t.tipo)
+                .find(|tipo| matches!(tipo, TipoToken::Para | TipoToken::LlaveAbre | TipoToken::LlaveCierra));
+            return cabecera == Some(&TipoToken::Para);
+        }
+        _ => {}
+    }
+    matches!(
+        &tokens[i + 1..],
+        [nombre, llave, ..]
+            if matches!(nombre.tipo, TipoToken::Identificador(_))
+                && llave.tipo == TipoToken::LlaveAbre
+    )
+}
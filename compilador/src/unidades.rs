@@ -0,0 +1,119 @@
+// unidades.rs - Unidades de medida de V-Code
+// Un número puede llevar una unidad pegada (`1.5m`, `20ms`, `45grados`).
+// Las medidas se guardan en la unidad base de su dimensión (metros,
+// segundos, radianes), así que las unidades compatibles se convierten solas.
+
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Dimensión de una medida: el exponente de cada magnitud base. `m/s` es
+/// longitud 1 y tiempo -1; un número sin unidad tiene todos a cero.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Dimension {
+    pub longitud: i8,
+    pub tiempo: i8,
+    pub angulo: i8,
+}
+
+impl Dimension {
+    pub const LONGITUD: Dimension = Dimension { longitud: 1, tiempo: 0, angulo: 0 };
+    pub const TIEMPO: Dimension = Dimension { longitud: 0, tiempo: 1, angulo: 0 };
+    pub const ANGULO: Dimension = Dimension { longitud: 0, tiempo: 0, angulo: 1 };
+    
+    /// Dimensión del producto de dos medidas
+    pub fn por(self, otra: Dimension) -> Dimension {
+        Dimension {
+            longitud: self.longitud + otra.longitud,
+            tiempo: self.tiempo + otra.tiempo,
+            angulo: self.angulo + otra.angulo,
+        }
+    }
+    
+    /// Dimensión del cociente de dos medidas
+    pub fn entre(self, otra: Dimension) -> Dimension {
+        self.por(Dimension {
+            longitud: -otra.longitud,
+            tiempo: -otra.tiempo,
+            angulo: -otra.angulo,
+        })
+    }
+    
+    pub fn es_adimensional(&self) -> bool {
+        *self == Dimension::default()
+    }
+    
+    /// Descripción para los mensajes de error: `longitud (m)`, `m/s`
+    pub fn describir(&self) -> String {
+        match *self {
+            _ if self.es_adimensional() => "número sin unidad".to_string(),
+            Dimension::LONGITUD => "longitud (m)".to_string(),
+            Dimension::TIEMPO => "tiempo (s)".to_string(),
+            Dimension::ANGULO => "ángulo (rad)".to_string(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// Unidades base con su exponente, como `m/s^2`. Sin numerador queda
+/// `/s`, para mostrarse pegado al valor (`2/s`).
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bases = [("m", self.longitud), ("s", self.tiempo), ("rad", self.angulo)];
+        let escribir = |f: &mut fmt::Formatter, simbolo: &str, exponente: i8, primero: bool| {
+            if !primero {
+                write!(f, "·")?;
+            }
+            match exponente {
+                1 => write!(f, "{}", simbolo),
+                _ => write!(f, "{}^{}", simbolo, exponente),
+            }
+        };
+        
+        let mut primero = true;
+        for (simbolo, exponente) in bases.iter().filter(|(_, e)| *e > 0) {
+            escribir(f, simbolo, *exponente, primero)?;
+            primero = false;
+        }
+        
+        let mut primero = true;
+        for (simbolo, exponente) in bases.iter().filter(|(_, e)| *e < 0) {
+            if primero {
+                write!(f, "/")?;
+            }
+            escribir(f, simbolo, -exponente, primero)?;
+            primero = false;
+        }
+        Ok(())
+    }
+}
+
+/// Unidad que puede seguir a un número
+#[derive(Debug, PartialEq)]
+pub struct Unidad {
+    pub simbolo: &'static str,
+    pub dimension: Dimension,
+    pub factor: f64, // Valor de una unidad en la unidad base
+}
+
+const UNIDADES: &[Unidad] = &[
+    Unidad { simbolo: "m", dimension: Dimension::LONGITUD, factor: 1.0 },
+    Unidad { simbolo: "cm", dimension: Dimension::LONGITUD, factor: 0.01 },
+    Unidad { simbolo: "mm", dimension: Dimension::LONGITUD, factor: 0.001 },
+    Unidad { simbolo: "km", dimension: Dimension::LONGITUD, factor: 1000.0 },
+    Unidad { simbolo: "s", dimension: Dimension::TIEMPO, factor: 1.0 },
+    Unidad { simbolo: "ms", dimension: Dimension::TIEMPO, factor: 0.001 },
+    Unidad { simbolo: "min", dimension: Dimension::TIEMPO, factor: 60.0 },
+    Unidad { simbolo: "h", dimension: Dimension::TIEMPO, factor: 3600.0 },
+    Unidad { simbolo: "rad", dimension: Dimension::ANGULO, factor: 1.0 },
+    Unidad { simbolo: "grados", dimension: Dimension::ANGULO, factor: PI / 180.0 },
+];
+
+/// Busca una unidad por su símbolo
+pub fn buscar(simbolo: &str) -> Option<&'static Unidad> {
+    UNIDADES.iter().find(|unidad| unidad.simbolo == simbolo)
+}
+
+/// Símbolos de todas las unidades, para los mensajes de error
+pub fn simbolos() -> String {
+    UNIDADES.iter().map(|unidad| unidad.simbolo).collect::<Vec<_>>().join(", ")
+}